@@ -0,0 +1,63 @@
+//! Plain A* against a contraction hierarchy on grids of streets.
+//!
+//! `cargo bench --bench routing` runs 30 by 30 and 100 by 100 grids, pass other sides to
+//! try bigger networks, e.g. `cargo bench --bench routing -- 320` for about 100k nodes.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use cars_and_roads::routing::a_star;
+use cars_and_roads::{Bpr, ContractionHierarchy, NodeID, SimConfig};
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+use common::grid_graph;
+
+
+const QUERIES: usize = 200;
+
+
+fn per_query(total: Duration) -> String {
+    format!("{:>10.1} µs/query", total.as_secs_f64() * 1e6 / QUERIES as f64)
+}
+
+
+fn main() {
+    let mut sides: Vec<i32> = std::env::args().skip(1).filter_map(|arg| arg.parse().ok()).collect();
+    if sides.is_empty() {
+        sides = vec![30, 100];
+    }
+
+    let model = Bpr::default();
+
+    for side in sides {
+        let mut rng = SimConfig::new(side as u64).rng();
+        let graph = grid_graph(side, &mut rng);
+        let nodes = (side * side) as usize;
+        let queries: Vec<(NodeID, NodeID)> = (0..QUERIES)
+            .map(|_| (NodeID(rng.random_range(0..nodes as i32)), NodeID(rng.random_range(0..nodes as i32))))
+            .collect();
+
+        let started = Instant::now();
+        let hierarchy = ContractionHierarchy::build(&graph, &model);
+        let preprocessing = started.elapsed();
+
+        let started = Instant::now();
+        for &(start, goal) in &queries {
+            black_box(a_star(start, goal, &graph, &model).ok());
+        }
+        let plain = started.elapsed();
+
+        let started = Instant::now();
+        for &(start, goal) in &queries {
+            black_box(hierarchy.route(start, goal).ok());
+        }
+        let contracted = started.elapsed();
+
+        println!("{nodes} nodes, {} roads, {} shortcuts, built in {:.2?}", graph.get_roads().len(), hierarchy.shortcuts(), preprocessing);
+        println!("    a_star              {}", per_query(plain));
+        println!("    contraction         {}   ({:.1}x)", per_query(contracted), plain.as_secs_f64() / contracted.as_secs_f64());
+    }
+}
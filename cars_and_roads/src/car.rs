@@ -1,148 +1,123 @@
 use macroquad::math::Vec2;
-use rand::{random_range, rng, Rng};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 use crate::road::NodeID;
-use crate::{RoadID, RoadGraph};
-use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap};
+use crate::idm::IdmParams;
+use crate::intersection::{Aspect, NodeControl, STOPPED_SPEED};
+use crate::routing::{passable_cost, Bpr, CostModel, RouteError};
+use crate::{Connector, Road, RoadID, RoadGraph};
+use std::collections::HashMap;
+use std::sync::Arc;
 
 
+/// Sideways acceleration drivers will put up with in a bend, in m/s²
+const MAX_LATERAL_ACCELERATION: f32 = 2.0;
 
-#[derive(Copy, Clone, PartialEq)]
-/// State is used for pathfinding algorithms
-struct State {
-    node: NodeID,
-    cost: f32,         // g(n)
-    est_total: f32,    // f(n) = g(n) + h(n)
-}
-
-impl State {
-    fn new(node: NodeID, cost: f32, est_total: f32) -> Self {
-        State { node, cost, est_total }
-    }
-}
-
-impl Eq for State {}
-
-impl Ord for State {
-    fn cmp(&self, other: &Self) -> Ordering {
-        // Reverse for min-heap behavior
-        other.est_total.partial_cmp(&self.est_total).unwrap_or(Ordering::Equal)
-    }
-}
-
-impl PartialOrd for State {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
+/// Extra distance past their braking distance that drivers look ahead for bends
+const CURVE_LOOKAHEAD_MARGIN: f32 = 20.0;
 
+/// How much quicker a driver has to be able to accelerate in the next lane over before
+/// changing to it, in m/s²
+const LANE_CHANGE_THRESHOLD: f32 = 0.2;
 
+/// How much the cars behind count next to the driver's own gain when changing lanes
+const POLITENESS: f32 = 0.3;
 
-pub fn a_star(start_node: NodeID, goal_node: NodeID, road_graph: &RoadGraph, debug: bool) -> Vec<RoadID> {
-    
-    let mut open = BinaryHeap::new();
-    let mut came_from: HashMap<NodeID, NodeID> = HashMap::new();
-    let mut cost_so_far: HashMap<NodeID, f32> = HashMap::new();
+/// Hardest braking a lane change may force on the car it cuts in front of, in m/s²
+const MAX_SAFE_BRAKING: f32 = 4.0;
 
+/// Added to the incentive of a lane change towards a lane that leads to the next road
+const MANDATORY_LANE_BIAS: f32 = 1.0;
 
+/// Within this distance of the end of a road, drivers in a lane that leads where they are
+/// going stay in it
+const LANE_KEEP_DISTANCE: f32 = 100.0;
 
-    let start_pos = road_graph.get_nodes().get(&start_node).unwrap().position;
-    let goal_pos = road_graph.get_nodes().get(&goal_node).unwrap().position;
+/// Seconds a driver waits after changing lanes before changing again
+const LANE_CHANGE_COOLDOWN: f32 = 3.0;
 
-    open.push(State::new(
-        start_node,
-        0.0,
-        start_pos.distance(goal_pos),
-    ));
+/// Sideways speed while changing lanes, in m/s
+const LANE_CHANGE_SPEED: f32 = 2.0;
 
-    cost_so_far.insert(start_node, 0.0);
+/// Seconds a driver sits at the end of a lane that goes the wrong way, waiting for a gap to
+/// change lanes, before going wherever the lane goes instead
+const LANE_CHANGE_PATIENCE: f32 = 10.0;
 
-    while let Some(State { node: current, cost, .. }) = open.pop() {
-        if current == goal_node {
-            if debug {
-                println!("Reached goal node {:?}", current);
-            }
+/// Seconds a car can sit stopped before it counts as stuck rather than just waiting
+const STUCK_AFTER: f32 = 120.0;
 
-            // Reconstruct node path
-            let mut node_path = vec![current];
-            let mut curr = current;
-            while let Some(&prev) = came_from.get(&curr) {
-                node_path.push(prev);
-                curr = prev;
-            }
-            node_path.reverse();
-
-            // Convert to road path
-            let mut road_path = Vec::new();
-            for win in node_path.windows(2) {
-                let from = win[0];
-                let to = win[1];
-                if let Some(edges) = road_graph.adjacency.get(&from) {
-                    if let Some(&(_, road_id)) = edges.iter().find(|&&(n, _)| n == to) {
-                        road_path.push(road_id);
-                    } else if debug {
-                        println!("⚠ No road found from {:?} to {:?}", from, to);
-                    }
-                }
-            }
 
-            if debug {
-                println!("Final node path: {:?}", node_path);
-                println!("Final road path: {:?}", road_path);
-            }
 
-            return road_path;
-        }
+#[derive(Clone, Copy)]
+/// The car behind in a lane, as far as a lane change cares.
+struct Follower {
+    gap: f32,
+    speed: f32,
+    acceleration: f32,
+    idm: IdmParams,
+}
 
-        if let Some(neighbors) = road_graph.adjacency.get(&current) {
-            for &(neighbor, road_id) in neighbors {
-                let road = road_graph.get_roads().get(&road_id).unwrap().read().unwrap();
-                if road.one_way {
-                    let dir = (road.to.position - road.from.position).normalize();
-                    let travel = (road_graph.get_nodes().get(&neighbor).unwrap().position - road_graph.get_nodes().get(&current).unwrap().position).normalize();
-                    if dir.dot(travel) <= 0.0 {
-                        continue; // wrong direction
-                    }
-                }
+impl Follower {
+    /// Its acceleration with a car going `speed` directly in front, `gap` ahead.
+    fn acceleration_behind(&self, gap: f32, speed: f32) -> f32 {
+        self.idm.acceleration(self.speed, Some((gap, speed)))
+    }
+}
 
-                let base_cost = road.length.max(1.0);
-                let density_penalty = 1.0 + road.traffic_density * 3.0;
-                let weight = base_cost * density_penalty;
 
-                let new_cost = cost + weight;
 
-                if new_cost < *cost_so_far.get(&neighbor).unwrap_or(&f32::INFINITY) {
-                    cost_so_far.insert(neighbor, new_cost);
-                    came_from.insert(neighbor, current);
-                    let est = new_cost + road_graph.get_nodes().get(&neighbor).unwrap().position.distance(goal_pos);
-                    open.push(State::new(neighbor, cost, est));
-                }
-            }
-        }
-    }
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+/// When a driver looks for a better route on the way.
+///
+/// Every search starts from the node at the end of the car's current road, with the
+/// densities as they are at that moment.
+pub enum ReroutePolicy {
+    /// Sticks to the route planned at the start
+    #[default]
+    Never,
+    /// Searches again every time it reaches a node
+    EveryNode,
+    /// Searches again every so many seconds
+    Periodic(f32),
+    /// Searches again once the rest of the route costs this fraction more than it did when it
+    /// was planned, 0.2 is 20% worse
+    OnDegradation(f32),
+}
 
-    if debug {
-        println!("No path found from {:?} to {:?}", start_node, goal_node);
-    }
 
-    vec![] // no path
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+/// Where a car is in its trip.
+pub enum CarState {
+    /// In the graph but hasn't driven a tick yet
+    #[default]
+    Spawning,
+    /// Driving along its route
+    EnRoute,
+    /// Stopped in a queue, at a signal or for a gap
+    Waiting,
+    /// At its destination, the simulation takes it out of the graph at the end of the tick
+    Arrived,
+    /// No route to its destination from where it is, or stopped for longer than any queue
+    /// should take. Starts driving again as soon as it can.
+    Stuck,
 }
 
 
+/// How drivers price routes unless told otherwise: travel time, up to 4x as long on a full road.
+pub(crate) fn default_cost_model() -> Arc<dyn CostModel> {
+    Arc::new(Bpr::new(3.0, 1.0))
+}
 
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Serialize, Deserialize)]
+pub struct CarID (pub i32);
 
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
-pub struct CarID (pub i32);
+/// One journey from an origin to a destination.
+pub struct TripID (pub i32);
 
 
-impl CarID {
-    fn new_rand() -> Self{
-        CarID(rand::rng().random_range(1..1000))
-    } 
-}
-
 impl From<i32> for CarID {
     fn from(value: i32) -> Self {
         CarID(value)
@@ -154,26 +129,45 @@ pub struct Car {
     // Public
     pub position: Vec2,
     pub velocity: f32,
-    pub acceleration: Vec2,
-    pub segment_index: usize,
+    pub acceleration: f32, // along the road, set by the IDM every tick
+    pub along: f32, // meters along `current_road`, past its end while turning onto the next road
+    pub lane: usize,
     pub destination: NodeID,
+    pub idm: IdmParams,
+    pub compliance: f32, // how the driver treats speed limits, 1.0 drives right at them, 1.1 speeds by 10%
+    pub reroute: ReroutePolicy,
+    pub cost_model: Arc<dyn CostModel>, // how the driver prices routes
+    pub trip: TripID,
+    pub origin: NodeID,     // where the trip started, the start of the road the car spawned on
+    pub depart_time: f32,   // simulated seconds, when the trip started
 
     // Private
     car_id: CarID,
     pub current_road: RoadID,
     path: Vec<RoadID>,
+    since_lane_change: f32, // seconds
+    stuck_for: f32,         // seconds spent stopped in a lane that doesn't lead to the next road
+    planned_costs: HashMap<RoadID, f32>, // cost of each road on the path when it was planned
+    since_plan: f32,        // seconds
+    reached_node: bool,     // moved onto a new road this tick
+    reroutes: u32,
+    state: CarState,
+    route_error: Option<RouteError>, // why the last search found nothing to drive
+    stopped_for: f32,       // seconds
+    odometer: f32,          // meters driven since spawning
 
     // For Rendering
     width: f32,
     height: f32,
     center: Vec2,
     heading: f32,
+    lateral: f32, // sideways distance from the road's points, eases towards the lane's offset
     color: (u8, u8, u8, u8),
 }
 
 impl Car {
 
-    /// Early Function - Not used
+    // Early Function - Not used
     /* 
     pub fn new(position: Vec2, velocity: f32, heading: f32) -> Self {
             let width= 5.0;
@@ -198,37 +192,33 @@ impl Car {
     */
 
     /// Spawns a car on the specified road of a RoadGraph.
-    pub fn new_on_road(car_id: Option<CarID>, road: RoadID, road_graph: &mut RoadGraph, velocity: f32, destination: NodeID) -> Self {
+    ///
+    /// The car starts out at `velocity`, after that it drives to the road's speed limit.
+    /// With `car_id` as `None` the graph allocates a fresh ID. The car only shows up on
+    /// the road once it is handed to `RoadGraph::add_car`.
+    pub fn new_on_road(car_id: Option<CarID>, road: RoadID, road_graph: &mut RoadGraph, velocity: f32, destination: NodeID, rng: &mut impl Rng) -> Self {
 
         let road_arc = road_graph.get_roads().get(&road).unwrap();
         let real_road = road_arc.read().unwrap();
-        let points = &real_road.points;
-    
-        let (start, next) = (points[0], points[1]);
-        let dir = (next - start).normalize_or_zero();
 
-        drop(real_road); // because road_arc will later be written to, just a safety check
-    
-        // Offset spawn to be 0.0–10.0 units into the segment
-        let offset = random_range(0.0..10.0);
-        let position = start + dir * offset;
-        let heading = dir.to_angle();
+        let lane = if real_road.lanes > 1 { rng.random_range(0..real_road.lanes) } else { 0 };
+        let lateral = real_road.lane_offset(lane);
+
+        // Spawn 0.0–10.0 meters along the road
+        let along = rng.random_range(0.0..10.0_f32).min(real_road.length);
+        let position = real_road.position_at(along);
+        let heading = real_road.tangent_at(along).to_angle();
+        let origin = real_road.from.id;
+
+        drop(real_road); // release the borrow on road_graph so it can allocate an ID below
     
         let width = 5.0;
         let height = 15.0;
         let center = Vec2 { x: width / 2.0, y: height / 2.0 };
     
-        let car_id = car_id.unwrap_or(CarID::new_rand());
-
-
-        let mut dyn_road = road_arc.write().unwrap();
-        dyn_road.num_vehicles_on += 1;
-        dyn_road.vehicles_on.push(car_id);
+        let car_id = car_id.unwrap_or_else(|| road_graph.next_car_id());
 
 
-        
-        let mut rng = rng();
-
         let (r, g, b, a) = (
             rng.random_range(0.0..=255.0) as u8,
             rng.random_range(0.0..=255.0) as u8,
@@ -239,17 +229,36 @@ impl Car {
         Car {
             position,
             velocity,
-            acceleration: Vec2::ZERO,
+            acceleration: 0.0,
             car_id,
             current_road: road,
             width,
             height,
             center,
             heading,
-            segment_index: 0,
+            lateral,
+            along,
+            lane,
             path: Vec::new(),
+            since_lane_change: LANE_CHANGE_COOLDOWN,
+            stuck_for: 0.0,
+            planned_costs: HashMap::new(),
+            since_plan: 0.0,
+            reached_node: false,
+            reroutes: 0,
+            state: CarState::Spawning,
+            route_error: None,
+            stopped_for: 0.0,
+            odometer: 0.0,
             color: (r, g, b, a),
             destination,
+            idm: IdmParams::default(),
+            compliance: 1.0,
+            reroute: ReroutePolicy::Never,
+            cost_model: default_cost_model(),
+            trip: TripID(car_id.0), // every car makes the one trip
+            origin,
+            depart_time: 0.0,
         }
     }
     
@@ -275,6 +284,11 @@ impl Car {
         self.center
     }
 
+    /// Where the car actually is: `position` follows the road's points, this is out in its lane.
+    pub fn lane_position(&self) -> Vec2 {
+        self.position + Vec2::from_angle(self.heading).perp() * self.lateral
+    }
+
     pub fn get_id(&self) -> CarID {
         self.car_id
     }
@@ -283,129 +297,461 @@ impl Car {
         self.path.clone()
     }
 
-    pub fn rotate_car(&mut self, rotation: f32) {
-        
-        if self.heading == 360.0 {
-            self.heading = 0.0;
+    /// How many times the driver has switched to a different route on the way.
+    pub fn reroutes(&self) -> u32 {
+        self.reroutes
+    }
+
+    pub fn state(&self) -> CarState {
+        self.state
+    }
+
+    /// Why the last route search came back empty, if it did.
+    pub fn route_error(&self) -> Option<RouteError> {
+        self.route_error
+    }
+
+    /// Meters driven since the car spawned.
+    pub fn distance_travelled(&self) -> f32 {
+        self.odometer
+    }
+
+    /// Moves the car sideways into `lane` of `road`, its current road, straight away.
+    pub(crate) fn put_in_lane(&mut self, lane: usize, road: &Road) {
+        self.lane = lane.min(road.lanes.saturating_sub(1));
+        self.lateral = road.lane_offset(self.lane);
+    }
+
+    /// Forgets the planned path. A new one is searched for at the end of the current road.
+    pub fn clear_path(&mut self) {
+        self.path.clear();
+    }
+
+    /// Forgets the planned path, all but the next road if the car is already past the end of
+    /// `road`, its current road, and turning onto it. The rest is searched for again.
+    pub(crate) fn clear_path_past_turn(&mut self, road: &Road) {
+        let committed = self.along > road.length;
+        self.path.truncate(usize::from(committed));
+    }
+
+    /// True once the car sits at the end of `road` (its current road) and that is its destination.
+    pub fn has_arrived(&self, road: &Road) -> bool {
+        self.path.is_empty()
+            && self.along >= road.length
+            && road.to.id == self.destination
+    }
+
+    /// Bumper-to-bumper gap to, and speed of, whatever is directly in front in the car's lane.
+    ///
+    /// Cars that have arrived are parked at the node, out of everyone's way.
+    ///
+    /// That's the car ahead in `road.vehicles_on`, or if this car is first in its lane, the last
+    /// car in the lane it will turn into on the next road of its path.
+    fn leader(&self, road: &Road, road_graph: &RoadGraph) -> Option<(f32, f32)> {
+        let own = self.along;
+        let (ahead, _) = self.lane_neighbours(road, road_graph, self.lane, own);
+        if ahead.is_some() {
+            return ahead;
         }
 
-        self.heading += rotation
-        
+        let next_id = *self.path.first()?;
+        let entry_lane = road_graph.entry_lane(road.id, self.lane, next_id);
+        let next_road = road_graph.get_roads().get(&next_id)?.read().unwrap();
+        let leader = next_road.vehicles_on.iter().rev()
+            .filter(|id| **id != self.car_id)
+            .filter_map(|id| road_graph.get_cars().get(id))
+            .map(|leader| leader.read().unwrap())
+            .find(|leader| leader.lane == entry_lane && !leader.has_arrived(&next_road))?;
+
+        let turn = Connector::between(road, &next_road).map_or(0.0, |turn| turn.length);
+        let gap = road.length + turn - own + leader.along - leader.height;
+        Some((gap, leader.velocity))
     }
 
-    /// Moves Car to end of road it is currently on
-    /// 
-    /// Returns true when at end of road
-    // In Car impl:
-    pub fn move_car_on_road(&mut self, dt: f32, road_graph: &RoadGraph) -> bool {
+    /// The nearest car or obstruction ahead in `lane` of `road`, as (gap, speed), and the
+    /// nearest car behind. `own` is how far along the road this car is.
+    fn lane_neighbours(&self, road: &Road, road_graph: &RoadGraph, lane: usize, own: f32) -> (Option<(f32, f32)>, Option<Follower>) {
+        let Some(place) = road.vehicles_on.iter().position(|id| *id == self.car_id) else { return (None, None) };
+        let cars_in = |ids: &[CarID]| ids.iter()
+            .filter_map(|id| road_graph.get_cars().get(id))
+            .map(|car| car.read().unwrap())
+            .filter(|car| car.lane == lane && !car.has_arrived(road))
+            .collect::<Vec<_>>();
+
+        let car_ahead = cars_in(&road.vehicles_on[..place]).last()
+            .map(|leader| (leader.along - own - leader.height, leader.velocity));
+
+        // An obstruction in the lane is a car that isn't going anywhere
+        let blocked = road.obstruction_ahead(lane, own).map(|at| (at - own, 0.0));
+        let leader = match (car_ahead, blocked) {
+            (Some(car), Some(blocked)) => Some(if blocked.0 < car.0 { blocked } else { car }),
+            (car, blocked) => car.or(blocked),
+        };
+
+        let follower = cars_in(&road.vehicles_on[place + 1..]).first()
+            .map(|follower| Follower {
+                gap: own - follower.along - self.height,
+                speed: follower.velocity,
+                acceleration: follower.acceleration,
+                idm: IdmParams { desired_speed: (road.max_speed() * follower.compliance).min(follower.idm.desired_speed), ..follower.idm },
+            });
+
+        (leader, follower)
+    }
 
-        let road = road_graph.get_roads().get(&self.current_road).unwrap().read().unwrap();
-        let points = &road.points;
+    /// The speed the driver is aiming for right now: the road's limit scaled by `compliance`,
+    /// lowered ahead of bends so the car can brake down to a comfortable cornering speed.
+    /// Bends in `turn`, the way through the junction at the end of the road, count too.
+    fn target_speed(&self, road: &Road, turn: Option<&Connector>) -> f32 {
+        let limit = (road.max_speed() * self.compliance).min(self.idm.desired_speed);
+        let lookahead = limit * limit / (2.0 * self.idm.comfortable_braking) + CURVE_LOOKAHEAD_MARGIN;
+
+        // Every bend between the points, measured from the start of the road
+        let road_bends = (1..road.points.len().saturating_sub(1))
+            .map(|index| (road.arc_lengths()[index], road.curvature_at_index(index)));
+        let turn_bends = turn.into_iter().flat_map(|turn| {
+            (1..turn.points.len().saturating_sub(1)).map(move |index| (road.length + turn.arc_lengths()[index], turn.curvature_at_index(index)))
+        });
+        let bends: Vec<(f32, f32)> = road_bends.chain(turn_bends).collect();
+
+        // The bend the car is in right now, then every one ahead within braking range
+        let mut target = limit;
+        let first = bends.partition_point(|(s, _)| *s <= self.along).saturating_sub(1);
+
+        for &(s, curvature) in &bends[first..] {
+            let distance = (s - self.along).max(0.0);
+            if distance > lookahead {
+                break;
+            }
+
+            if curvature > 0.0 {
+                let corner_speed = (MAX_LATERAL_ACCELERATION / curvature).sqrt();
+                let brake_in_time = (corner_speed * corner_speed + 2.0 * self.idm.comfortable_braking * distance).sqrt();
+                target = target.min(brake_in_time);
+            }
+        }
+
+        target
+    }
 
-        const MIN_SEGMENT_LENGTH_FOR_HEADING_SQ: f32 = 0.01; 
+    /// Distance to the end of `road` if the car has to stop there, because the node's
+    /// control won't let it make its next movement yet.
+    ///
+    /// On amber the car only stops if it still comfortably can. At stop and yield signs it
+    /// waits until the control lets it go. A car in a lane that doesn't lead to its next road
+    /// waits at the end until it gets into one that does. Once past the end it is turning
+    /// through the junction and goes on regardless.
+    fn stop_line_gap(&self, road: &Road, road_graph: &RoadGraph) -> Option<f32> {
+        let next_road = *self.path.first()?;
+        let remaining = road.length - self.along;
+        if remaining < 0.0 {
+            return None;
+        }
 
-        // If segment_index is invalid or road has < 2 points for any meaningful segment.
-        if points.len() < 2 {
-            return true; // Cannot form a segment, consider it "done".
+        if !road_graph.lane_connections(road.id, next_road).iter().any(|&(lane, _)| lane == self.lane) {
+            return Some(remaining);
         }
 
-        if self.segment_index >= points.len() {
-            panic!("🚨 Invalid segment_index {} for road with {} points!", self.segment_index, points.len()); // bail or clamp
+        let control = road_graph.get_control(road.to.id)?;
+
+        let must_stop = match control {
+            NodeControl::Signal(signal) => match signal.aspect(road.id, next_road) {
+                Aspect::Green => false,
+                Aspect::Amber => remaining >= self.velocity * self.velocity / (2.0 * self.idm.comfortable_braking),
+                Aspect::Red => true,
+            },
+            NodeControl::Priority(priority) => !priority.is_granted(self.car_id),
+        };
+
+        must_stop.then_some(remaining)
+    }
+
+    /// Runs the IDM against the car in front and updates `acceleration` and `velocity`.
+    fn update_speed(&mut self, road_graph: &RoadGraph, dt: f32) {
+        let road = road_graph.get_roads().get(&self.current_road).unwrap().read().unwrap();
+        let leader = self.leader(&road, road_graph);
+        let turn = self.turn(&road, road_graph);
+        let idm = IdmParams { desired_speed: self.target_speed(&road, turn.as_ref()), ..self.idm };
+        let stop_line = self.stop_line_gap(&road, road_graph);
+        drop(road);
+
+        self.acceleration = idm.acceleration(self.velocity, leader);
+
+        // Over the target speed the IDM only eases off. Coming up to a bend or a lower limit,
+        // brake down to it as hard as is comfortable, which is what `target_speed` allows for
+        if self.velocity > idm.desired_speed {
+            let to_target = ((idm.desired_speed - self.velocity) / dt).max(-self.idm.comfortable_braking);
+            self.acceleration = self.acceleration.min(to_target);
+        }
+
+        // A stop line is a leader that doesn't move, brake for whichever is more pressing
+        if let Some(gap) = stop_line {
+            self.acceleration = self.acceleration.min(idm.acceleration(self.velocity, Some((gap, 0.0))));
+        }
+
+        self.velocity = (self.velocity + self.acceleration * dt).max(0.0);
+    }
+
+    /// Moves the car sideways towards its lane, and picks a new lane when it is worth it.
+    ///
+    /// MOBIL style: the car changes when it can accelerate noticeably harder in the next lane
+    /// over, counting what the cars behind it win or lose at `POLITENESS`, and only if the car
+    /// it pulls in front of won't have to brake harder than `MAX_SAFE_BRAKING`. Lanes closer to
+    /// one that leads to the next road of the path get a bonus.
+    fn change_lanes(&mut self, road_graph: &RoadGraph, dt: f32) {
+        let road = road_graph.get_roads().get(&self.current_road).unwrap().read().unwrap();
+
+        let step = LANE_CHANGE_SPEED * dt;
+        self.lateral += (road.lane_offset(self.lane) - self.lateral).clamp(-step, step);
+
+        // Past the end of the road the car is already committed to its turn
+        self.since_lane_change += dt;
+        if road.lanes < 2 || self.since_lane_change < LANE_CHANGE_COOLDOWN || self.along > road.length {
+            return;
         }
 
-        if self.segment_index >= points.len() - 1 {
-            // Already at the end of the last segment.
-            if !points.is_empty() {
-                self.position = points[points.len() - 1]; // Ensure snapped to the very end.
+        let own = self.along;
+        let remaining = road.length - own;
+        let turn = self.turn(&road, road_graph);
+        let idm = IdmParams { desired_speed: self.target_speed(&road, turn.as_ref()), ..self.idm };
+
+        let usable: Vec<usize> = self.path.first()
+            .map(|next| road_graph.lane_connections(road.id, *next).into_iter().map(|(lane, _)| lane).collect())
+            .unwrap_or_default();
+        let lanes_off = |lane: usize| usable.iter().map(|usable| usable.abs_diff(lane)).min().unwrap_or(0);
+
+        let (leader, follower) = self.lane_neighbours(&road, road_graph, self.lane, own);
+        let current = idm.acceleration(self.velocity, leader);
+
+        // The car behind gets to close up on whoever is in front of this one
+        let left_behind = follower.map_or(0.0, |follower| {
+            let closed_up = match leader {
+                Some((gap, speed)) => follower.acceleration_behind(follower.gap + self.height + gap, speed),
+                None => follower.idm.acceleration(follower.speed, None),
+            };
+            closed_up - follower.acceleration
+        });
+
+        let mut best: Option<(usize, f32)> = None;
+        let neighbours = [self.lane.checked_sub(1), Some(self.lane + 1).filter(|lane| *lane < road.lanes)];
+
+        for lane in neighbours.into_iter().flatten() {
+            if lanes_off(lane) > lanes_off(self.lane) && lanes_off(self.lane) == 0 && remaining < LANE_KEEP_DISTANCE {
+                continue;
+            }
+
+            let (new_leader, new_follower) = self.lane_neighbours(&road, road_graph, lane, own);
+
+            if new_leader.is_some_and(|(gap, _)| gap <= 0.0) {
+                continue;
+            }
+            let cut_in = new_follower.map(|follower| (follower.gap, follower.acceleration_behind(follower.gap, self.velocity)));
+            if cut_in.is_some_and(|(gap, braking)| gap <= 0.0 || braking < -MAX_SAFE_BRAKING) {
+                continue;
+            }
+
+            let mut incentive = idm.acceleration(self.velocity, new_leader) - current
+                + POLITENESS * (left_behind + new_follower.zip(cut_in).map_or(0.0, |(follower, (_, after))| after - follower.acceleration));
+
+            if lanes_off(lane) < lanes_off(self.lane) {
+                incentive += MANDATORY_LANE_BIAS;
+            }
+
+            if incentive > LANE_CHANGE_THRESHOLD && best.is_none_or(|(_, top)| incentive > top) {
+                best = Some((lane, incentive));
             }
-            return true; // Done with the road.
         }
 
-        // Target point of the current segment.
-        let segment_target_point = points[self.segment_index + 1];
-        let segment_start_point = points[self.segment_index]; // Current base of segment
-
-        // Ensure heading is correctly set for the current segment.
-        let current_segment_direction = (segment_target_point - segment_start_point).normalize_or_zero();
-        if current_segment_direction.length_squared() > MIN_SEGMENT_LENGTH_FOR_HEADING_SQ {
-            // Only update heading if the segment is not tiny and heading is not already aligned.
-            // This avoids issues if car was teleported or segment is very small.
-        } else if (segment_target_point - self.position).length_squared() < MIN_SEGMENT_LENGTH_FOR_HEADING_SQ {
-            // Segment is tiny, and we are already basically at its target point.
-            // Force advance to next segment.
-            self.position = segment_target_point;
-            self.segment_index += 1;
-
-            // Update heading
-            if self.segment_index < points.len() - 1 {
-                let next_segment_start = points[self.segment_index];
-                let next_segment_target = points[self.segment_index + 1];
-                let next_direction = (next_segment_target - next_segment_start).normalize_or_zero();
-                if next_direction.length_squared() > MIN_SEGMENT_LENGTH_FOR_HEADING_SQ {
-                    self.heading = next_direction.to_angle();
-                }
+        if let Some((lane, _)) = best {
+            self.lane = lane;
+            self.since_lane_change = 0.0;
+            self.stuck_for = 0.0;
+            return;
+        }
+
+        // Two cars side by side, each in the other's lane, would wait for each other forever
+        if lanes_off(self.lane) > 0 && self.velocity < 0.1 {
+            self.stuck_for += dt;
+        } else {
+            self.stuck_for = 0.0;
+        }
+        if self.stuck_for > LANE_CHANGE_PATIENCE {
+            self.stuck_for = 0.0;
+            self.follow_lane(&road, road_graph);
+        }
+    }
+
+    /// Searches for a route from the end of the current road, replacing the current path.
+    /// With `keep_next` the next road of the path stays and the search starts from its end.
+    fn plan_route(&mut self, keep_next: bool, road_graph: &RoadGraph) {
+        let roads = road_graph.get_roads();
+        let kept = self.path.first().copied().filter(|_| keep_next);
+        let start_node = match kept {
+            Some(next) => roads.get(&next).unwrap().read().unwrap().to.id,
+            None => roads.get(&self.current_road).unwrap().read().unwrap().to.id,
+        };
+
+        self.route_error = None;
+        let onward = match road_graph.route(start_node, self.destination, self.cost_model.as_ref()) {
+            Ok(route) => route.roads,
+            Err(RouteError::StartIsGoal(_)) => Vec::new(),
+            Err(error) => {
+                log::debug!("car {:?}: {}", self.car_id, error);
+                self.route_error = Some(error);
+                Vec::new()
             }
-            return self.segment_index >= points.len() - 1; // Check if done
+        };
+        self.path = kept.into_iter().chain(onward).collect();
+
+        log::debug!("car {:?}: routed from node {:?} to {:?}, path {:?}", self.car_id, start_node, self.destination, self.path);
+
+        if self.path.first() == Some(&self.current_road) {
+            self.path.remove(0);
         }
 
+        self.price_path(road_graph);
+    }
 
-        let vector_car_to_target = segment_target_point - self.position;
-        let distance_to_target = vector_car_to_target.length();
-        
-        let travel_this_frame = self.velocity * dt;
-
-        if travel_this_frame >= distance_to_target && distance_to_target > 0.001 { // This accounts for any wild floating point things
-            // Car reaches or passes the target point of the current segment
-            self.position = segment_target_point; // Snap to target
-            self.segment_index += 1;
-
-            // Check if done with the entire road
-            if self.segment_index >= points.len() - 1 {
-                return true; // Done with road and returns successful
-            } else {
-                // Not done with road, so prepare for the next segment
-                // Update heading for the new current segment
-                let new_segment_start = points[self.segment_index]; // This is segment_target_point
-                let new_segment_target = points[self.segment_index + 1];
-                let new_direction = (new_segment_target - new_segment_start).normalize_or_zero();
-                if new_direction.length_squared() > MIN_SEGMENT_LENGTH_FOR_HEADING_SQ {
-                    self.heading = new_direction.to_angle();
-                } else {
-                    // New segment is also tiny, keep old heading or let next iteration handle it.
-                }
-                return false; // Advanced to next segment, not done with road
+    /// Notes what each road of the path costs now, for `ReroutePolicy::OnDegradation` to
+    /// compare against, and starts the clock on the plan.
+    fn price_path(&mut self, road_graph: &RoadGraph) {
+        self.planned_costs = self.path.iter()
+            .filter_map(|id| road_graph.get_roads().get(id))
+            .map(|road| road.read().unwrap())
+            .map(|road| (road.id, passable_cost(self.cost_model.as_ref(), &road).unwrap_or(f32::INFINITY)))
+            .collect();
+        self.since_plan = 0.0;
+    }
+
+    /// Whether `reroute` calls for a new search this tick.
+    fn wants_reroute(&self, road_graph: &RoadGraph) -> bool {
+        match self.reroute {
+            ReroutePolicy::Never => false,
+            ReroutePolicy::EveryNode => self.reached_node,
+            ReroutePolicy::Periodic(period) => self.since_plan >= period,
+            ReroutePolicy::OnDegradation(fraction) => {
+                let (planned, now) = self.path.iter()
+                    .filter_map(|id| road_graph.get_roads().get(id))
+                    .map(|road| road.read().unwrap())
+                    .map(|road| {
+                        let now = passable_cost(self.cost_model.as_ref(), &road).unwrap_or(f32::INFINITY);
+                        (self.planned_costs.get(&road.id).copied().unwrap_or(now), now)
+                    })
+                    .fold((0.0, 0.0), |(planned, total), (then, now)| (planned + then, total + now));
+
+                planned > 0.0 && now > planned * (1.0 + fraction)
             }
-        } else if distance_to_target <= 0.001 { // Already at (or very close to) the target
-            self.position = segment_target_point; // Snap for precision
-            self.segment_index += 1;
-
-            if self.segment_index >= points.len() - 1 {
-                return true; // Done with road
-            } else {
-                let new_segment_start = points[self.segment_index];
-                let new_segment_target = points[self.segment_index + 1];
-                let new_direction = (new_segment_target - new_segment_start).normalize_or_zero();
-                if new_direction.length_squared() > MIN_SEGMENT_LENGTH_FOR_HEADING_SQ {
-                    self.heading = new_direction.to_angle();
-                }
-                return false;
+        }
+    }
+
+    /// Gives up on the planned turn and takes a road the current lane leads to instead,
+    /// planning the rest of the way from the end of it.
+    fn follow_lane(&mut self, road: &Road, road_graph: &RoadGraph) {
+        for exit in road_graph.lane_exits(road.id, self.lane) {
+            let exit_end = road_graph.get_roads().get(&exit).unwrap().read().unwrap().to.id;
+
+            let onward = match road_graph.route(exit_end, self.destination, self.cost_model.as_ref()) {
+                Ok(route) => route.roads,
+                Err(RouteError::StartIsGoal(_)) => Vec::new(),
+                Err(_) => continue,
+            };
+
+            self.path = std::iter::once(exit).chain(onward).collect();
+            return;
+        }
+    }
+
+    /// Puts the car at the very start of `road`, facing along it, with no planned path.
+    ///
+    /// Only moves the car itself, the caller keeps `Road::vehicles_on` in sync.
+    pub(crate) fn place_on_road(&mut self, road: &Road) {
+        self.current_road = road.id;
+        self.lane = self.lane.min(road.lanes.saturating_sub(1));
+        self.path.clear();
+        self.place_at_distance(road, 0.0);
+    }
+
+    /// Puts the car `distance` meters along `road`, its current road. Keeps the planned path.
+    ///
+    /// Used when the road changes shape under the car.
+    pub(crate) fn place_at_distance(&mut self, road: &Road, distance: f32) {
+        self.along = distance.clamp(0.0, road.length);
+        self.follow_road(road);
+    }
+
+    /// Puts the car `along` meters into `road`, its current road, on its way along `path`.
+    /// Past the end of the road is that far into the turn onto the first road of `path`.
+    ///
+    /// Picks a saved car back up where it was, see `Level::from_data`.
+    pub(crate) fn resume(&mut self, road: &Road, along: f32, path: Vec<RoadID>, road_graph: &RoadGraph) {
+        self.path = path;
+        self.price_path(road_graph);
+
+        let turn = self.turn(road, road_graph);
+        self.along = along.clamp(0.0, road.length + turn.as_ref().map_or(0.0, |turn| turn.length));
+
+        match turn {
+            Some(turn) if self.along > road.length => {
+                let into = self.along - road.length;
+                self.face(turn.position_at(into), turn.tangent_at(into));
             }
+            _ => self.follow_road(road),
+        }
+    }
+
+    /// Puts `position` and `heading` where `along` is on `road`.
+    fn follow_road(&mut self, road: &Road) {
+        self.face(road.position_at(self.along), road.tangent_at(self.along));
+    }
+
+    /// Puts the car at `position`, facing along `tangent`.
+    fn face(&mut self, position: Vec2, tangent: Vec2) {
+        self.position = position;
+
+        // A road with no length has no direction, keep facing the way the car came in
+        if tangent != Vec2::ZERO {
+            self.heading = tangent.to_angle();
+        }
+    }
+
+    /// The way through the junction at the end of `road`, its current road, onto the next
+    /// road of the path. `None` without a next road, or if the two meet right at the node.
+    fn turn(&self, road: &Road, road_graph: &RoadGraph) -> Option<Connector> {
+        let next_road = road_graph.get_roads().get(self.path.first()?)?.read().unwrap();
+        Connector::between(road, &next_road)
+    }
+
+    pub fn rotate_car(&mut self, rotation: f32) {
+        
+        if self.heading == 360.0 {
+            self.heading = 0.0;
         }
-        else {
-            // Car moves along the current segment but does not reach its end
-            // Move using the segment's fixed direction (derived from current self.heading,
-            // which should have been set when this segment was entered).
-
-            let direction = (segment_target_point - self.position).normalize_or_zero();
-            self.position += direction * travel_this_frame;
-            self.heading = direction.to_angle(); // Keep it fresh
-            
-            // self.heading = (segment_target_point - self.position).normalize_or_zero().to_angle();
-            // will actually cause oscillations! 
-            // It was my initial step and I spent a good amount of time debugging it. 
+
+        self.heading += rotation
+        
+    }
+
+    /// Moves the car along the road it is on at its current speed, then through the junction
+    /// at its end onto the next road of the path.
+    ///
+    /// Returns true once it is at the end of the road and that turn, ready for the next road.
+    pub fn move_car_on_road(&mut self, dt: f32, road_graph: &RoadGraph) -> bool {
+        let road = road_graph.get_roads().get(&self.current_road).unwrap().read().unwrap();
+        let turn = self.turn(&road, road_graph);
+        let end = road.length + turn.as_ref().map_or(0.0, |turn| turn.length);
+
+        // A car whose path changed under it mid-turn, leaving no turn ahead, waits at the road's end
+        self.along = (self.along + self.velocity * dt).min(end);
+
+        match turn {
+            Some(turn) if self.along > road.length => {
+                let into = self.along - road.length;
+                self.face(turn.position_at(into), turn.tangent_at(into));
+            }
+            _ => self.follow_road(&road),
         }
 
-        false // Not done with current segment of road
+        self.along >= end
     }
     
     
@@ -413,62 +759,74 @@ impl Car {
     /// Moves car from starting road to inputted destination
     /// 
     /// Uses the A* algorithm 
-    pub fn move_car_to_destination(&mut self, road_graph: &RoadGraph, dt: f32, debug: bool) {
+    pub fn move_car_to_destination(&mut self, road_graph: &RoadGraph, dt: f32) {
 
         let destination = self.destination;
 
+        // Speed first, so the distance covered this tick respects the car in front
+        self.update_speed(road_graph, dt);
+        self.change_lanes(road_graph, dt);
+
         // check if car done with its own road
-        let done = self.move_car_on_road(dt, &road_graph);
+        let before = self.along;
+        let done = self.move_car_on_road(dt, road_graph);
         let curr_road = road_graph.get_roads().get(&self.current_road).unwrap().read().unwrap();
-        if debug {
-            println!(
-            "[Step] ID: {:?} | Pos: {:.1},{:.1} | Seg: {} / {}",
+        self.odometer += (self.along - before).max(0.0);
+        log::trace!(
+            "car {:?}: at {:.1},{:.1}, {:.1} of {:.1} m along road {:?}",
             self.car_id,
             self.position.x,
             self.position.y,
-            self.segment_index,
-            curr_road.points.len() - 1
-            ); 
-        }
+            self.along,
+            curr_road.length,
+            self.current_road,
+        );
     
         // Car is at end of road, and destination.id matches current_road.end.id
 
-        if self.path.is_empty()
-            && self.segment_index >= curr_road.points.len() - 1
-            && curr_road.to.id == destination
-        {
-            if debug {
-                println!("✅ Fully arrived at destination {:?}", destination);
-            }
+        if self.has_arrived(&curr_road) {
+            log::debug!("car {:?}: arrived at node {:?}", self.car_id, destination);
+            self.velocity = 0.0;
+            self.acceleration = 0.0;
+            self.state = CarState::Arrived;
             return;
         }
     
-        // Runs A* again when road is finished.
-        // Can potentially be modified to provide on-the-fly rerouting, as in it will suggest another road before car finishes its own.
+        // Runs A* whenever the path runs out, and whenever the reroute policy asks for it.
+        // Planning ahead of the node lets the car see which signal movement it needs.
+        self.since_plan += dt;
         if self.path.is_empty() {
-            
-    
-            if self.segment_index < curr_road.points.len() - 1 {
-                // Still moving on current road, wait before routing
-                return;
-            }
-    
-            let start_node = curr_road.to.id;
-            self.path = a_star(start_node, destination, &road_graph, debug);
-    
-            if debug {
-                println!("📍 Rerouted from node {:?} to {:?}, path: {:?}", start_node, destination, self.path);
-            }
-    
-            if self.path.first() == Some(&self.current_road) {
-                self.path.remove(0);
+            self.plan_route(false, road_graph);
+        } else if self.wants_reroute(road_graph) {
+            let old_path = self.path.clone();
+
+            // Too close to the node to get into the lane for a different turn, stick with it
+            let remaining = curr_road.length - self.along;
+            self.plan_route(remaining < LANE_KEEP_DISTANCE, road_graph);
+
+            if self.path != old_path {
+                self.reroutes += 1;
             }
         }
+        self.reached_node = false;
         drop(curr_road);
 
+        if self.velocity < STOPPED_SPEED {
+            self.stopped_for += dt;
+        } else {
+            self.stopped_for = 0.0;
+        }
+        self.state = if self.route_error.is_some() || self.stopped_for > STUCK_AFTER {
+            CarState::Stuck
+        } else if self.stopped_for > 0.0 {
+            CarState::Waiting
+        } else {
+            CarState::EnRoute
+        };
+
         // Moves to next road in path if exists. This is the only part of any function that can move cars to different roads. 
-        if done {
-            if let Some(next_road) = self.path.first().copied() {
+        if done
+            && let Some(next_road) = self.path.first().copied() {
                 let mut curr_road = road_graph.get_roads().get(&self.current_road).unwrap().write().unwrap();
 
                 {self.path.remove(0);
@@ -478,9 +836,9 @@ impl Car {
 
                 drop(curr_road);
 
-
-
+                self.lane = road_graph.entry_lane(self.current_road, self.lane, next_road);
                 self.current_road = next_road;
+                self.reached_node = true;
 
                 let mut curr_road = road_graph.get_roads().get(&self.current_road).unwrap().write().unwrap();
 
@@ -488,29 +846,20 @@ impl Car {
                 curr_road.vehicles_on.push(self.car_id);
                 curr_road.num_vehicles_on += 1;
 
-                self.segment_index = 0;
+                self.along = 0.0;
 
     
                 let new_road = curr_road;
-                let points = &new_road.points;
     
-                let dist_to_start = (points[0] - self.position).length();
-                if dist_to_start < 2.0 {
-                    self.position = points[0]; // Snap to road start
-                } else {
-                    if debug {println!("❌ Car jumped to road {:?} with dist {:.2}. Rejecting.", self.current_road, dist_to_start)};
+                let dist_to_start = new_road.position_at(0.0).distance(self.position);
+                if dist_to_start >= 2.0 {
+                    log::debug!("car {:?}: road {:?} starts {:.2} m away, dropping the path", self.car_id, self.current_road, dist_to_start);
                     self.path.clear(); // Invalidate bad path
                     return;
                 }
-    
-                if points.len() >= 2 {
-                    let dir = (points[1] - points[0]).normalize_or_zero();
-                    if dir.length_squared() > 0.0 {
-                        self.heading = dir.to_angle();
-                    }
-                }
+
+                self.follow_road(&new_road); // snap to the road start
             }
-        }
     }
     
     
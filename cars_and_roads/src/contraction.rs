@@ -0,0 +1,373 @@
+//! Contraction hierarchies, for routing on networks too big for plain A*.
+//!
+//! Building one contracts the nodes one at a time, least important first, adding a shortcut
+//! wherever a cheapest route ran through the node being removed. A query then only ever has
+//! to climb: a Dijkstra up the hierarchy from the start and another up from the goal over
+//! reversed roads, meeting somewhere near the top.
+//!
+//! Costs are read from the cost model once, when the hierarchy is built. Routes match
+//! `a_star` for as long as those costs hold, rebuild when they drift (see `is_stale`).
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::routing::{passable_cost, CostModel, Route, RouteError, State};
+use crate::{NodeID, RoadGraph, RoadID};
+
+
+// Witness searches give up after settling this many nodes. Giving up early only ever adds
+// shortcuts that weren't needed, never loses a route.
+const WITNESS_SETTLE_LIMIT: usize = 500;
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// What an edge of the hierarchy stands for.
+enum Via {
+    Road(RoadID),
+    Shortcut(usize), // through this contracted node, unpacks into the two edges either side of it
+}
+
+
+#[derive(Clone, Debug)]
+/// A road graph preprocessed for fast point-to-point queries under one cost model.
+pub struct ContractionHierarchy {
+    ids: Vec<NodeID>,
+    index: HashMap<NodeID, usize>,
+    rank: Vec<usize>, // order of contraction, higher is more important
+    edges: HashMap<(usize, usize), (f32, Via)>, // cheapest road or shortcut between two nodes
+    up: Vec<Vec<(usize, f32)>>,   // edges to higher ranked nodes
+    down: Vec<Vec<(usize, f32)>>, // edges from higher ranked nodes, stored at the lower end
+    costs: HashMap<RoadID, f32>,
+    epoch: u64,
+}
+
+
+impl ContractionHierarchy {
+
+    /// Contracts every node of `road_graph`, pricing roads with `model` as they are now.
+    pub fn build(road_graph: &RoadGraph, model: &dyn CostModel) -> Self {
+        let mut ids: Vec<NodeID> = road_graph.get_nodes().keys().copied().collect();
+        ids.sort_by_key(|node| node.0);
+        let index: HashMap<NodeID, usize> = ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+
+        // Closed roads get no cost, and so no edge
+        let costs: HashMap<RoadID, f32> = road_graph.roads_to_iter()
+            .filter_map(|road| {
+                let road = road.read().unwrap();
+                passable_cost(model, &road).map(|cost| (road.id, cost))
+            })
+            .collect();
+
+        // Only the cheapest of several roads between the same two nodes can be on a route
+        let mut edges: HashMap<(usize, usize), (f32, Via)> = HashMap::new();
+        for (from, neighbors) in &road_graph.adjacency {
+            let Some(&u) = index.get(from) else { continue };
+            for (to, road) in neighbors {
+                let (Some(&v), Some(&cost)) = (index.get(to), costs.get(road)) else { continue };
+                if u != v && edges.get(&(u, v)).is_none_or(|&(best, _)| cost < best) {
+                    edges.insert((u, v), (cost, Via::Road(*road)));
+                }
+            }
+        }
+
+        let mut contraction = Contraction::new(ids.len(), &edges);
+        let mut rank = vec![0; ids.len()];
+
+        // Least important first, the importance of a node's neighbours changes as it goes so
+        // re-check the top of the queue before trusting it
+        let mut queue: BinaryHeap<Reverse<(i32, usize)>> = (0..ids.len())
+            .map(|node| Reverse((contraction.importance(node), node)))
+            .collect();
+
+        let mut next_rank = 0;
+        while let Some(Reverse((importance, node))) = queue.pop() {
+            let current = contraction.importance(node);
+            if current > importance && queue.peek().is_some_and(|Reverse((next, _))| current > *next) {
+                queue.push(Reverse((current, node)));
+                continue;
+            }
+
+            for (from, to, cost) in contraction.contract(node) {
+                if edges.get(&(from, to)).is_none_or(|&(best, _)| cost < best) {
+                    edges.insert((from, to), (cost, Via::Shortcut(node)));
+                }
+            }
+            rank[node] = next_rank;
+            next_rank += 1;
+        }
+
+        let mut up = vec![Vec::new(); ids.len()];
+        let mut down = vec![Vec::new(); ids.len()];
+        for (&(from, to), &(cost, _)) in &edges {
+            if rank[from] < rank[to] {
+                up[from].push((to, cost));
+            } else {
+                down[to].push((from, cost));
+            }
+        }
+
+        // `edges` iterates in no particular order, keep ties between equal routes reproducible
+        for list in up.iter_mut().chain(down.iter_mut()) {
+            list.sort_by_key(|&(node, _)| node);
+        }
+
+        ContractionHierarchy { ids, index, rank, edges, up, down, costs, epoch: road_graph.cost_epoch() }
+    }
+
+    /// Whether the graph has changed, or its densities drifted, since this was built.
+    pub fn is_stale(&self, road_graph: &RoadGraph) -> bool {
+        road_graph.cost_epoch() != self.epoch
+    }
+
+    /// Shortcuts added on top of the graph's own roads.
+    pub fn shortcuts(&self) -> usize {
+        self.edges.values().filter(|(_, via)| matches!(via, Via::Shortcut(_))).count()
+    }
+
+    /// The cheapest route from `start_node` to `goal_node`, the same one `a_star` would find
+    /// with the costs this was built with.
+    pub fn route(&self, start_node: NodeID, goal_node: NodeID) -> Result<Route, RouteError> {
+        let &start = self.index.get(&start_node).ok_or(RouteError::UnknownNode(start_node))?;
+        let &goal = self.index.get(&goal_node).ok_or(RouteError::UnknownNode(goal_node))?;
+
+        if start == goal {
+            return Err(RouteError::StartIsGoal(start_node));
+        }
+
+        let mut forward = Search::new(start);
+        let mut backward = Search::new(goal);
+        let mut best: Option<(f32, usize)> = None;
+
+        // Alternate between the two searches until neither can beat the best meeting point
+        loop {
+            let bound = best.map_or(f32::INFINITY, |(cost, _)| cost);
+            let searching_forward = match (forward.peek(), backward.peek()) {
+                (Some(f), Some(b)) if f.min(b) < bound => f <= b,
+                (Some(f), None) if f < bound => true,
+                (None, Some(b)) if b < bound => false,
+                _ => break,
+            };
+
+            let (search, other, edges) = if searching_forward {
+                (&mut forward, &backward, &self.up)
+            } else {
+                (&mut backward, &forward, &self.down)
+            };
+
+            let Some((node, cost)) = search.settle(edges) else { continue };
+            if let Some(&across) = other.costs.get(&node)
+                && best.is_none_or(|(best, _)| cost + across < best) {
+                best = Some((cost + across, node));
+            }
+        }
+
+        let (_, meeting) = best.ok_or(RouteError::Unreachable(start_node, goal_node))?;
+
+        // Climb back down from the meeting point, then unpack the shortcuts on the way
+        let mut hops = vec![meeting];
+        let mut node = meeting;
+        while let Some(&previous) = forward.came_from.get(&node) {
+            hops.push(previous);
+            node = previous;
+        }
+        hops.reverse();
+        node = meeting;
+        while let Some(&next) = backward.came_from.get(&node) {
+            hops.push(next);
+            node = next;
+        }
+
+        let mut roads = Vec::new();
+        for pair in hops.windows(2) {
+            self.unpack(pair[0], pair[1], &mut roads);
+        }
+
+        // Summed road by road, like `a_star` does, so equal routes cost exactly the same
+        let mut nodes = vec![start_node];
+        let mut cost = 0.0;
+        for (road, to) in &roads {
+            nodes.push(self.ids[*to]);
+            cost += self.costs[road];
+        }
+
+        Ok(Route { nodes, roads: roads.into_iter().map(|(road, _)| road).collect(), cost })
+    }
+
+    /// The roads under the edge from `from` to `to`, each with the node it ends at.
+    fn unpack(&self, from: usize, to: usize, roads: &mut Vec<(RoadID, usize)>) {
+        match self.edges[&(from, to)].1 {
+            Via::Road(road) => roads.push((road, to)),
+            Via::Shortcut(middle) => {
+                self.unpack(from, middle, roads);
+                self.unpack(middle, to, roads);
+            }
+        }
+    }
+
+    /// Where a node came in the contraction order, mostly of interest for debugging.
+    pub fn rank(&self, node: NodeID) -> Option<usize> {
+        self.index.get(&node).map(|&i| self.rank[i])
+    }
+
+}
+
+
+/// The graph as it shrinks while nodes are contracted.
+struct Contraction {
+    outgoing: Vec<HashMap<usize, f32>>,
+    incoming: Vec<HashMap<usize, f32>>,
+    contracted_neighbors: Vec<i32>,
+}
+
+impl Contraction {
+
+    fn new(nodes: usize, edges: &HashMap<(usize, usize), (f32, Via)>) -> Self {
+        let mut outgoing = vec![HashMap::new(); nodes];
+        let mut incoming = vec![HashMap::new(); nodes];
+        for (&(from, to), &(cost, _)) in edges {
+            outgoing[from].insert(to, cost);
+            incoming[to].insert(from, cost);
+        }
+
+        Contraction { outgoing, incoming, contracted_neighbors: vec![0; nodes] }
+    }
+
+    /// How much contracting `node` would grow the graph, plus a nudge to spread contraction
+    /// evenly instead of eating one neighbourhood at a time.
+    fn importance(&self, node: usize) -> i32 {
+        let shortcuts = self.shortcuts_for(node).len() as i32;
+        let removed = (self.outgoing[node].len() + self.incoming[node].len()) as i32;
+        shortcuts - removed + self.contracted_neighbors[node]
+    }
+
+    /// Shortcuts between the neighbours of `node` needed to keep their cheapest routes once it
+    /// is gone, as (from, to, cost).
+    fn shortcuts_for(&self, node: usize) -> Vec<(usize, usize, f32)> {
+        let mut shortcuts = Vec::new();
+
+        for (&from, &into) in &self.incoming[node] {
+            let Some(longest) = self.outgoing[node].iter()
+                .filter(|(to, _)| **to != from)
+                .map(|(_, cost)| into + cost)
+                .reduce(f32::max)
+            else { continue };
+
+            let witnesses = self.witness_search(from, node, longest);
+            for (&to, &out) in &self.outgoing[node] {
+                if to == from {
+                    continue;
+                }
+                let through = into + out;
+                if witnesses.get(&to).is_none_or(|&witness| witness > through) {
+                    shortcuts.push((from, to, through));
+                }
+            }
+        }
+
+        shortcuts
+    }
+
+    /// Dijkstra from `from` around `avoid`, as far as `limit`.
+    fn witness_search(&self, from: usize, avoid: usize, limit: f32) -> HashMap<usize, f32> {
+        let mut costs = HashMap::from([(from, 0.0)]);
+        let mut open = BinaryHeap::from([State::new(from, 0.0, 0.0)]);
+        let mut settled = 0;
+
+        while let Some(State { node, cost, .. }) = open.pop() {
+            if cost > costs[&node] {
+                continue;
+            }
+            settled += 1;
+            if cost > limit || settled > WITNESS_SETTLE_LIMIT {
+                break;
+            }
+
+            for (&next, &step) in &self.outgoing[node] {
+                if next == avoid {
+                    continue;
+                }
+                let through = cost + step;
+                if costs.get(&next).is_none_or(|&known| through < known) {
+                    costs.insert(next, through);
+                    open.push(State::new(next, through, through));
+                }
+            }
+        }
+
+        costs
+    }
+
+    /// Takes `node` out of the graph, returning the shortcuts that replace it.
+    fn contract(&mut self, node: usize) -> Vec<(usize, usize, f32)> {
+        let shortcuts = self.shortcuts_for(node);
+
+        for &from in self.incoming[node].keys() {
+            self.outgoing[from].remove(&node);
+            self.contracted_neighbors[from] += 1;
+        }
+        for &to in self.outgoing[node].keys() {
+            self.incoming[to].remove(&node);
+            self.contracted_neighbors[to] += 1;
+        }
+        self.outgoing[node].clear();
+        self.incoming[node].clear();
+
+        for &(from, to, cost) in &shortcuts {
+            let known = self.outgoing[from].entry(to).or_insert(cost);
+            *known = known.min(cost);
+            let known = self.incoming[to].entry(from).or_insert(cost);
+            *known = known.min(cost);
+        }
+
+        shortcuts
+    }
+
+}
+
+
+/// One direction of a query, only ever going up the hierarchy.
+struct Search {
+    open: BinaryHeap<State<usize>>,
+    costs: HashMap<usize, f32>,
+    came_from: HashMap<usize, usize>,
+}
+
+impl Search {
+
+    fn new(from: usize) -> Self {
+        Search {
+            open: BinaryHeap::from([State::new(from, 0.0, 0.0)]),
+            costs: HashMap::from([(from, 0.0)]),
+            came_from: HashMap::new(),
+        }
+    }
+
+    /// Cost of the next node to settle, skipping entries made stale by a cheaper way in.
+    fn peek(&mut self) -> Option<f32> {
+        while let Some(state) = self.open.peek() {
+            if state.cost > self.costs[&state.node] {
+                self.open.pop();
+            } else {
+                return Some(state.cost);
+            }
+        }
+        None
+    }
+
+    /// Settles the cheapest open node, returning it and its cost.
+    fn settle(&mut self, edges: &[Vec<(usize, f32)>]) -> Option<(usize, f32)> {
+        let State { node, cost, .. } = self.open.pop()?;
+
+        for &(next, step) in &edges[node] {
+            let through = cost + step;
+            if self.costs.get(&next).is_none_or(|&known| through < known) {
+                self.costs.insert(next, through);
+                self.came_from.insert(next, node);
+                self.open.push(State::new(next, through, through));
+            }
+        }
+
+        Some((node, cost))
+    }
+
+}
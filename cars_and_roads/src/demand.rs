@@ -0,0 +1,301 @@
+//! Traffic demand: who wants to drive where, and when.
+//!
+//! An `OdMatrix` gives the trips per hour between pairs of nodes, a `DemandProfile` scales
+//! it over the day. As the simulation runs, trips turn up at their origins as Poisson
+//! arrivals and get a car on the first road of their route, waiting their turn when that
+//! road is full. The route is looked up again each time a waiting trip is tried, so edits
+//! and closures made while it waits send it a different way, or drop it if none is left.
+
+use std::collections::{HashMap, VecDeque};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::car::default_cost_model;
+use crate::{Car, NodeID, RoadGraph, RoadID};
+
+
+/// Seconds in a day, profiles wrap around after this
+const DAY: f32 = 24.0 * 3600.0;
+
+/// A new car needs this much empty road at the start of its entry road, in meters
+const MIN_SPAWN_GAP: f32 = 20.0;
+
+/// Speed cars join the road at, in m/s
+const SPAWN_SPEED: f32 = 5.0;
+
+
+
+#[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+/// Trips per hour between pairs of nodes, before the time of day is taken into account.
+pub struct OdMatrix {
+    trips: Vec<(NodeID, NodeID, f32)>, // a list rather than a map, so runs draw in the same order
+}
+
+impl OdMatrix {
+
+    pub fn new() -> Self {
+        OdMatrix::default()
+    }
+
+    /// Sets the trips per hour from `origin` to `destination`, replacing what was there.
+    pub fn set(&mut self, origin: NodeID, destination: NodeID, per_hour: f32) {
+        let per_hour = per_hour.max(0.0);
+        match self.trips.iter_mut().find(|(from, to, _)| *from == origin && *to == destination) {
+            Some(pair) => pair.2 = per_hour,
+            None => self.trips.push((origin, destination, per_hour)),
+        }
+    }
+
+    /// Trips per hour from `origin` to `destination`.
+    pub fn get(&self, origin: NodeID, destination: NodeID) -> f32 {
+        self.trips.iter()
+            .find(|(from, to, _)| *from == origin && *to == destination)
+            .map_or(0.0, |(_, _, per_hour)| *per_hour)
+    }
+
+    /// Every pair with its trips per hour.
+    pub fn pairs(&self) -> impl Iterator<Item = (NodeID, NodeID, f32)> + '_ {
+        self.trips.iter().copied()
+    }
+
+    /// Trips per hour over every pair.
+    pub fn total(&self) -> f32 {
+        self.trips.iter().map(|(_, _, per_hour)| per_hour).sum()
+    }
+
+}
+
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// How busy the day is, as a multiplier on the OD matrix.
+///
+/// Points are (hour of day, multiplier), sorted by hour. In between them the multiplier is
+/// interpolated, wrapping round from the last point of the day to the first.
+pub struct DemandProfile {
+    pub points: Vec<(f32, f32)>,
+}
+
+impl DemandProfile {
+
+    /// The OD matrix as it is, all day.
+    pub fn flat() -> Self {
+        DemandProfile { points: vec![(0.0, 1.0)] }
+    }
+
+    /// Quiet nights, an AM peak around 8:00 and a longer PM peak around 17:30.
+    pub fn commuter() -> Self {
+        DemandProfile {
+            points: vec![
+                (0.0, 0.1),
+                (5.0, 0.2),
+                (8.0, 1.8),
+                (10.0, 0.8),
+                (15.0, 0.9),
+                (17.5, 2.0),
+                (20.0, 0.6),
+                (23.0, 0.2),
+            ],
+        }
+    }
+
+    /// The multiplier at `seconds` past midnight.
+    pub fn factor(&self, seconds: f32) -> f32 {
+        let hour = seconds.rem_euclid(DAY) / 3600.0;
+
+        let Some(&(first_hour, first)) = self.points.first() else { return 1.0 };
+        let &(last_hour, last) = self.points.last().unwrap();
+
+        // Between two points of the day, or across midnight from the last back to the first
+        let ((from_hour, from), (to_hour, to)) = match self.points.windows(2).find(|pair| hour >= pair[0].0 && hour < pair[1].0) {
+            Some(pair) => (pair[0], pair[1]),
+            None if hour >= last_hour => ((last_hour, last), (first_hour + 24.0, first)),
+            None => ((last_hour - 24.0, last), (first_hour, first)),
+        };
+
+        let span = to_hour - from_hour;
+        let t = if span > 0.0 { (hour - from_hour) / span } else { 0.0 };
+        (from + (to - from) * t).max(0.0)
+    }
+
+}
+
+impl Default for DemandProfile {
+    fn default() -> Self {
+        DemandProfile::flat()
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+/// What happens to a trip whose entry road is full when it turns up.
+pub enum SpawnPolicy {
+    /// It waits at its origin and goes as soon as there is room, first come first served
+    #[default]
+    Queue,
+    /// It never happens
+    Refuse,
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+/// Running totals of what the demand has asked for and what came of it.
+pub struct DemandStats {
+    pub requested: u64,  // trips that turned up
+    pub spawned: u64,    // got a car
+    pub refused: u64,    // turned away from a full road under `SpawnPolicy::Refuse`
+    pub unroutable: u64, // no route from their origin to their destination, when they turned up or later
+}
+
+
+#[derive(Clone, Copy, Debug)]
+/// A trip that has turned up but doesn't have a car yet.
+struct PendingTrip {
+    destination: NodeID,
+}
+
+
+#[derive(Clone, Debug)]
+/// Spawns cars from an OD matrix as the simulation runs, see the module docs.
+pub struct Demand {
+    pub matrix: OdMatrix,
+    pub profile: DemandProfile,
+    pub start_time: f32, // seconds past midnight at the start of the run
+    pub policy: SpawnPolicy,
+
+    queues: HashMap<NodeID, VecDeque<PendingTrip>>,
+    stats: DemandStats,
+}
+
+impl Demand {
+
+    /// Demand from `matrix`, flat all day, starting at midnight.
+    pub fn new(matrix: OdMatrix) -> Self {
+        Demand {
+            matrix,
+            profile: DemandProfile::flat(),
+            start_time: 0.0,
+            policy: SpawnPolicy::Queue,
+            queues: HashMap::new(),
+            stats: DemandStats::default(),
+        }
+    }
+
+    /// Sets the profile, and the hour of day the run starts at.
+    pub fn with_profile(mut self, profile: DemandProfile, start_hour: f32) -> Self {
+        self.profile = profile;
+        self.start_time = start_hour * 3600.0;
+        self
+    }
+
+    pub fn with_policy(mut self, policy: SpawnPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn stats(&self) -> DemandStats {
+        self.stats
+    }
+
+    /// Trips waiting at their origins for room on the road.
+    pub fn queued(&self) -> usize {
+        self.queues.values().map(VecDeque::len).sum()
+    }
+
+    /// Trips waiting at `origin`.
+    pub fn queued_at(&self, origin: NodeID) -> usize {
+        self.queues.get(&origin).map_or(0, VecDeque::len)
+    }
+
+    /// Draws the trips that turn up in the `dt` seconds from `time` (simulation seconds),
+    /// then gives cars to as many waiting trips as there is room for.
+    ///
+    /// Called by `Simulation::step` at the start of every tick.
+    pub fn spawn(&mut self, road_graph: &mut RoadGraph, time: f32, dt: f32, rng: &mut impl Rng) {
+        let factor = self.profile.factor(self.start_time + time);
+
+        for &(origin, destination, per_hour) in &self.matrix.trips {
+            let arrivals = poisson(per_hour * factor * dt / 3600.0, rng);
+
+            for _ in 0..arrivals {
+                self.stats.requested += 1;
+
+                if entry_road(road_graph, origin, destination).is_none() {
+                    self.stats.unroutable += 1;
+                    continue;
+                }
+
+                self.queues.entry(origin).or_default().push_back(PendingTrip { destination });
+            }
+        }
+
+        let mut origins: Vec<NodeID> = self.queues.keys().copied().collect();
+        origins.sort_by_key(|node| node.0);
+
+        for origin in origins {
+            let queue = self.queues.get_mut(&origin).unwrap();
+
+            while let Some(&trip) = queue.front() {
+                // The road it would have started on may have gone or closed since it turned up
+                let Some(entry) = entry_road(road_graph, origin, trip.destination) else {
+                    queue.pop_front();
+                    self.stats.unroutable += 1;
+                    continue;
+                };
+
+                if has_room(road_graph, entry) {
+                    queue.pop_front();
+
+                    let mut car = Car::new_on_road(None, entry, road_graph, SPAWN_SPEED, trip.destination, rng);
+                    car.depart_time = time;
+                    match road_graph.add_car(car) {
+                        Ok(()) => self.stats.spawned += 1,
+                        Err(_) => self.stats.unroutable += 1,
+                    }
+                } else if self.policy == SpawnPolicy::Refuse {
+                    queue.pop_front();
+                    self.stats.refused += 1;
+                } else {
+                    break;
+                }
+            }
+        }
+
+        self.queues.retain(|_, queue| !queue.is_empty());
+    }
+
+}
+
+
+/// The first road of the route a new car would take, `None` if there is no route.
+fn entry_road(road_graph: &RoadGraph, origin: NodeID, destination: NodeID) -> Option<RoadID> {
+    road_graph.route(origin, destination, default_cost_model().as_ref()).ok()?.roads.first().copied()
+}
+
+/// Whether a car can join `road` now: it is under capacity and nobody is near its start.
+fn has_room(road_graph: &RoadGraph, road: RoadID) -> bool {
+    let Some(road) = road_graph.get_roads().get(&road) else { return false };
+    let road = road.read().unwrap();
+
+    (road.vehicles_on.len() as i32) < road.capacity
+        && road.vehicles_on.iter()
+            .filter_map(|id| road_graph.get_cars().get(id))
+            .all(|car| car.read().unwrap().along > MIN_SPAWN_GAP)
+}
+
+/// A draw from a Poisson distribution with mean `mean`, counting uniform draws until their
+/// product drops below e^-mean. Fine for the small means of a single tick.
+fn poisson(mean: f32, rng: &mut impl Rng) -> u32 {
+    if mean <= 0.0 {
+        return 0;
+    }
+
+    let limit = (-mean).exp();
+    let mut product: f32 = rng.random_range(0.0..1.0);
+    let mut count = 0;
+    while product > limit {
+        product *= rng.random_range(0.0..1.0);
+        count += 1;
+    }
+    count
+}
@@ -0,0 +1,68 @@
+//! Intelligent Driver Model (IDM) for longitudinal car-following.
+//!
+//! Each car accelerates towards its desired speed on a free road and brakes
+//! smoothly as the gap to the car in front shrinks.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+/// Per-driver IDM parameters.
+pub struct IdmParams {
+    pub desired_speed: f32,       // v0, top speed on a free road, road limits usually lower it
+    pub time_headway: f32,        // T, seconds kept to the leader
+    pub min_gap: f32,             // s0, bumper-to-bumper gap when stopped
+    pub max_acceleration: f32,    // a
+    pub comfortable_braking: f32, // b, positive
+    pub exponent: f32,            // delta, how quickly acceleration drops near v0
+}
+
+impl Default for IdmParams {
+    fn default() -> Self {
+        IdmParams {
+            desired_speed: 40.0,
+            time_headway: 1.5,
+            min_gap: 2.0,
+            max_acceleration: 1.0,
+            comfortable_braking: 1.5,
+            exponent: 4.0,
+        }
+    }
+}
+
+impl IdmParams {
+
+    pub fn with_desired_speed(desired_speed: f32) -> Self {
+        IdmParams { desired_speed, ..Default::default() }
+    }
+
+    /// Acceleration for a car going `speed`.
+    ///
+    /// `leader` is the bumper-to-bumper gap to the car (or obstacle) in front and that
+    /// car's speed, `None` on a free road.
+    pub fn acceleration(&self, speed: f32, leader: Option<(f32, f32)>) -> f32 {
+
+        let free_road = if self.desired_speed > 0.0 {
+            1.0 - (speed / self.desired_speed).powf(self.exponent)
+        } else {
+            -1.0 // nowhere it wants to go, so just brake
+        };
+
+        let interaction = match leader {
+            Some((gap, leader_speed)) => {
+                let approach = speed - leader_speed;
+                let desired_gap = self.min_gap
+                    + (speed * self.time_headway
+                        + speed * approach / (2.0 * (self.max_acceleration * self.comfortable_braking).sqrt()))
+                        .max(0.0);
+
+                // A gap of zero (or overlapping cars) would divide by zero, treat it as very tight
+                let gap = gap.max(0.01);
+                (desired_gap / gap).powi(2)
+            }
+            None => 0.0,
+        };
+
+        self.max_acceleration * (free_road - interaction)
+    }
+
+}
@@ -0,0 +1,422 @@
+//! Traffic control at nodes.
+//!
+//! A node with no control lets everyone straight through. A controlled node decides,
+//! per movement (incoming road, outgoing road), whether a car may enter it.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{CarID, RoadID};
+
+
+/// Cars closer than this to the end of their road count as being at the stop line
+const STOP_LINE_RANGE: f32 = 5.0;
+
+/// Below this speed a car counts as stopped
+pub(crate) const STOPPED_SPEED: f32 = 0.1;
+
+/// Seconds a give-way driver needs between itself and oncoming priority traffic
+const CRITICAL_GAP: f32 = 4.0;
+
+/// Priority traffic closer than this always blocks, however slowly it is going
+const MIN_CLEAR_DISTANCE: f32 = 10.0;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// What a signal shows for one movement.
+pub enum Aspect {
+    Green,
+    Amber,
+    Red,
+}
+
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// One stage of a signal plan: the movements that get green together, and for how long.
+pub struct SignalPhase {
+    pub movements: Vec<(RoadID, RoadID)>, // (incoming road, outgoing road)
+    pub green: f32,
+    pub amber: f32,
+    pub red: f32, // all-red clearance after amber, before the next phase starts
+}
+
+impl SignalPhase {
+    pub fn new(movements: Vec<(RoadID, RoadID)>, green: f32, amber: f32, red: f32) -> Self {
+        SignalPhase { movements, green, amber, red }
+    }
+
+    pub fn allows(&self, from: RoadID, to: RoadID) -> bool {
+        self.movements.contains(&(from, to))
+    }
+
+    /// Roads with at least one movement in this phase, each listed once.
+    pub fn incoming_roads(&self) -> Vec<RoadID> {
+        let mut roads: Vec<RoadID> = self.movements.iter().map(|&(from, _)| from).collect();
+        roads.sort_by_key(|road| road.0);
+        roads.dedup();
+        roads
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+/// How a signal decides when to move on to the next phase.
+pub enum SignalMode {
+    /// Every phase runs for exactly its `green` time, in order
+    FixedTime,
+    /// Green lasts at least `min_green` and at most `max_green`. In between it is held while
+    /// cars are waiting on the phase, and handed over early when they aren't but someone
+    /// else is. Phases nobody is waiting on are skipped.
+    Actuated { min_green: f32, max_green: f32 },
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stage {
+    Green,
+    Amber,
+    AllRed,
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+/// A traffic signal cycling through a list of phases.
+pub struct SignalController {
+    pub phases: Vec<SignalPhase>,
+    pub mode: SignalMode,
+
+    current: usize,
+    stage: Stage,
+    elapsed: f32, // time spent in the current stage
+}
+
+impl SignalController {
+
+    pub fn new(phases: Vec<SignalPhase>, mode: SignalMode) -> Self {
+        SignalController {
+            phases,
+            mode,
+            current: 0,
+            stage: Stage::Green,
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn fixed_time(phases: Vec<SignalPhase>) -> Self {
+        SignalController::new(phases, SignalMode::FixedTime)
+    }
+
+    pub fn actuated(phases: Vec<SignalPhase>, min_green: f32, max_green: f32) -> Self {
+        SignalController::new(phases, SignalMode::Actuated { min_green, max_green })
+    }
+
+    /// Index into `phases` of the phase currently running.
+    pub fn current_phase(&self) -> usize {
+        self.current
+    }
+
+    /// What the signal shows for the movement from road `from` into road `to`.
+    pub fn aspect(&self, from: RoadID, to: RoadID) -> Aspect {
+        match self.phases.get(self.current) {
+            Some(phase) if phase.allows(from, to) => self.stage_aspect(),
+            _ => Aspect::Red,
+        }
+    }
+
+    /// The most permissive aspect shown to any movement out of road `from`, for drawing
+    /// one signal head per approach.
+    pub fn approach_aspect(&self, from: RoadID) -> Aspect {
+        match self.phases.get(self.current) {
+            Some(phase) if phase.movements.iter().any(|&(road, _)| road == from) => self.stage_aspect(),
+            _ => Aspect::Red,
+        }
+    }
+
+    fn stage_aspect(&self) -> Aspect {
+        match self.stage {
+            Stage::Green => Aspect::Green,
+            Stage::Amber => Aspect::Amber,
+            Stage::AllRed => Aspect::Red,
+        }
+    }
+
+    /// Advances the signal by `dt`.
+    ///
+    /// `has_demand` says whether cars are waiting for a phase, it is only asked in actuated mode.
+    pub fn update(&mut self, dt: f32, has_demand: impl Fn(&SignalPhase) -> bool) {
+        if self.phases.is_empty() {
+            return;
+        }
+
+        self.elapsed += dt;
+        let phase = &self.phases[self.current];
+
+        match self.stage {
+            Stage::Green => {
+                let end_green = match self.mode {
+                    SignalMode::FixedTime => self.elapsed >= phase.green,
+                    SignalMode::Actuated { min_green, max_green } => {
+                        let others_waiting = self.phases.iter().enumerate()
+                            .any(|(index, other)| index != self.current && has_demand(other));
+
+                        others_waiting
+                            && (self.elapsed >= max_green || (self.elapsed >= min_green && !has_demand(phase)))
+                    }
+                };
+
+                if end_green {
+                    self.enter(Stage::Amber);
+                }
+            }
+            Stage::Amber => {
+                if self.elapsed >= phase.amber {
+                    self.enter(Stage::AllRed);
+                }
+            }
+            Stage::AllRed => {
+                if self.elapsed >= phase.red {
+                    self.current = self.next_phase(&has_demand);
+                    self.enter(Stage::Green);
+                }
+            }
+        }
+    }
+
+    fn enter(&mut self, stage: Stage) {
+        self.stage = stage;
+        self.elapsed = 0.0;
+    }
+
+    fn next_phase(&self, has_demand: &impl Fn(&SignalPhase) -> bool) -> usize {
+        let count = self.phases.len();
+        let following = (self.current + 1) % count;
+
+        match self.mode {
+            SignalMode::FixedTime => following,
+            SignalMode::Actuated { .. } => (0..count)
+                .map(|offset| (following + offset) % count)
+                .find(|&index| has_demand(&self.phases[index]))
+                .unwrap_or(following),
+        }
+    }
+
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// Unsignalized right-of-way rules.
+pub enum PriorityRule {
+    /// Everyone stops, then goes in the order they stopped
+    AllWayStop,
+    /// Major approaches drive through, minor ones stop and then give way to them
+    TwoWayStop,
+    /// Major approaches drive through, minor ones give way to them without having to stop
+    Yield,
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The first car waiting in one lane of a road into a node, as a priority control sees it.
+pub struct Approach {
+    pub road: RoadID,
+    pub next_road: RoadID,
+    pub car: CarID,
+    pub distance: f32, // to the end of the road
+    pub speed: f32,
+    pub braking: f32,  // the driver's comfortable deceleration
+}
+
+impl Approach {
+    fn at_stop_line(&self) -> bool {
+        self.distance <= STOP_LINE_RANGE && self.speed < STOPPED_SPEED
+    }
+
+    fn can_still_stop(&self) -> bool {
+        self.distance >= self.speed * self.speed / (2.0 * self.braking)
+    }
+
+    /// Whether a give-way driver can go in front of this car.
+    fn leaves_gap(&self) -> bool {
+        self.distance > MIN_CLEAR_DISTANCE && self.arrival_time() > CRITICAL_GAP
+    }
+
+    /// Seconds until it reaches the stop line at the speed it is going.
+    fn arrival_time(&self) -> f32 {
+        self.distance / self.speed.max(STOPPED_SPEED)
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+/// Stop signs and yield signs: which cars may enter the node is worked out each tick from
+/// the cars waiting on every approach and which of their movements cross.
+///
+/// Of two major movements that cross, the car that gets to the line sooner goes first. Minor
+/// ones give way to both, and of two that cross, the first to stop (or with yield signs, to
+/// turn up) goes first. Nobody goes across a car already committed to the node.
+pub struct PriorityControl {
+    pub rule: PriorityRule,
+    pub major: Vec<RoadID>, // incoming roads with priority, unused for all-way stops
+
+    clock: f32,
+    arrived_at: HashMap<CarID, f32>, // when each waiting car became first in its lane
+    stopped_at: HashMap<CarID, f32>, // when each waiting car came to a halt at the line
+    granted: HashSet<CarID>,
+}
+
+impl PriorityControl {
+
+    pub fn new(rule: PriorityRule, major: Vec<RoadID>) -> Self {
+        PriorityControl {
+            rule,
+            major,
+            clock: 0.0,
+            arrived_at: HashMap::new(),
+            stopped_at: HashMap::new(),
+            granted: HashSet::new(),
+        }
+    }
+
+    pub fn all_way_stop() -> Self {
+        PriorityControl::new(PriorityRule::AllWayStop, Vec::new())
+    }
+
+    pub fn two_way_stop(major: Vec<RoadID>) -> Self {
+        PriorityControl::new(PriorityRule::TwoWayStop, major)
+    }
+
+    pub fn yield_to(major: Vec<RoadID>) -> Self {
+        PriorityControl::new(PriorityRule::Yield, major)
+    }
+
+    pub fn is_major(&self, road: RoadID) -> bool {
+        self.rule != PriorityRule::AllWayStop && self.major.contains(&road)
+    }
+
+    /// Whether drivers on `road` have to stop at the line before going.
+    pub fn must_stop(&self, road: RoadID) -> bool {
+        match self.rule {
+            PriorityRule::AllWayStop => true,
+            PriorityRule::TwoWayStop => !self.is_major(road),
+            PriorityRule::Yield => false,
+        }
+    }
+
+    /// Whether `car` may enter the node, as of the last `update`.
+    pub fn is_granted(&self, car: CarID) -> bool {
+        self.granted.contains(&car)
+    }
+
+    /// Works out who may go this tick.
+    ///
+    /// `approaches` holds the first car in each lane of every incoming road and `conflict`
+    /// says whether two (incoming, outgoing) movements cross or merge.
+    pub fn update(&mut self, dt: f32, approaches: &[Approach], conflict: impl Fn((RoadID, RoadID), (RoadID, RoadID)) -> bool) {
+        self.clock += dt;
+
+        let waiting: HashSet<CarID> = approaches.iter().map(|approach| approach.car).collect();
+        self.arrived_at.retain(|car, _| waiting.contains(car));
+        self.stopped_at.retain(|car, _| waiting.contains(car));
+
+        for approach in approaches {
+            self.arrived_at.entry(approach.car).or_insert(self.clock);
+            if approach.at_stop_line() {
+                self.stopped_at.entry(approach.car).or_insert(self.clock);
+            }
+        }
+
+        // Once a car is let go and can't stop comfortably any more, it's committed
+        let previously = std::mem::take(&mut self.granted);
+        let committed: HashSet<CarID> = approaches.iter()
+            .filter(|approach| previously.contains(&approach.car) && !approach.can_still_stop())
+            .map(|approach| approach.car)
+            .collect();
+        self.granted.extend(&committed);
+
+        // Minor approaches take turns in the order they stopped, or turned up if they needn't stop
+        let minor_queue = match self.rule {
+            PriorityRule::Yield => &self.arrived_at,
+            PriorityRule::AllWayStop | PriorityRule::TwoWayStop => &self.stopped_at,
+        };
+
+        for approach in approaches.iter().filter(|approach| !committed.contains(&approach.car)) {
+            if self.must_stop(approach.road) && !self.stopped_at.contains_key(&approach.car) {
+                continue;
+            }
+
+            let movement = (approach.road, approach.next_road);
+            let conflicting: Vec<&Approach> = approaches.iter()
+                .filter(|other| other.road != approach.road)
+                .filter(|other| conflict(movement, (other.road, other.next_road)))
+                .collect();
+
+            if conflicting.iter().any(|other| committed.contains(&other.car)) {
+                continue;
+            }
+
+            // Earlier goes first, ties broken by road ID
+            let may_go = match self.rule {
+                PriorityRule::AllWayStop => {
+                    let mine = (self.stopped_at[&approach.car], approach.road.0);
+                    conflicting.iter()
+                        .filter_map(|other| self.stopped_at.get(&other.car).map(|time| (*time, other.road.0)))
+                        .all(|theirs| mine < theirs)
+                }
+                PriorityRule::TwoWayStop | PriorityRule::Yield if self.is_major(approach.road) => {
+                    let mine = (approach.arrival_time(), approach.road.0);
+                    conflicting.iter()
+                        .filter(|other| self.is_major(other.road))
+                        .all(|other| mine < (other.arrival_time(), other.road.0))
+                }
+                PriorityRule::TwoWayStop | PriorityRule::Yield => {
+                    let mine = (minor_queue[&approach.car], approach.road.0);
+                    conflicting.iter().all(|other| if self.is_major(other.road) {
+                        other.leaves_gap()
+                    } else {
+                        minor_queue.get(&other.car).is_none_or(|time| mine < (*time, other.road.0))
+                    })
+                }
+            };
+
+            if may_go {
+                self.granted.insert(approach.car);
+            }
+        }
+    }
+
+}
+
+
+/// Whether two movements through a node cross, judged from the directions their roads
+/// leave the node in.
+///
+/// Each argument is the bearing (radians) from the node towards where a movement comes from
+/// or goes to. Drawn on a circle around the node, two movements cross when exactly one end of
+/// one lies strictly inside the arc spanned by the other.
+pub fn movements_cross(a_in: f32, a_out: f32, b_in: f32, b_out: f32) -> bool {
+    use std::f32::consts::TAU;
+
+    const SAME_DIRECTION: f32 = 1e-3;
+
+    let arc = |from: f32, to: f32| (to - from).rem_euclid(TAU);
+    let same = |x: f32, y: f32| arc(x, y).min(arc(y, x)) < SAME_DIRECTION;
+
+    // Sharing a road end means the paths meet there side by side, not that they cross
+    if [a_in, a_out].iter().any(|&a| same(a, b_in) || same(a, b_out)) {
+        return false;
+    }
+
+    let span = arc(a_in, a_out);
+    let inside = |angle: f32| arc(a_in, angle) < span;
+
+    inside(b_in) != inside(b_out)
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+/// Right-of-way control a node can carry.
+pub enum NodeControl {
+    Signal(SignalController),
+    Priority(PriorityControl),
+}
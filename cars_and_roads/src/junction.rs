@@ -0,0 +1,140 @@
+//! The shape of the network where roads meet.
+//!
+//! Roads into a junction stop short of its node, leaving room for the turns through it. Each
+//! turn is a `Connector`: a bezier from the end of one road to the start of the next whose
+//! control points lie along both roads, so it leaves the first and joins the second with no
+//! kink in direction. Cars past the stop line drive the connector before moving onto the
+//! next road. A node only one other node connects to is no junction, and roads run right up
+//! to it. A U-turn onto a road's own twin starts where it ends, so it still turns on the spot.
+
+use std::collections::HashSet;
+
+use macroquad::math::Vec2;
+
+use crate::road::{bend_at, cumulative_lengths, direction_at, generate_bezier, point_at, sample_bezier, Road, RoadGraph, LANE_WIDTH};
+use crate::{NodeID, RoadID};
+
+
+/// Room left between the node and the widest road into a junction, in meters
+const JUNCTION_MARGIN: f32 = 4.0;
+
+/// A road gives up at most this share of its straight-line length to each of its junctions
+const MAX_SETBACK_SHARE: f32 = 1.0 / 3.0;
+
+/// Ends closer together than this, in meters, already meet and need no connector
+const MIN_CONNECTOR_LENGTH: f32 = 0.5;
+
+const CONNECTOR_STEPS: usize = 24;
+
+
+#[derive(Clone, Debug)]
+/// The curve a turn through a junction follows, from the end of `from` to the start of `to`.
+pub struct Connector {
+    pub from: RoadID,
+    pub to: RoadID,
+    pub length: f32, // in meters
+    pub points: Vec<Vec2>,
+    arc_lengths: Vec<f32>, // distance along the connector to each of `points`
+}
+
+impl Connector {
+
+    /// The turn from `from` onto `to`, `None` if `to` doesn't start at the node `from` leads
+    /// to or the two roads already meet there.
+    pub fn between(from: &Road, to: &Road) -> Option<Connector> {
+        if from.to.id != to.from.id {
+            return None;
+        }
+
+        let start = from.position_at(from.length);
+        let end = to.position_at(0.0);
+        let span = start.distance(end);
+        if span < MIN_CONNECTOR_LENGTH {
+            return None;
+        }
+
+        let (leaving, joining) = (from.tangent_at(from.length), to.tangent_at(0.0));
+        let handle = span * handle_share(leaving.angle_between(joining).abs());
+        let control = [start, start + leaving * handle, end - joining * handle, end];
+
+        let points = sample_bezier(control, CONNECTOR_STEPS);
+        let arc_lengths = cumulative_lengths(&points);
+
+        Some(Connector {
+            from: from.id,
+            to: to.id,
+            length: arc_lengths.last().copied().unwrap_or(0.0),
+            points,
+            arc_lengths,
+        })
+    }
+
+    /// Distance along the connector to each of `points`, starting from zero.
+    pub fn arc_lengths(&self) -> &[f32] {
+        &self.arc_lengths
+    }
+
+    /// The point `s` meters into the turn. Clamped to either end.
+    pub fn position_at(&self, s: f32) -> Vec2 {
+        point_at(&self.points, &self.arc_lengths, s).unwrap_or_default()
+    }
+
+    /// The direction of travel `s` meters into the turn, as a unit vector.
+    pub fn tangent_at(&self, s: f32) -> Vec2 {
+        direction_at(&self.points, &self.arc_lengths, s)
+    }
+
+    /// How sharply the turn bends at `points[index]`, as 1 / turning radius.
+    pub fn curvature_at_index(&self, index: usize) -> f32 {
+        bend_at(&self.points, index)
+    }
+
+}
+
+
+/// How far along each road's direction a connector's control points reach, as a share of
+/// the distance between its ends, for a turn through `angle` radians. That is what makes the
+/// bezier closest to a circular arc: a third going straight on, about 0.39 for a right angle.
+fn handle_share(angle: f32) -> f32 {
+    let half = angle.min(std::f32::consts::PI - 0.01) / 2.0;
+    if half < 0.001 {
+        return 1.0 / 3.0;
+    }
+    2.0 / 3.0 * (half / 2.0).tan() / half.sin()
+}
+
+/// How far short of `node` roads into it stop, in meters. Zero unless it is a junction, else
+/// enough to clear the widest road into it.
+pub(crate) fn setback(road_graph: &RoadGraph, node: NodeID) -> f32 {
+    let outgoing = road_graph.adjacency.get(&node).cloned().unwrap_or_default();
+    let incoming = road_graph.incoming_roads(node);
+
+    let roads: Vec<_> = outgoing.iter().map(|&(_, id)| id).chain(incoming)
+        .filter_map(|id| road_graph.get_roads().get(&id))
+        .map(|road| road.read().unwrap())
+        .collect();
+
+    let others: HashSet<NodeID> = roads.iter()
+        .map(|road| if road.from.id == node { road.to.id } else { road.from.id })
+        .filter(|other| *other != node)
+        .collect();
+    if others.len() < 2 {
+        return 0.0;
+    }
+
+    let widest = roads.iter().map(|road| road.lanes.max(1)).max().unwrap_or(1);
+    widest as f32 * LANE_WIDTH + JUNCTION_MARGIN
+}
+
+/// The points of a road from `start` to `end` with `curviness`, pulled back from either node
+/// by its setback, and how far it ended up pulled back at each end. Never gives up more than
+/// `MAX_SETBACK_SHARE` of the road at either end.
+pub(crate) fn shape_between(start: Vec2, end: Vec2, setbacks: (f32, f32), curviness: f32) -> (Vec<Vec2>, (f32, f32)) {
+    let chord = end - start;
+    let limit = chord.length() * MAX_SETBACK_SHARE;
+    let dir = chord.normalize_or_zero();
+
+    let setbacks = (setbacks.0.min(limit), setbacks.1.min(limit));
+    let points = sample_bezier(generate_bezier(start + dir * setbacks.0, end - dir * setbacks.1, curviness), 50);
+    (points, setbacks)
+}
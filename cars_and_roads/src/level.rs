@@ -1,11 +1,14 @@
-/// This just defines the Simulation I want to run.
-/// Designed to be modular.
-/// 
+//! This just defines the Simulation I want to run.
+//! Designed to be modular.
+//!
+//! Levels live in JSON files, see `LevelData` for what goes in one. The `levels` folder of
+//! this crate has the stock ones.
 
 use crate::*;
 use macroquad::math::Vec2;
-use ::rand::random_range;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 
 
 
@@ -26,220 +29,447 @@ pub fn adjacency_to_dot(adj: &HashMap<NodeID, Vec<(NodeID, RoadID)>>) -> String
     }
 
     writeln!(dot, "}}").unwrap();
-    println!("{}", &dot);
     dot
 }
 
 
 
 
-pub struct Level {
-    pub road_graph: RoadGraph,
+#[derive(Debug)]
+/// Reasons a level can't be loaded or saved.
+pub enum LevelError {
+    Io(std::io::Error),
+    Format(serde_json::Error),
+    /// The level refers to something that isn't in it, or has two of something
+    Graph(GraphError),
+    /// The network failed `RoadGraph::validate` under the level's `ValidationMode`
+    Invalid(Vec<Issue>),
 }
 
+impl std::fmt::Display for LevelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LevelError::Io(error) => write!(f, "couldn't read or write the level: {}", error),
+            LevelError::Format(error) => write!(f, "not a valid level file: {}", error),
+            LevelError::Graph(error) => write!(f, "inconsistent level: {}", error),
+            LevelError::Invalid(issues) => {
+                write!(f, "invalid level network:")?;
+                issues.iter().try_for_each(|issue| write!(f, "\n  {}", issue))
+            }
+        }
+    }
+}
 
-impl Level {
+impl std::error::Error for LevelError {}
 
-    pub fn sim1(device: String, num_cars: i32) -> Self {
+impl From<std::io::Error> for LevelError {
+    fn from(error: std::io::Error) -> Self {
+        LevelError::Io(error)
+    }
+}
 
-    let screen_width = if device == "laptop" {1920.0} else {1200.0};
-    let screen_height = if device == "laptop" {1200.0} else {1920.0};
+impl From<serde_json::Error> for LevelError {
+    fn from(error: serde_json::Error) -> Self {
+        LevelError::Format(error)
+    }
+}
 
-    println!("Width: {}\nHeight: {}", screen_width, screen_height);
+impl From<GraphError> for LevelError {
+    fn from(error: GraphError) -> Self {
+        LevelError::Graph(error)
+    }
+}
 
 
-    let center: Vec2 = Vec2 {x: screen_width / 2.0, y: screen_height / 2.0};
+fn default_dt() -> f32 { 0.1 }
+fn default_density_window() -> f32 { 30.0 }
+fn default_lanes() -> usize { 1 }
+fn default_speed() -> f32 { 5.0 }
+fn default_compliance() -> f32 { 1.0 }
+
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// How the level is run.
+pub struct SimSettings {
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default = "default_dt")]
+    pub dt: f32, // seconds per tick
+    #[serde(default = "default_density_window")]
+    pub density_window: f32, // seconds, see `RoadGraph::set_density_window`
+    #[serde(default)]
+    pub validation: ValidationMode, // what loading does about what `RoadGraph::validate` finds
+}
 
-    // Nodes and Roads
-    
-    let node1: Node = Node::new_node(NodeID(1), Vec2 { x: center.x - 450.0, y: center.y });
-    let node2: Node = Node::new_node(NodeID(2), Vec2 { x: center.x + 150.0, y: center.y });
-    let node3: Node = Node::new_node(NodeID(3), Vec2 { x: center.x + 150.0, y: center.y - 300.0 });
-    let node4: Node = Node::new_node(NodeID(4), Vec2 { x: center.x - 600.0, y: center.y - 400.0 });
+impl Default for SimSettings {
+    fn default() -> Self {
+        SimSettings { seed: 0, dt: default_dt(), density_window: default_density_window(), validation: ValidationMode::default() }
+    }
+}
 
-    let road1: Road = Road::new_road_with_curves(RoadID(0), node1, node2, 100, 60.0, 50.0);
-    let road2: Road = Road::new_road(RoadID(1), node1, node3, 100, 60.0, );
-    let road3: Road = Road::new_road(RoadID(2), node2, node3, 100, 60.0, );
-    let road4: Road = Road::new_road_with_curves(RoadID(3), node3, node4, 30, 65.0, 50.0);
 
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NodeData {
+    pub id: NodeID,
+    pub x: f32,
+    pub y: f32,
+}
 
-    let mut road_graph: RoadGraph = RoadGraph::new(vec![road1, road2, road3, road4].into(), 
-                                                   vec![node1, node2, node3, node4].into());
 
-    let num_roads = road_graph.get_roads().len() - 1;
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// One direction of a road.
+///
+/// A two-way road only needs writing down once: with `one_way` false the other direction is
+/// made on load, as `twin` if that is given. The shape comes from `curviness` and the
+/// junctions at either end, so it is never stored.
+pub struct RoadData {
+    pub id: RoadID,
+    pub from: NodeID,
+    pub to: NodeID,
+    pub capacity: i32,
+    pub speed_limit: f32, // km/h
+    #[serde(default)]
+    pub curviness: f32,
+    #[serde(default = "default_lanes")]
+    pub lanes: usize,
+    #[serde(default)]
+    pub toll: f32,
+    #[serde(default)]
+    pub one_way: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub twin: Option<RoadID>,
+}
 
-    adjacency_to_dot(&road_graph.get_adjacency());
 
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ControlData {
+    Signal { node: NodeID, phases: Vec<SignalPhase>, mode: SignalMode },
+    Priority { node: NodeID, rule: PriorityRule, major: Vec<RoadID> },
+}
 
-    let cars: Vec<Car> = 
-        (0..num_cars)
-            .map(|i| Car::new_on_road(None, RoadID(i & num_roads as i32), &mut road_graph, 5.0, NodeID(4)))
-            .collect();
 
-    for car in cars {
-        road_graph.add_car(car);
-    }
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DemandData {
+    pub matrix: OdMatrix,
+    #[serde(default)]
+    pub profile: DemandProfile,
+    #[serde(default)]
+    pub start_hour: f32,
+    #[serde(default)]
+    pub policy: SpawnPolicy,
+}
 
-    Level {road_graph}
 
-    
-    }
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// A car on the road when the level starts.
+pub struct CarData {
+    /// Handed out by the graph when left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<CarID>,
+    pub road: RoadID,
+    pub destination: NodeID,
+    #[serde(default = "default_speed")]
+    pub speed: f32, // m/s
+    /// Picked at random on multi-lane roads when left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lane: Option<usize>,
+    /// Meters along `road`, past its end while turning onto `path[0]`. Somewhere in the
+    /// first 10 m when left out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub along: Option<f32>,
+    /// The roads still to drive after `road`, planned on the first tick when left out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub path: Vec<RoadID>,
+    #[serde(default)]
+    pub idm: IdmParams,
+    #[serde(default = "default_compliance")]
+    pub compliance: f32,
+    #[serde(default)]
+    pub reroute: ReroutePolicy,
+}
 
 
-    pub fn sim2(device: String, num_cars: i32) -> Self {
-        let screen_width = if device == "laptop" {1920.0} else {1200.0};
-        let screen_height = if device == "laptop" {1200.0} else {1920.0};
-        let center = Vec2 { x: screen_width / 2.0, y: screen_height / 2.0 };
-
-        println!("Width: {}\nHeight: {}", screen_width, screen_height);
-
-        // === Nodes ===
-        let node_top = Node::new_node(NodeID(1), Vec2 { x: center.x, y: center.y - 300.0 });
-        let node_right = Node::new_node(NodeID(2), Vec2 { x: center.x + 300.0, y: center.y });
-        let node_bottom = Node::new_node(NodeID(3), Vec2 { x: center.x, y: center.y + 300.0 });
-        let node_left = Node::new_node(NodeID(4), Vec2 { x: center.x - 300.0, y: center.y });
-        let node_center = Node::new_node(NodeID(5), center);
-
-        // === Roads ===
-        let road_top_center = Road::new_road(RoadID(0), node_top, node_center, 100, 60.0);
-        let road_center_right = Road::new_road_with_curves(RoadID(1), node_center, node_right, 100, 60.0, 30.0);
-        let road_right_bottom = Road::new_road(RoadID(2), node_right, node_bottom, 100, 60.0);
-        let road_bottom_center = Road::new_road_with_curves(RoadID(3), node_bottom, node_center, 100, 60.0, 30.0);
-        let road_center_left = Road::new_road(RoadID(4), node_center, node_left, 100, 60.0);
-        let road_left_top = Road::new_road_with_curves(RoadID(5), node_left, node_top, 100, 60.0, 30.0);
-
-        let mut road_graph = RoadGraph::new(
-            vec![
-                road_top_center,
-                road_center_right,
-                road_right_bottom,
-                road_bottom_center,
-                road_center_left,
-                road_left_top,
-            ].into(),
-            vec![node_top, node_right, node_bottom, node_left, node_center].into(),
-        );
-
-        adjacency_to_dot(&road_graph.get_adjacency());
-
-        // === Cars using your syntax ===
-        let cars: Vec<Car> = 
-            (0..num_cars)
-                .map(|i| {
-                    // match the same goals as earlier sim2 for variety
-                    let goals = [
-                        NodeID(3), // bottom
-                        NodeID(4), // left
-                        NodeID(1), // top
-                        NodeID(2), // right
-                        NodeID(1), // top
-                        NodeID(3), // bottom
-                    ];
-                    Car::new_on_road(Some(CarID(i)), RoadID(i % 5), &mut road_graph, 5.0, goals[(i as usize % goals.len()) as usize])
-                })
-                .collect();
-
-        for car in cars {
-            road_graph.add_car(car);
-        }
-        Level { road_graph }
-    }   
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// An obstruction on one direction of a road, see `Obstruction`.
+pub struct ObstructionData {
+    pub id: ObstructionID,
+    pub road: RoadID,
+    #[serde(default)]
+    pub kind: ObstructionKind,
+    pub at: f32, // meters along the road
+    /// Every lane when left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lanes: Option<Vec<usize>>,
+    /// Stays until cleared when left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remaining: Option<f32>, // seconds
+}
+
+
+#[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+/// Everything in a level file.
+pub struct LevelData {
+    #[serde(default)]
+    pub sim: SimSettings,
+    pub nodes: Vec<NodeData>,
+    pub roads: Vec<RoadData>,
+    #[serde(default)]
+    pub controls: Vec<ControlData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub demand: Option<DemandData>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub obstructions: Vec<ObstructionData>,
+    #[serde(default)]
+    pub cars: Vec<CarData>,
+}
+
 
-    pub fn sim3(num_cars: i32) -> Self {
+pub struct Level {
+    pub road_graph: RoadGraph,
+    pub demand: Option<Demand>, // cars to spawn as the sim runs, for `Simulation::with_demand`
+    pub config: SimConfig,
+    pub dt: f32,
+    pub validation: ValidationMode,
+    pub issues: Vec<Issue>, // what validation found on load that wasn't bad enough to refuse the level
+}
 
-        let screen_width: f32 = 1080.0;
-        let screen_height: f32 = 1920.0;
-        let center = Vec2 { x: screen_width / 2.0, y: screen_height / 2.0 };
 
-        println!("Width: {}\nHeight: {}", screen_width, screen_height);
+impl Level {
 
-        // === Nodes ===
-        let node_start = Node::new_node(NodeID(1), Vec2 { x: center.x - 500.0, y: center.y });
-        let node_top   = Node::new_node(NodeID(2), Vec2 { x: center.x + 300.0, y: center.y - 200.0 });
-        let node_mid   = Node::new_node(NodeID(3), Vec2 { x: center.x + 300.0, y: center.y });
-        let node_bot   = Node::new_node(NodeID(4), Vec2 { x: center.x + 300.0, y: center.y + 200.0 });
+    /// Reads a level file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LevelError> {
+        let text = std::fs::read_to_string(path)?;
+        Level::from_data(&serde_json::from_str(&text)?)
+    }
 
-        // === Roads ===
-        let road_top = Road::new_road_with_curves(RoadID(0), node_start, node_top, 100, 55.0, 40.0);
-        let road_mid = Road::new_road(RoadID(1), node_start, node_mid, 100, 55.0);
-        let road_bot = Road::new_road_with_curves(RoadID(2), node_start, node_bot, 100, 55.0, 40.0);
+    /// Writes the level as it is now, roads, controls, obstructions and cars included, to a
+    /// level file. Cars are saved where they are, with their drivers and routes.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), LevelError> {
+        let text = serde_json::to_string_pretty(&self.to_data())?;
+        std::fs::write(path, text + "\n")?;
+        Ok(())
+    }
 
-        let mut road_graph = RoadGraph::new(
-            vec![road_top, road_mid, road_bot].into(),
-            vec![node_start, node_top, node_mid, node_bot].into(),
-        );
+    /// Builds the level `data` describes, seeding its cars from `data.sim.seed`, then
+    /// validates it as `data.sim.validation` says.
+    pub fn from_data(data: &LevelData) -> Result<Self, LevelError> {
+        let config = SimConfig::new(data.sim.seed);
+        let mut rng = config.rng();
 
-        adjacency_to_dot(&road_graph.get_adjacency());
+        let nodes: HashMap<NodeID, Node> = data.nodes.iter()
+            .map(|node| (node.id, Node::new_node(node.id, Vec2::new(node.x, node.y))))
+            .collect();
+        if nodes.len() != data.nodes.len() {
+            let mut seen = HashSet::new();
+            let duplicate = data.nodes.iter().find(|node| !seen.insert(node.id)).unwrap();
+            return Err(GraphError::DuplicateNode(duplicate.id).into());
+        }
 
+        let mut roads = Vec::new();
+        for road in &data.roads {
+            let node = |id: NodeID| nodes.get(&id).copied().ok_or(GraphError::UnknownNode(id));
+            let (from, to) = (node(road.from)?, node(road.to)?);
+
+            let mut built = Road::new_road_with_curves(road.id, from, to, road.capacity, road.speed_limit, road.curviness, &mut rng);
+            built.lanes = road.lanes.max(1);
+            built.toll = road.toll;
+            built.one_way = road.one_way;
+            built.twin = road.twin.filter(|_| !road.one_way);
+            roads.push(built);
+        }
 
-        let cars: Vec<Car> = {
-        let goals = [NodeID(2), NodeID(3), NodeID(4)];
-        
-            (0..num_cars)
-                .map(|i| Car::new_on_road(Some(CarID(i)), RoadID(i & 2), &mut road_graph, 10.0, goals[(i as usize % goals.len()) as usize]))
-                .collect()
-            };
+        // The other directions that were only written down by ID
+        let mut ids: Vec<RoadID> = roads.iter().map(|road| road.id).collect();
+        let twins: Vec<Road> = roads.iter()
+            .filter_map(|road| road.twin.filter(|twin| !ids.contains(twin)).map(|twin| road.reversed(twin)))
+            .collect();
+        ids.extend(twins.iter().map(|road| road.id));
+        roads.extend(twins);
 
-        for car in cars {
-            road_graph.add_car(car);
+        let mut sorted = ids.clone();
+        sorted.sort_by_key(|id| id.0);
+        if let Some(pair) = sorted.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(GraphError::DuplicateRoad(pair[0]).into());
         }
 
-        Level { road_graph }
-    }
+        let mut road_graph = RoadGraph::new(Some(roads), Some(nodes.into_values().collect()));
+        road_graph.set_density_window(data.sim.density_window);
 
-    pub fn sim_roundabout(device: String, num_cars: i32) -> Level {
+        for control in &data.controls {
+            let (node, control) = match control {
+                ControlData::Signal { node, phases, mode } => (*node, NodeControl::Signal(SignalController::new(phases.clone(), *mode))),
+                ControlData::Priority { node, rule, major } => (*node, NodeControl::Priority(PriorityControl::new(*rule, major.clone()))),
+            };
+            road_graph.set_control(node, control)?;
+        }
 
-        let screen_width = if device == "laptop" {1920.0} else {1200.0};
-        let screen_height = if device == "laptop" {1200.0} else {1920.0};
+        for obstruction in &data.obstructions {
+            let placed = Obstruction {
+                id: obstruction.id,
+                kind: obstruction.kind,
+                at: obstruction.at,
+                lanes: obstruction.lanes.clone(),
+                remaining: obstruction.remaining,
+            };
+            road_graph.place_obstruction(obstruction.road, placed)?;
+        }
 
-        let center = Vec2::new(screen_width / 2.0, screen_height / 2.0);
-        let radius = 200.0;
-    
-        // 4-way roundabout (N, E, S, W)
-        let node_n = Node::new_node(NodeID(0), center + Vec2::new(0.0, -radius * 2.0));
-        let node_e = Node::new_node(NodeID(1), center + Vec2::new(radius * 2.0, 0.0));
-        let node_s = Node::new_node(NodeID(2), center + Vec2::new(0.0, radius * 2.0));
-        let node_w = Node::new_node(NodeID(3), center + Vec2::new(-radius * 2.0, 0.0));
-    
-        // Inner roundabout nodes (clockwise)
-        let node_rn = Node::new_node(NodeID(4), center + Vec2::new(0.0, -radius));
-        let node_re = Node::new_node(NodeID(5), center + Vec2::new(radius, 0.0));
-        let node_rs = Node::new_node(NodeID(6), center + Vec2::new(0.0, radius));
-        let node_rw = Node::new_node(NodeID(7), center + Vec2::new(-radius, 0.0));
-    
-        let nodes = vec![node_n, node_e, node_s, node_w, node_rn, node_re, node_rs, node_rw];
-    
-        let roads = vec![
-            Road::new_road(RoadID(0), node_n, node_rn, 40, 30.0),
-            Road::new_road(RoadID(1), node_rn, node_re, 40, 30.0),
-            Road::new_road(RoadID(2), node_re, node_rs, 40, 30.0),
-            Road::new_road(RoadID(3), node_rs, node_rw, 40, 30.0),
-            Road::new_road(RoadID(4), node_rw, node_rn, 40, 30.0), // loop back
-    
-            Road::new_road(RoadID(5), node_rs, node_s, 40, 30.0),
-            Road::new_road(RoadID(6), node_re, node_e, 40, 30.0),
-            Road::new_road(RoadID(7), node_rw, node_w, 40, 30.0),
-        ];
-    
-        let mut road_graph = RoadGraph::new(Some(roads), Some(nodes));
-    
+        // Cars without an ID get the ones after the highest given
+        let mut next_id = data.cars.iter().filter_map(|car| car.id).map(|id| id.0 + 1).max().unwrap_or(0);
+
+        for car in &data.cars {
+            if let Some(unknown) = std::iter::once(&car.road).chain(&car.path).find(|id| !road_graph.get_roads().contains_key(id)) {
+                return Err(GraphError::UnknownRoad(*unknown).into());
+            }
+
+            let id = car.id.unwrap_or_else(|| {
+                next_id += 1;
+                CarID(next_id - 1)
+            });
+
+            let mut spawned = Car::new_on_road(Some(id), car.road, &mut road_graph, car.speed, car.destination, &mut rng);
+            spawned.idm = car.idm;
+            spawned.compliance = car.compliance;
+            spawned.reroute = car.reroute;
+
+            let road = road_graph.get_roads()[&car.road].read().unwrap();
+            if let Some(lane) = car.lane {
+                spawned.put_in_lane(lane, &road);
+            }
+            if car.along.is_some() || !car.path.is_empty() {
+                spawned.resume(&road, car.along.unwrap_or(spawned.along), car.path.clone(), &road_graph);
+            }
+            drop(road);
+
+            road_graph.add_car(spawned)?;
+        }
+
+        let demand = data.demand.as_ref().map(|demand| {
+            Demand::new(demand.matrix.clone())
+                .with_profile(demand.profile.clone(), demand.start_hour)
+                .with_policy(demand.policy)
+        });
+
+        let issues = match data.sim.validation {
+            ValidationMode::Off => Vec::new(),
+            mode => {
+                let validation = road_graph.validate();
+                if !validation.is_ok() || (mode == ValidationMode::Strict && !validation.issues.is_empty()) {
+                    return Err(LevelError::Invalid(validation.issues));
+                }
+                validation.issues
+            }
+        };
+
+        Ok(Level { road_graph, demand, config, dt: data.sim.dt, validation: data.sim.validation, issues })
+    }
 
-        let fin_nodes = [2, 1, 3];
-        let speed = random_range(3.0..5.0);
+    /// The level as it is now, as a level file would describe it.
+    pub fn to_data(&self) -> LevelData {
+        let graph = &self.road_graph;
 
-        let cars: Vec<Car> = 
-                (0..num_cars)
-                .into_iter()
-                .map(|x| Car::new_on_road(None, RoadID(0), &mut road_graph, speed, NodeID(fin_nodes[x as usize % fin_nodes.len()])))
-                .collect(); // N to E)
-    
+        let mut nodes: Vec<NodeData> = graph.nodes_to_iter()
+            .map(|node| NodeData { id: node.id, x: node.position.x, y: node.position.y })
+            .collect();
+        nodes.sort_by_key(|node| node.id.0);
+
+        let mut roads: Vec<Road> = graph.roads_to_iter().map(|road| road.read().unwrap().clone()).collect();
+        roads.sort_by_key(|road| road.id.0);
+
+        let by_id: HashMap<RoadID, &Road> = roads.iter().map(|road| (road.id, road)).collect();
+        let roads: Vec<RoadData> = roads.iter()
+            .filter(|road| {
+                // The later of two twins goes without saying if it is just the first one reversed
+                let implied = road.twin
+                    .and_then(|twin| by_id.get(&twin))
+                    .is_some_and(|twin| {
+                        twin.id.0 < road.id.0
+                            && (twin.capacity, twin.speed_limit, twin.lanes, twin.toll, twin.curviness) == (road.capacity, road.speed_limit, road.lanes, road.toll, road.curviness)
+                    });
+                !implied
+            })
+            .map(|road| RoadData {
+                id: road.id,
+                from: road.from.id,
+                to: road.to.id,
+                capacity: road.capacity,
+                speed_limit: road.speed_limit,
+                curviness: road.curviness,
+                lanes: road.lanes,
+                toll: road.toll,
+                one_way: road.one_way,
+                twin: road.twin,
+            })
+            .collect();
 
+        let mut controls: Vec<ControlData> = graph.get_controls().iter()
+            .map(|(node, control)| match control {
+                NodeControl::Signal(signal) => ControlData::Signal { node: *node, phases: signal.phases.clone(), mode: signal.mode },
+                NodeControl::Priority(priority) => ControlData::Priority { node: *node, rule: priority.rule, major: priority.major.clone() },
+            })
+            .collect();
+        controls.sort_by_key(|control| match control {
+            ControlData::Signal { node, .. } | ControlData::Priority { node, .. } => node.0,
+        });
+
+        let demand = self.demand.as_ref().map(|demand| DemandData {
+            matrix: demand.matrix.clone(),
+            profile: demand.profile.clone(),
+            start_hour: demand.start_time / 3600.0,
+            policy: demand.policy,
+        });
+
+        let mut obstructions: Vec<ObstructionData> = graph.roads_to_iter()
+            .map(|road| road.read().unwrap())
+            .flat_map(|road| road.obstructions.iter().map(|obstruction| ObstructionData {
+                id: obstruction.id(),
+                road: road.id,
+                kind: obstruction.kind,
+                at: obstruction.at,
+                lanes: obstruction.lanes.clone(),
+                remaining: obstruction.remaining,
+            }).collect::<Vec<_>>())
+            .collect();
+        obstructions.sort_by_key(|obstruction| obstruction.id.0);
+
+        let mut cars: Vec<CarData> = graph.cars_to_iter()
+            .map(|car| car.read().unwrap())
+            .map(|car| CarData {
+                id: Some(car.get_id()),
+                road: car.current_road,
+                destination: car.destination,
+                speed: car.velocity,
+                lane: Some(car.lane),
+                along: Some(car.along),
+                path: car.get_path(),
+                idm: car.idm,
+                compliance: car.compliance,
+                reroute: car.reroute,
+            })
+            .collect();
+        cars.sort_by_key(|car| car.id.map(|id| id.0));
+
+        LevelData {
+            sim: SimSettings { seed: self.config.seed, dt: self.dt, density_window: graph.density_window(), validation: self.validation },
+            nodes,
+            roads,
+            controls,
+            demand,
+            obstructions,
+            cars,
+        }
+    }
 
-        for car in cars {
-            road_graph.add_car(car);
+    /// A simulation of the level, with its tick length and demand.
+    pub fn into_simulation(self) -> Simulation {
+        let mut sim = Simulation::new(self.road_graph, self.dt);
+        if let Some(demand) = self.demand {
+            sim = sim.with_demand(demand, &self.config);
         }
-        Level { road_graph }
+        sim
     }
+
     
     
     
@@ -1,9 +1,26 @@
 pub mod car;
 pub mod road;
 pub mod level;
+pub mod idm;
+pub mod intersection;
+pub mod junction;
+pub mod routing;
+pub mod contraction;
+pub mod demand;
+pub mod obstruction;
+pub mod simulation;
+pub mod validation;
 
 
-pub use car::{Car, CarID};
+pub use car::{Car, CarID, CarState, ReroutePolicy, TripID};
+pub use contraction::ContractionHierarchy;
+pub use demand::{Demand, DemandProfile, DemandStats, OdMatrix, SpawnPolicy};
+pub use idm::IdmParams;
+pub use intersection::{Aspect, Approach, NodeControl, PriorityControl, PriorityRule, SignalController, SignalMode, SignalPhase};
+pub use junction::Connector;
+pub use obstruction::{Obstruction, ObstructionID, ObstructionKind};
 pub use road::*;
+pub use routing::{Bpr, CostModel, Distance, FreeFlowTime, Generalized, ModelKey, PathTree, Route, RouteError};
+pub use simulation::{SimConfig, Simulation, Trip};
+pub use validation::{Issue, Severity, Validation, ValidationMode};
 pub use macroquad::prelude::*;
-
@@ -0,0 +1,52 @@
+//! Things in the way: accidents and roadworks that close lanes of a road for a while.
+//!
+//! An `Obstruction` sits at one point of one direction of a road. Cars in a lane it blocks
+//! treat it as a stopped car and queue behind it, or change lanes round it. A road with every
+//! lane blocked is closed, and route searches leave it out until the obstruction clears.
+
+use serde::{Deserialize, Serialize};
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default, Serialize, Deserialize)]
+pub struct ObstructionID (pub i32);
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ObstructionKind {
+    #[default]
+    Accident,
+    Roadworks,
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+/// Something blocking lanes of a road, see the module docs.
+pub struct Obstruction {
+    pub(crate) id: ObstructionID, // handed out by `RoadGraph::add_obstruction`
+    pub kind: ObstructionKind,
+    pub at: f32,                   // meters along the road from its start
+    pub lanes: Option<Vec<usize>>, // lanes it blocks, `None` for every lane
+    pub remaining: Option<f32>,    // seconds until it clears by itself, `None` to stay until cleared
+}
+
+impl Obstruction {
+
+    /// Closes the whole road `at` meters along it, for `duration` seconds or until cleared.
+    pub fn closure(kind: ObstructionKind, at: f32, duration: Option<f32>) -> Self {
+        Obstruction { id: ObstructionID::default(), kind, at, lanes: None, remaining: duration }
+    }
+
+    /// Closes just `lanes` of the road `at` meters along it.
+    pub fn lanes(kind: ObstructionKind, at: f32, lanes: Vec<usize>, duration: Option<f32>) -> Self {
+        Obstruction { id: ObstructionID::default(), kind, at, lanes: Some(lanes), remaining: duration }
+    }
+
+    pub fn id(&self) -> ObstructionID {
+        self.id
+    }
+
+    pub fn blocks(&self, lane: usize) -> bool {
+        self.lanes.as_ref().is_none_or(|lanes| lanes.contains(&lane))
+    }
+
+}
@@ -1,13 +1,30 @@
-use std::{collections::HashMap, sync::{Arc, RwLock}};
+use std::{collections::HashMap, sync::{Arc, Mutex, RwLock}};
 
 use macroquad::{math::{Vec2}};
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 
-use crate::{road, Car, CarID};
+use crate::intersection::{movements_cross, Approach, NodeControl};
+use crate::junction::{self, Connector};
+use crate::obstruction::{Obstruction, ObstructionID};
+use crate::routing::{a_star, CostModel, Route, RouteCache, RouteError};
+use crate::validation::{self, Validation};
+use crate::{Car, CarID, TripID};
 
 
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+/// How far before the end of a road a signal notices waiting cars
+const DETECTOR_RANGE: f32 = 50.0;
+
+/// Width of one lane, in meters
+pub const LANE_WIDTH: f32 = 6.0;
+
+/// Seconds of history `traffic_density` is smoothed over unless the graph is told otherwise
+const DEFAULT_DENSITY_WINDOW: f32 = 30.0;
+const DEFAULT_EPOCH_THRESHOLD: f32 = 0.05; // change in `traffic_density` that makes cached routes stale
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default, Serialize, Deserialize)]
 pub struct NodeID (pub i32);
 
 impl From<i32> for NodeID {
@@ -22,9 +39,9 @@ impl From<NodeID> for i32 {
     }
 }
 
-impl ToString for NodeID {
-    fn to_string(&self) -> String {
-        self.0.to_string()
+impl std::fmt::Display for NodeID {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
     }
 }
 
@@ -50,37 +67,46 @@ impl Node {
 }
 
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default, Serialize, Deserialize)]
 pub struct RoadID (pub i32);
 #[derive(Clone, Debug)]
 /// A road is actually an edge between two Node objects
 /// in the same way there are edges in a Directed Graph
+///
+/// A two-way road is a pair of these, one per direction, pointing at each other through `twin`.
 pub struct Road {
 
     pub id: RoadID,
     pub from: Node,
     pub to: Node,
-    pub length: f32,
+    pub length: f32, // driven along `points`, in meters
     pub capacity: i32,
     pub vehicles_on: Vec<CarID>,
     pub num_vehicles_on: i32,
     pub speed_limit: f32,
+    pub toll: f32, // charged every time a car drives it
     pub one_way: bool,
-    pub traffic_density: f32,
+    pub twin: Option<RoadID>, // the same road driven the other way, if it is two-way
+    pub lanes: usize,         // in this direction, lane 0 is the one nearest the centre line
+    pub curviness: f32,       // how far the bezier through `points` bows out from a straight line
+    pub traffic_density: f32, // share of `capacity` in use, smoothed by `RoadGraph::update_density`
+    pub vehicles_per_km: f32, // over all lanes, smoothed the same way
+    pub obstructions: Vec<Obstruction>, // in this direction only, kept in order along the road
 
-    pub points: Vec<Vec2>, // this will expose any curves to the rendering function
+    pub points: Vec<Vec2>, // this will expose any curves to the rendering function, change them with `set_points`
+    arc_lengths: Vec<f32>, // distance along the road to each of `points`
+    setbacks: (f32, f32),  // how far `points` stop short of `from` and `to`, in meters
 
 }
 
 /// Helper Function to populate points Vector
-fn make_points(curves: i32, from_node: Node, to_node: Node) -> Vec<Vec2> { // Assuming NodeWithPos has position
+#[allow(dead_code)]
+fn make_points(curves: i32, from_node: Node, to_node: Node, rng: &mut impl Rng) -> Vec<Vec2> { // Assuming NodeWithPos has position
 
     if curves == 0 {
         return Vec::new();
     }
 
-    let mut rng = rand::rng(); // Standard way to get a RNG
-
     let from_pos = from_node.position;
     let to_pos = to_node.position;
 
@@ -112,13 +138,79 @@ fn make_points(curves: i32, from_node: Node, to_node: Node) -> Vec<Vec2> { // As
     intermediate_points
 }
 
+/// Distance along a polyline to each of its points.
+pub(crate) fn cumulative_lengths(points: &[Vec2]) -> Vec<f32> {
+    let mut travelled = 0.0;
+    let mut lengths = Vec::with_capacity(points.len());
+    for (index, point) in points.iter().enumerate() {
+        if index > 0 {
+            travelled += points[index - 1].distance(*point);
+        }
+        lengths.push(travelled);
+    }
+    lengths
+}
+
+/// The segment of a polyline `s` meters along it falls in, as the index of its first point
+/// and how far into it `s` is, from 0.0 to 1.0. `s` is clamped to the polyline.
+pub(crate) fn segment_at(arc_lengths: &[f32], s: f32) -> (usize, f32) {
+    let Some(&length) = arc_lengths.last().filter(|_| arc_lengths.len() >= 2) else { return (0, 0.0) };
+
+    let s = s.clamp(0.0, length);
+    let index = arc_lengths.partition_point(|along| *along <= s).clamp(1, arc_lengths.len() - 1) - 1;
+    let span = arc_lengths[index + 1] - arc_lengths[index];
+    let t = if span > 0.0 { (s - arc_lengths[index]) / span } else { 0.0 };
+    (index, t)
+}
+
+/// The point `s` meters along a polyline, `None` if it has no points.
+pub(crate) fn point_at(points: &[Vec2], arc_lengths: &[f32], s: f32) -> Option<Vec2> {
+    let (index, t) = segment_at(arc_lengths, s);
+    match points.get(index + 1) {
+        Some(next) => Some(points[index].lerp(*next, t)),
+        None => points.first().copied(),
+    }
+}
+
+/// The direction `s` meters along a polyline, as a unit vector. Zero if it has no length.
+pub(crate) fn direction_at(points: &[Vec2], arc_lengths: &[f32], s: f32) -> Vec2 {
+    let (index, _) = segment_at(arc_lengths, s);
+
+    // Zero length segments (the ends of a bezier can bunch up) take the next one that isn't
+    points.windows(2).skip(index)
+        .map(|pair| (pair[1] - pair[0]).normalize_or_zero())
+        .find(|direction| *direction != Vec2::ZERO)
+        .unwrap_or(Vec2::ZERO)
+}
+
+/// How sharply a polyline bends at `points[index]`, as 1 / turning radius. Zero at the ends.
+pub(crate) fn bend_at(points: &[Vec2], index: usize) -> f32 {
+    if index == 0 || index + 1 >= points.len() {
+        return 0.0;
+    }
+
+    let incoming = points[index] - points[index - 1];
+    let outgoing = points[index + 1] - points[index];
+    let span = (incoming.length() + outgoing.length()) / 2.0;
+
+    if span < 0.001 {
+        return 0.0;
+    }
+
+    incoming.angle_between(outgoing).abs() / span
+}
+
 /// Generates 4 control points for a Bezier curve between start and end.
+///
+/// The control points sit a quarter and three quarters of the way along, pushed `curviness`
+/// meters to either side, so the curve bends into a gentle S the same way at any length.
 pub fn generate_bezier(start: Vec2, end: Vec2, curviness: f32) -> [Vec2; 4] {
-    let dir = (end - start).normalize();
+    let chord = end - start;
+    let dir = chord.normalize_or_zero();
     let normal = Vec2::new(-dir.y, dir.x);
 
-    let control1 = start + dir * 0.25 + normal * curviness;
-    let control2 = start + dir * 0.75 - normal * curviness;
+    let control1 = start + chord * 0.25 + normal * curviness;
+    let control2 = start + chord * 0.75 - normal * curviness;
 
     [start, control1, control2, end]
 }
@@ -143,51 +235,57 @@ pub fn sample_bezier(points: [Vec2; 4], steps: usize) -> Vec<Vec2> {
 
 impl Default for Road {
     fn default() -> Self {
-        Road::new_road(RoadID(0), Node::default(), Node::default(), 0, 0.0)
+        Road::new_road(RoadID(0), Node::default(), Node::default(), 0, 0.0, &mut StdRng::seed_from_u64(0))
     }
 }
 
 impl Road {
-    pub fn new_road(id: RoadID, from: Node, to: Node, capacity: i32, speed_limit: f32,) -> Self {
+    pub fn new_road(id: RoadID, from: Node, to: Node, capacity: i32, speed_limit: f32, rng: &mut impl Rng) -> Self {
 
         let num_vehicles_on = 0;
-        let density = num_vehicles_on as f32 / capacity as f32;
-        let length = from.position.distance(to.position);
 
-        let one_way = rand::rng().random_range(1..=1000) < 200;
+        let one_way = rng.random_range(1..=1000) < 200;
 
 
         let control = generate_bezier(from.position, to.position, 80.0);
         
         let points = sample_bezier(control, 50); // adjust step count for smoothness
+        let arc_lengths = cumulative_lengths(&points);
         
         Road {
             id,
             from,
             to,
-            length,
+            length: arc_lengths.last().copied().unwrap_or(0.0),
             capacity,
             vehicles_on: Vec::new(),
             num_vehicles_on,
             speed_limit,
+            toll: 0.0,
             one_way,
+            twin: None,
+            lanes: 1,
+            curviness: 80.0,
             points,
-            traffic_density: density,
+            arc_lengths,
+            setbacks: (0.0, 0.0),
+            traffic_density: 0.0,
+            vehicles_per_km: 0.0,
+            obstructions: Vec::new(),
         }
     }
 
-    pub fn new_road_with_curves(id: RoadID, from: Node, to: Node, capacity: i32, speed_limit: f32, curviness: f32) -> Self {
+    pub fn new_road_with_curves(id: RoadID, from: Node, to: Node, capacity: i32, speed_limit: f32, curviness: f32, rng: &mut impl Rng) -> Self {
 
         let num_vehicles_on = 0;
-        let density = num_vehicles_on as f32 / capacity as f32;
-        let length = from.position.distance(to.position);
 
-        let one_way = rand::rng().random_range(1..=1000) < 200;
+        let one_way = rng.random_range(1..=1000) < 200;
 
 
         let control = generate_bezier(from.position, to.position, curviness);
 
         let points = sample_bezier(control, 50); // adjust step count for smoothness
+        let arc_lengths = cumulative_lengths(&points);
         
 
 
@@ -195,18 +293,220 @@ impl Road {
             id,
             from,
             to,
-            length,
+            length: arc_lengths.last().copied().unwrap_or(0.0),
             capacity,
             vehicles_on: Vec::new(),
             num_vehicles_on,
             speed_limit,
+            toll: 0.0,
             one_way,
+            twin: None,
+            lanes: 1,
+            curviness,
             points,
-            traffic_density: density,
+            arc_lengths,
+            setbacks: (0.0, 0.0),
+            traffic_density: 0.0,
+            vehicles_per_km: 0.0,
+            obstructions: Vec::new(),
+        }
+    }
+
+    /// Share of `capacity` in use right now. A road without a capacity never counts as full.
+    pub fn occupancy(&self) -> f32 {
+        if self.capacity > 0 {
+            self.vehicles_on.len() as f32 / self.capacity as f32
+        } else {
+            0.0
+        }
+    }
+
+    /// Cars per kilometre of road right now, over all lanes.
+    pub fn current_vehicles_per_km(&self) -> f32 {
+        let km = self.length / 1000.0;
+        if km > 0.0 {
+            self.vehicles_on.len() as f32 / km
+        } else {
+            0.0
+        }
+    }
+
+    /// Whether every lane is blocked by an obstruction, so nothing can drive through.
+    pub fn is_closed(&self) -> bool {
+        (0..self.lanes.max(1)).all(|lane| self.obstructions.iter().any(|obstruction| obstruction.blocks(lane)))
+    }
+
+    /// Share of the lanes no obstruction blocks, 1.0 on a clear road and 0.0 on a closed one.
+    pub fn open_share(&self) -> f32 {
+        let lanes = self.lanes.max(1);
+        let open = (0..lanes).filter(|lane| !self.obstructions.iter().any(|obstruction| obstruction.blocks(*lane))).count();
+        open as f32 / lanes as f32
+    }
+
+    /// How far along the road the first obstruction blocking `lane` beyond `from` meters is.
+    pub fn obstruction_ahead(&self, lane: usize, from: f32) -> Option<f32> {
+        self.obstructions.iter()
+            .filter(|obstruction| obstruction.at > from && obstruction.blocks(lane))
+            .map(|obstruction| obstruction.at)
+            .next()
+    }
+
+    /// `speed_limit` is in km/h, this is the same limit in world units (meters) per second.
+    pub fn max_speed(&self) -> f32 {
+        self.speed_limit / 3.6
+    }
+
+    /// Replaces the geometry of the road, keeping `length` and the arc lengths in step with it.
+    pub fn set_points(&mut self, points: Vec<Vec2>) {
+        self.arc_lengths = cumulative_lengths(&points);
+        self.length = self.arc_lengths.last().copied().unwrap_or(0.0);
+        self.points = points;
+    }
+
+    /// Meters from node to node: `length` plus the stretch of the junction at either end the
+    /// road stops short of. Route costs are priced on this, so they cover the turns too and
+    /// never come to less than the straight line between the nodes.
+    pub fn route_length(&self) -> f32 {
+        self.setbacks.0 + self.length + self.setbacks.1
+    }
+
+    /// Distance along the road to each of `points`, starting from zero.
+    pub fn arc_lengths(&self) -> &[f32] {
+        &self.arc_lengths
+    }
+
+    /// The point `s` meters along the road, on `points`. Clamped to either end.
+    pub fn position_at(&self, s: f32) -> Vec2 {
+        point_at(&self.points, &self.arc_lengths, s).unwrap_or(self.from.position)
+    }
+
+    /// The direction of travel `s` meters along the road, as a unit vector. Zero on a road
+    /// with no length.
+    pub fn tangent_at(&self, s: f32) -> Vec2 {
+        direction_at(&self.points, &self.arc_lengths, s)
+    }
+
+    /// How sharply the road bends `s` meters along it, as 1 / turning radius, blended
+    /// between the points either side.
+    pub fn curvature_at(&self, s: f32) -> f32 {
+        let (index, t) = segment_at(&self.arc_lengths, s);
+        self.curvature_at_index(index) * (1.0 - t) + self.curvature_at_index(index + 1) * t
+    }
+
+    /// How sharply the road bends at `points[index]`, as 1 / turning radius.
+    ///
+    /// Zero at either end of the road and on straight stretches.
+    pub fn curvature_at_index(&self, index: usize) -> f32 {
+        bend_at(&self.points, index)
+    }
+
+    /// Sideways distance from `points` to the middle of `lane`, positive to the right of the
+    /// direction of travel.
+    ///
+    /// A two-way road keeps its centre line between the two directions, so its lanes all lie
+    /// on the right. A one-way road is centred on its points.
+    pub fn lane_offset(&self, lane: usize) -> f32 {
+        let first = if self.twin.is_some() { 0.0 } else { -(self.lanes as f32) / 2.0 };
+        (first + lane as f32 + 0.5) * LANE_WIDTH
+    }
+
+    /// `points` moved sideways by `offset`, positive to the right of the direction of travel.
+    pub fn offset_points(&self, offset: f32) -> Vec<Vec2> {
+        let points = &self.points;
+
+        (0..points.len())
+            .map(|index| {
+                let before = points[index.saturating_sub(1)];
+                let after = points[(index + 1).min(points.len() - 1)];
+                let direction = (after - before).normalize_or_zero();
+                points[index] + Vec2::new(-direction.y, direction.x) * offset
+            })
+            .collect()
+    }
+
+    /// The opposite direction of this road: same geometry walked backwards, with its own
+    /// occupancy and density.
+    pub fn reversed(&self, id: RoadID) -> Road {
+        let mut points = self.points.clone();
+        points.reverse();
+        let arc_lengths = cumulative_lengths(&points);
+
+        Road {
+            id,
+            from: self.to,
+            to: self.from,
+            length: arc_lengths.last().copied().unwrap_or(0.0),
+            capacity: self.capacity,
+            vehicles_on: Vec::new(),
+            num_vehicles_on: 0,
+            speed_limit: self.speed_limit,
+            toll: self.toll,
+            one_way: false,
+            twin: Some(self.id),
+            lanes: self.lanes,
+            curviness: self.curviness,
+            points,
+            arc_lengths,
+            setbacks: (self.setbacks.1, self.setbacks.0),
+            traffic_density: 0.0,
+            vehicles_per_km: 0.0,
+            obstructions: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Reasons a RoadGraph refuses an edit.
+pub enum GraphError {
+    DuplicateCar(CarID),
+    DuplicateRoad(RoadID),
+    DuplicateNode(NodeID),
+    UnknownRoad(RoadID),
+    UnknownNode(NodeID),
+    /// `RemovalPolicy::Reject` and there are cars on the road
+    RoadOccupied(RoadID),
+    /// `RemovalPolicy::Reject` and cars are still heading to the node
+    NodeIsDestination(NodeID),
+    /// The road has no lane with this index
+    UnknownLane(RoadID, usize),
+}
+
+impl std::fmt::Display for GraphError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GraphError::DuplicateCar(id) => write!(f, "a car with id {:?} is already in the graph", id),
+            GraphError::DuplicateRoad(id) => write!(f, "a road with id {:?} is already in the graph", id),
+            GraphError::DuplicateNode(id) => write!(f, "a node with id {} is already in the graph", id),
+            GraphError::UnknownRoad(id) => write!(f, "no road with id {:?} in the graph", id),
+            GraphError::UnknownNode(id) => write!(f, "no node with id {} in the graph", id),
+            GraphError::RoadOccupied(id) => write!(f, "road {:?} still has cars on it", id),
+            GraphError::NodeIsDestination(id) => write!(f, "cars are still heading to node {}", id),
+            GraphError::UnknownLane(id, lane) => write!(f, "road {:?} has no lane {}", id, lane),
         }
     }
 }
 
+impl std::error::Error for GraphError {}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+/// What to do with cars that are on a road when it gets removed.
+///
+/// Cars that only had the road planned further along their path always just drop
+/// their path and plan a new one at the end of the road they are on. The trips of cars
+/// taken out are kept for `RoadGraph::take_removed_trips`.
+pub enum RemovalPolicy {
+    /// Move each car to the start of the road its new route to its destination takes from
+    /// the same node, or take it out if there is no such route
+    #[default]
+    Reroute,
+    /// Take the cars out of the simulation
+    Despawn,
+    /// Refuse the edit and leave the graph untouched
+    Reject,
+}
+
+
 #[derive(Debug, Clone)]
 /// A RoadGraph has an array representation of all the roads and nodes inserted into it.
 /// 
@@ -215,24 +515,39 @@ pub struct RoadGraph {
     roads: HashMap<RoadID, Arc<RwLock<Road>>>,
     nodes: HashMap<NodeID, Node>,
     cars:  HashMap<CarID, Arc<RwLock<Car>>>,
-    pub adjacency: HashMap<NodeID, Vec<(NodeID, RoadID)>>,
+    removed_trips: Vec<TripID>, // cut short by edits, since the last `take_removed_trips`
+    pub(crate) adjacency: HashMap<NodeID, Vec<(NodeID, RoadID)>>,
+    controls: HashMap<NodeID, NodeControl>, // signals and the like, lives here since Nodes are copied into every Road
+    density_window: f32, // seconds
+
+    // Cached routes are only good for one cost epoch, which moves on when the graph changes
+    // or some road's density drifts `epoch_threshold` from where it was at the last one
+    route_cache: Arc<Mutex<RouteCache>>,
+    cost_epoch: u64,
+    epoch_threshold: f32,
+    epoch_densities: HashMap<RoadID, f32>,
+    top_speed: f32, // m/s, the fastest limit on any road, which A* aims its search by
+
+    // Monotonic ID allocators, always one past the highest ID the graph has seen
+    next_car_id: i32,
+    next_road_id: i32,
+    next_node_id: i32,
+    next_obstruction_id: i32,
 }
 
 
 impl RoadGraph {
     /// Initialize a RoadGraph
     /// Takes an array of roads, nodes, and cars
-    
+    ///
+    /// Every road that isn't `one_way` and has no `twin` yet gets one, so it can be driven both ways.
     pub fn new(roads: Option<Vec<Road>>, nodes: Option<Vec<Node>>) -> Self {
 
-        let mut road_map: HashMap<RoadID, Arc<RwLock<Road>>> = HashMap::new();
-        let temp_roads = roads.unwrap_or_default();
+        let mut temp_roads = roads.unwrap_or_default();
 
-        for road in temp_roads {
-            road_map.insert(road.id, Arc::new(RwLock::new(road)));
-        }
-
-        let roads = road_map;
+        // HashMap iteration order changes between runs, so insert (and hand out twin IDs)
+        // in ID order to keep whole runs reproducible
+        temp_roads.sort_by_key(|road| road.id.0);
 
 
         let mut node_map: HashMap<NodeID, Node> = HashMap::new();
@@ -244,42 +559,340 @@ impl RoadGraph {
         }
 
         let nodes = node_map;
-    
-
-        let mut adjacency: HashMap<NodeID, Vec<(NodeID, RoadID)>> = HashMap::new();
-
-
-        for (_id, road_arc) in &roads {
-            let road = road_arc.read().unwrap();
-
-            adjacency
-                .entry(road.from.id) // from NodeID
-                .or_default()
-                .push((road.to.id, road.id)); // to (NodeID, using RoadID)
-         }
-        
-        //println!("adj: {:?}", adjacency);
 
 
         let cars: HashMap<CarID, Arc<RwLock<Car>>> = HashMap::new();
 
+        let next_road_id = temp_roads.iter().map(|road| road.id.0 + 1).max().unwrap_or(0);
+        let next_node_id = nodes.keys().map(|id| id.0 + 1).max().unwrap_or(0);
+
 
-        RoadGraph {
-            roads,
+        let mut road_graph = RoadGraph {
+            roads: HashMap::new(),
             nodes,
-            adjacency,
+            adjacency: HashMap::new(),
+            controls: HashMap::new(),
+            density_window: DEFAULT_DENSITY_WINDOW,
+            route_cache: Arc::default(),
+            cost_epoch: 0,
+            epoch_threshold: DEFAULT_EPOCH_THRESHOLD,
+            epoch_densities: HashMap::new(),
+            top_speed: 0.0,
             cars,
+            removed_trips: Vec::new(),
+            next_car_id: 0,
+            next_road_id,
+            next_node_id,
+            next_obstruction_id: 0,
+        };
+
+        let two_way: Vec<RoadID> = temp_roads.iter()
+            .filter(|road| !road.one_way && road.twin.is_none())
+            .map(|road| road.id)
+            .collect();
+
+        for road in temp_roads {
+            road_graph.insert_road(road);
         }
 
+        for id in two_way {
+            road_graph.add_twin(id);
+        }
+
+        let nodes: Vec<NodeID> = road_graph.nodes.keys().copied().collect();
+        road_graph.shape_junctions(&nodes);
+
+        road_graph
+
     }
-    
 
-    pub fn add_road(&mut self, road: Road) {
+    /// Puts a road in the roads map and `adjacency`, no questions asked.
+    fn insert_road(&mut self, road: Road) {
+        let edges = self.adjacency.entry(road.from.id).or_default();
+        edges.push((road.to.id, road.id)); // to (NodeID, using RoadID)
+        edges.sort_by_key(|&(_, road_id)| road_id.0);
+
+        self.epoch_densities.insert(road.id, road.traffic_density);
+        self.top_speed = self.top_speed.max(road.max_speed());
         self.roads.insert(road.id, Arc::new(RwLock::new(road)));
+        self.cost_epoch += 1;
+    }
+
+    /// Creates the opposite direction of road `id` and links the two together.
+    fn add_twin(&mut self, id: RoadID) -> RoadID {
+        let twin_id = self.next_road_id();
+
+        let mut road = self.roads.get(&id).unwrap().write().unwrap();
+        let twin = road.reversed(twin_id);
+        road.twin = Some(twin_id);
+        road.one_way = false;
+        drop(road);
+
+        self.insert_road(twin);
+        twin_id
+    }
+
+    /// The fastest speed limit on any road in the graph, in m/s.
+    ///
+    /// Kept up to date by the graph's own edits, a limit written straight into a road isn't
+    /// seen until the next of them. Use `set_road_limits`.
+    pub fn top_speed(&self) -> f32 {
+        self.top_speed
+    }
+
+    /// Works `top_speed` out again, for when the fastest road may have gone or slowed down.
+    fn refresh_top_speed(&mut self) {
+        self.top_speed = self.roads.values().map(|road| road.read().unwrap().max_speed()).fold(0.0, f32::max);
+    }
+
+    /// Hands out a RoadID no road in this graph has used yet.
+    pub fn next_road_id(&mut self) -> RoadID {
+        let id = RoadID(self.next_road_id);
+        self.next_road_id += 1;
+        id
+    }
+
+    /// Hands out a NodeID no node in this graph has used yet.
+    pub fn next_node_id(&mut self) -> NodeID {
+        let id = NodeID(self.next_node_id);
+        self.next_node_id += 1;
+        id
+    }
+
+    /// Hands out a CarID no car in this graph has used yet.
+    pub fn next_car_id(&mut self) -> CarID {
+        let id = CarID(self.next_car_id);
+        self.next_car_id += 1;
+        id
+    }
+
+    /// Adds a road between two nodes already in the graph and links it into `adjacency`.
+    ///
+    /// A road that isn't `one_way` gets a twin for the other direction, read it back from `twin`.
+    pub fn add_road(&mut self, road: Road) -> Result<(), GraphError> {
+        if self.roads.contains_key(&road.id) {
+            return Err(GraphError::DuplicateRoad(road.id));
+        }
+
+        for node in [road.from.id, road.to.id] {
+            if !self.nodes.contains_key(&node) {
+                return Err(GraphError::UnknownNode(node));
+            }
+        }
+
+        let id = road.id;
+        let ends = [road.from.id, road.to.id];
+        let needs_twin = !road.one_way && road.twin.is_none();
+
+        self.next_road_id = self.next_road_id.max(id.0 + 1);
+        self.insert_road(road);
+
+        if needs_twin {
+            self.add_twin(id);
+        }
+
+        self.shape_junctions(&ends);
+        Ok(())
     }
 
-    pub fn remove_road(&mut self, id: RoadID) {
-        self.roads.remove(&id);
+    /// Removes a road (both directions of it if it is two-way), handling the cars on it
+    /// according to `policy`.
+    pub fn remove_road(&mut self, id: RoadID, policy: RemovalPolicy) -> Result<Road, GraphError> {
+        let twin = self.roads.get(&id).ok_or(GraphError::UnknownRoad(id))?.read().unwrap().twin;
+
+        let mut ids = vec![id];
+        ids.extend(twin);
+
+        let mut removed = self.remove_roads(&ids, &[], policy)?;
+        Ok(removed.remove(0))
+    }
+
+    /// Makes a road one-way (dropping its twin, with `policy` for the cars on it) or two-way
+    /// (adding a twin).
+    pub fn set_one_way(&mut self, id: RoadID, one_way: bool, policy: RemovalPolicy) -> Result<(), GraphError> {
+        let twin = self.roads.get(&id).ok_or(GraphError::UnknownRoad(id))?.read().unwrap().twin;
+
+        match (one_way, twin) {
+            (true, Some(twin)) => {
+                self.remove_roads(&[twin], &[], policy)?;
+            }
+            (false, None) => {
+                self.add_twin(id);
+            }
+            _ => {}
+        }
+
+        self.roads.get(&id).unwrap().write().unwrap().one_way = one_way;
+        Ok(())
+    }
+
+    /// Sets the capacity and speed limit (km/h) of a road, both directions of it if it is
+    /// two-way. Drivers see the new costs from the next cost epoch.
+    pub fn set_road_limits(&mut self, id: RoadID, capacity: i32, speed_limit: f32) -> Result<(), GraphError> {
+        for road_id in self.both_directions(id)? {
+            let mut road = self.roads.get(&road_id).unwrap().write().unwrap();
+            road.capacity = capacity.max(0);
+            road.speed_limit = speed_limit.max(0.0);
+        }
+
+        self.refresh_top_speed();
+        self.cost_epoch += 1;
+        Ok(())
+    }
+
+    /// Reshapes a road (and its twin) to the bezier `generate_bezier` makes for `curviness`.
+    ///
+    /// Its length changes with it, so cached routes are dropped and drivers price the road
+    /// again. Cars already on it are carried over to the new shape by `reshape`.
+    pub fn set_curviness(&mut self, id: RoadID, curviness: f32) -> Result<(), GraphError> {
+        for road_id in self.both_directions(id)? {
+            self.roads.get(&road_id).unwrap().write().unwrap().curviness = curviness;
+        }
+
+        self.reshape(id);
+        self.cost_epoch += 1;
+        Ok(())
+    }
+
+    /// Reshapes every road that starts or ends at one of `nodes`, for when a junction there
+    /// may have grown or shrunk.
+    fn shape_junctions(&mut self, nodes: &[NodeID]) {
+        let mut ids: Vec<RoadID> = self.roads.values()
+            .map(|road| road.read().unwrap())
+            .filter(|road| nodes.contains(&road.from.id) || nodes.contains(&road.to.id))
+            .map(|road| road.id)
+            .collect();
+        ids.sort_by_key(|id| id.0);
+
+        let mut done = Vec::new();
+        for id in ids {
+            if done.contains(&id) {
+                continue;
+            }
+            done.extend(self.both_directions(id).unwrap());
+            self.reshape(id);
+        }
+    }
+
+    /// Regenerates the points of road `id` (and its twin) from its curviness, stopping short of
+    /// junctions at either end. See the `junction` module.
+    ///
+    /// Cars on it stay the same share of the way along, in the same order, and keep their paths.
+    fn reshape(&mut self, id: RoadID) {
+        let road = self.roads.get(&id).unwrap().read().unwrap();
+        let (from, to, curviness) = (road.from, road.to, road.curviness);
+        let directions: Vec<RoadID> = std::iter::once(id).chain(road.twin).collect();
+        drop(road);
+
+        let setbacks = (junction::setback(self, from.id), junction::setback(self, to.id));
+        let (forward, setbacks) = junction::shape_between(from.position, to.position, setbacks, curviness);
+
+        for road_id in directions {
+            let mut road = self.roads.get(&road_id).unwrap().write().unwrap();
+
+            let old_length = road.length;
+            let shares: Vec<(CarID, f32)> = road.vehicles_on.iter()
+                .filter_map(|car_id| self.cars.get(car_id).map(|car| (*car_id, car.read().unwrap().along)))
+                .map(|(car_id, along)| (car_id, if old_length > 0.0 { along / old_length } else { 0.0 }))
+                .collect();
+
+            // The twin runs the other way, so its curve is this one's walked backwards
+            let mut points = forward.clone();
+            road.setbacks = setbacks;
+            if road_id != id {
+                points.reverse();
+                road.setbacks = (setbacks.1, setbacks.0);
+            }
+            road.set_points(points);
+
+            let new_length = road.length;
+            for (car_id, share) in shares {
+                self.cars.get(&car_id).unwrap().write().unwrap().place_at_distance(&road, share * new_length);
+            }
+            if old_length > 0.0 {
+                road.obstructions.iter_mut().for_each(|obstruction| obstruction.at *= new_length / old_length);
+            }
+        }
+    }
+
+    /// Drops `obstruction` onto road `id`, its position clamped to the road. Returns the ID to
+    /// clear it with.
+    ///
+    /// Cars already past it drive on. Drivers planning to use a road it closes completely
+    /// drop their path and plan again, keeping the next road if they are already turning onto it.
+    pub fn add_obstruction(&mut self, id: RoadID, mut obstruction: Obstruction) -> Result<ObstructionID, GraphError> {
+        obstruction.id = ObstructionID(self.next_obstruction_id);
+        self.place_obstruction(id, obstruction)
+    }
+
+    /// `add_obstruction`, keeping the ID `obstruction` already has. Used to put a saved one back.
+    pub(crate) fn place_obstruction(&mut self, id: RoadID, mut obstruction: Obstruction) -> Result<ObstructionID, GraphError> {
+        let road = self.roads.get(&id).ok_or(GraphError::UnknownRoad(id))?;
+        let mut road = road.write().unwrap();
+
+        if let Some(&lane) = obstruction.lanes.iter().flatten().find(|lane| **lane >= road.lanes.max(1)) {
+            return Err(GraphError::UnknownLane(id, lane));
+        }
+
+        let obstruction_id = obstruction.id;
+        obstruction.at = obstruction.at.clamp(0.0, road.length);
+        self.next_obstruction_id = self.next_obstruction_id.max(obstruction_id.0 + 1);
+
+        let place = road.obstructions.partition_point(|other| other.at <= obstruction.at);
+        road.obstructions.insert(place, obstruction);
+        let closed = road.is_closed();
+        drop(road);
+
+        if closed {
+            for car in self.cars.values() {
+                let mut car = car.write().unwrap();
+                if car.get_path().contains(&id) {
+                    let current = self.roads[&car.current_road].read().unwrap();
+                    car.clear_path_past_turn(&current);
+                }
+            }
+        }
+
+        self.cost_epoch += 1;
+        Ok(obstruction_id)
+    }
+
+    /// Takes an obstruction off its road, `None` if it has already cleared.
+    pub fn clear_obstruction(&mut self, id: ObstructionID) -> Option<Obstruction> {
+        for road in self.roads.values() {
+            let mut road = road.write().unwrap();
+            if let Some(place) = road.obstructions.iter().position(|obstruction| obstruction.id == id) {
+                self.cost_epoch += 1;
+                return Some(road.obstructions.remove(place));
+            }
+        }
+        None
+    }
+
+    /// Counts timed obstructions down by `dt` seconds and clears the ones that run out.
+    pub fn update_obstructions(&mut self, dt: f32) {
+        for road in self.roads.values() {
+            let mut road = road.write().unwrap();
+            if road.obstructions.is_empty() {
+                continue;
+            }
+
+            for remaining in road.obstructions.iter_mut().filter_map(|obstruction| obstruction.remaining.as_mut()) {
+                *remaining -= dt;
+            }
+
+            let before = road.obstructions.len();
+            road.obstructions.retain(|obstruction| obstruction.remaining.is_none_or(|remaining| remaining > 0.0));
+            if road.obstructions.len() != before {
+                self.cost_epoch += 1;
+            }
+        }
+    }
+
+    /// `id` and its twin, if it has one.
+    fn both_directions(&self, id: RoadID) -> Result<Vec<RoadID>, GraphError> {
+        let twin = self.roads.get(&id).ok_or(GraphError::UnknownRoad(id))?.read().unwrap().twin;
+        Ok(std::iter::once(id).chain(twin).collect())
     }
 
     pub fn roads_to_iter(&self) -> impl Iterator<Item = &Arc<RwLock<Road>>> {
@@ -290,12 +903,188 @@ impl RoadGraph {
         &self.roads
     }
 
-    pub fn add_node(&mut self, node: Node) {
+    pub fn add_node(&mut self, node: Node) -> Result<(), GraphError> {
+        if self.nodes.contains_key(&node.id) {
+            return Err(GraphError::DuplicateNode(node.id));
+        }
+
+        self.next_node_id = self.next_node_id.max(node.id.0 + 1);
         self.nodes.insert(node.id, node);
+        Ok(())
+    }
+
+    /// Removes a node along with every road that starts or ends at it.
+    ///
+    /// `policy` applies to cars on those roads and to cars whose destination is the node.
+    /// Under `Reroute` the latter have nowhere left to go, so they are despawned.
+    pub fn remove_node(&mut self, id: NodeID, policy: RemovalPolicy) -> Result<Node, GraphError> {
+        if !self.nodes.contains_key(&id) {
+            return Err(GraphError::UnknownNode(id));
+        }
+
+        let mut incident: Vec<RoadID> = self.roads.values()
+            .map(|road| road.read().unwrap())
+            .filter(|road| road.from.id == id || road.to.id == id)
+            .map(|road| road.id)
+            .collect();
+        incident.sort_by_key(|road_id| road_id.0);
+
+        let mut headed_here: Vec<CarID> = self.cars.iter()
+            .filter(|(_, car)| car.read().unwrap().destination == id)
+            .map(|(car_id, _)| *car_id)
+            .collect();
+        headed_here.sort_by_key(|car_id| car_id.0);
+
+        if policy == RemovalPolicy::Reject && !headed_here.is_empty() {
+            return Err(GraphError::NodeIsDestination(id));
+        }
+
+        self.remove_roads(&incident, &headed_here, policy)?;
+
+        self.adjacency.remove(&id);
+        self.controls.remove(&id);
+        Ok(self.nodes.remove(&id).unwrap())
+    }
+
+    /// Removes `ids` (and the cars in `doomed`) in one go, so a rejected edit leaves
+    /// the graph exactly as it was.
+    fn remove_roads(&mut self, ids: &[RoadID], doomed: &[CarID], policy: RemovalPolicy) -> Result<Vec<Road>, GraphError> {
+
+        // Refuse before touching anything
+        if policy == RemovalPolicy::Reject
+            && let Some(id) = ids.iter().find(|id| !self.roads[id].read().unwrap().vehicles_on.is_empty()) {
+                return Err(GraphError::RoadOccupied(*id));
+            }
+
+        for car_id in doomed {
+            self.despawn(*car_id);
+        }
+
+        // Cars on the removed roads, front first, with the node they were driving away from
+        let mut displaced: Vec<(CarID, NodeID)> = Vec::new();
+
+        let mut removed = Vec::with_capacity(ids.len());
+
+        for id in ids {
+            let road = self.roads.remove(id).unwrap();
+            let road = Arc::try_unwrap(road).map(|lock| lock.into_inner().unwrap()).unwrap_or_else(|arc| arc.read().unwrap().clone());
+
+            if let Some(edges) = self.adjacency.get_mut(&road.from.id) {
+                edges.retain(|&(_, road_id)| road_id != *id);
+            }
+            self.epoch_densities.remove(id);
+            self.cost_epoch += 1;
+
+            // The other direction, if it survives, is now a one-way road
+            if let Some(twin) = road.twin.and_then(|twin| self.roads.get(&twin)) {
+                let mut twin = twin.write().unwrap();
+                twin.twin = None;
+                twin.one_way = true;
+            }
+
+            displaced.extend(road.vehicles_on.iter().map(|car_id| (*car_id, road.from.id)));
+            removed.push(road);
+        }
+
+        self.refresh_top_speed();
+
+        // Anyone planning to drive over a removed road has to plan again
+        for car in self.cars.values() {
+            let mut car = car.write().unwrap();
+            if car.get_path().iter().any(|road_id| ids.contains(road_id)) {
+                car.clear_path();
+            }
+        }
+
+        let ends: Vec<NodeID> = removed.iter().flat_map(|road| [road.from.id, road.to.id]).collect();
+        self.shape_junctions(&ends);
+
+        // With the roads gone, each car starts again down the first road of its new route
+        let mut detours: Vec<(RoadID, Vec<CarID>)> = Vec::new();
+        for (car_id, from) in displaced {
+            let Some(car) = self.cars.get(&car_id).cloned() else { continue };
+
+            let detour = match policy {
+                RemovalPolicy::Reroute => {
+                    let car = car.read().unwrap();
+                    self.route(from, car.destination, car.cost_model.as_ref()).ok()
+                        .and_then(|route| route.roads.first().copied())
+                }
+                _ => None,
+            };
+
+            match detour {
+                Some(detour) => match detours.iter_mut().find(|(road_id, _)| *road_id == detour) {
+                    Some((_, cars)) => cars.push(car_id),
+                    None => detours.push((detour, vec![car_id])),
+                },
+                None => self.despawn(car_id),
+            }
+        }
+
+        for (detour, cars) in detours {
+            self.queue_at_start(detour, &cars);
+        }
+
+        Ok(removed)
     }
 
-    pub fn remove_node(&mut self, id: NodeID) {
-        self.nodes.remove(&id);
+    /// Lines `cars` up, front first, from the start of `road_id` behind whoever is on it
+    /// already, each its stopping gap behind the one ahead. Cars that don't fit before the
+    /// last car already there, or on the road at all, are despawned, front ones first.
+    fn queue_at_start(&mut self, road_id: RoadID, cars: &[CarID]) {
+        let road = self.roads.get(&road_id).unwrap().clone();
+
+        // From the back of the queue forward, until someone doesn't fit
+        let placed: Vec<(CarID, f32)> = {
+            let road = road.read().unwrap();
+
+            // The back of the last car on the road, as far forward as the queue can reach
+            let room = road.vehicles_on.last()
+                .and_then(|id| self.cars.get(id))
+                .map(|car| {
+                    let car = car.read().unwrap();
+                    car.along - car.get_height()
+                })
+                .unwrap_or(f32::INFINITY);
+
+            let mut placed = Vec::new();
+            let mut behind = None; // where the car just placed is, and the gap it keeps
+            for car_id in cars.iter().rev() {
+                let car = self.cars.get(car_id).unwrap().read().unwrap();
+                let along = behind.map_or(0.0, |(back, gap)| back + gap + car.get_height());
+                if along + car.idm.min_gap > room || along > road.length {
+                    break;
+                }
+
+                placed.push((*car_id, along));
+                behind = Some((along, car.idm.min_gap));
+            }
+            placed
+        };
+
+        for car_id in &cars[..cars.len() - placed.len()] {
+            self.despawn(*car_id);
+        }
+
+        let mut road = road.write().unwrap();
+        for (car_id, along) in placed {
+            let mut car = self.cars.get(&car_id).unwrap().write().unwrap();
+            car.place_on_road(&road);
+            car.place_at_distance(&road, along);
+            drop(car);
+            self.insert_in_order(&mut road, car_id, along);
+        }
+    }
+
+    /// Puts `id` into `road.vehicles_on` behind every car further along than `along`, which
+    /// keeps it ordered front to back, how cars find the one they follow.
+    fn insert_in_order(&self, road: &mut Road, id: CarID, along: f32) {
+        let place = road.vehicles_on.iter()
+            .position(|other| self.cars.get(other).is_some_and(|other| other.read().unwrap().along < along))
+            .unwrap_or(road.vehicles_on.len());
+        road.vehicles_on.insert(place, id);
+        road.num_vehicles_on += 1;
     }
 
     pub fn nodes_to_iter(&self) -> impl Iterator<Item = &Node> {
@@ -306,12 +1095,47 @@ impl RoadGraph {
         &self.nodes
     }
 
-    pub fn add_car(&mut self, car: Car) {
-        self.cars.insert(car.get_id(), Arc::new(RwLock::new(car)));
+    /// Adds a car and registers it on the road it was spawned on.
+    pub fn add_car(&mut self, car: Car) -> Result<(), GraphError> {
+        let id = car.get_id();
+
+        if self.cars.contains_key(&id) {
+            return Err(GraphError::DuplicateCar(id));
+        }
+
+        let road = self.roads.get(&car.current_road).ok_or(GraphError::UnknownRoad(car.current_road))?;
+        self.insert_in_order(&mut road.write().unwrap(), id, car.along);
+
+        self.next_car_id = self.next_car_id.max(id.0 + 1);
+        self.cars.insert(id, Arc::new(RwLock::new(car)));
+        Ok(())
+    }
+
+    /// Takes out a car an edit has left with nowhere to go, noting its trip as removed.
+    fn despawn(&mut self, id: CarID) {
+        if let Some(car) = self.cars.get(&id) {
+            self.removed_trips.push(car.read().unwrap().trip);
+            self.remove_car(id);
+        }
+    }
+
+    /// Trips whose cars edits have taken out of the graph since the last call, in the order
+    /// they went. A `Simulation` takes them every tick and counts them in
+    /// `Simulation::trips_removed`, so only call this on a graph that isn't in one.
+    pub fn take_removed_trips(&mut self) -> Vec<TripID> {
+        std::mem::take(&mut self.removed_trips)
     }
 
+    /// Removes a car from the graph and from the road it is on.
     pub fn remove_car(&mut self, id: CarID) {
-        self.cars.remove(&id);
+        if let Some(car) = self.cars.remove(&id) {
+            let road_id = car.read().unwrap().current_road;
+            if let Some(road) = self.roads.get(&road_id) {
+                let mut road = road.write().unwrap();
+                road.vehicles_on.retain(|x| *x != id);
+                road.num_vehicles_on = road.vehicles_on.len() as i32;
+            }
+        }
     }
 
     pub fn cars_to_iter(&self) -> impl Iterator<Item = &Arc<RwLock<Car>>> {
@@ -327,6 +1151,334 @@ impl RoadGraph {
         self.adjacency.clone()
     }
 
+    /// Puts a signal (or other control) on a node, replacing whatever it had.
+    pub fn set_control(&mut self, node: NodeID, control: NodeControl) -> Result<(), GraphError> {
+        if !self.nodes.contains_key(&node) {
+            return Err(GraphError::UnknownNode(node));
+        }
+
+        self.controls.insert(node, control);
+        Ok(())
+    }
+
+    pub fn remove_control(&mut self, node: NodeID) -> Option<NodeControl> {
+        self.controls.remove(&node)
+    }
+
+    pub fn get_control(&self, node: NodeID) -> Option<&NodeControl> {
+        self.controls.get(&node)
+    }
+
+    pub fn get_controls(&self) -> &HashMap<NodeID, NodeControl> {
+        &self.controls
+    }
+
+    /// Roads that end at `node`, in ID order.
+    pub fn incoming_roads(&self, node: NodeID) -> Vec<RoadID> {
+        let mut roads: Vec<RoadID> = self.roads.values()
+            .map(|road| road.read().unwrap())
+            .filter(|road| road.to.id == node)
+            .map(|road| road.id)
+            .collect();
+        roads.sort_by_key(|road| road.0);
+        roads
+    }
+
+    /// Every (road, next road) pair a car can drive from the end of road `from`.
+    pub fn movements_from(&self, from: RoadID) -> Vec<(RoadID, RoadID)> {
+        let Some(road) = self.roads.get(&from) else { return Vec::new() };
+        let node = road.read().unwrap().to.id;
+
+        self.adjacency.get(&node)
+            .map(|edges| edges.iter().map(|&(_, to)| (from, to)).collect())
+            .unwrap_or_default()
+    }
+
+    /// The curve of every turn through `node`, in the order of `incoming_roads` and then
+    /// `movements_from`. Turns between roads that meet right at the node have none.
+    pub fn connectors(&self, node: NodeID) -> Vec<Connector> {
+        self.incoming_roads(node).into_iter()
+            .flat_map(|from| self.movements_from(from))
+            .filter_map(|(from, to)| {
+                let from = self.roads.get(&from)?.read().unwrap();
+                let to = self.roads.get(&to)?.read().unwrap();
+                Connector::between(&from, &to)
+            })
+            .collect()
+    }
+
+    /// Which lanes of road `from` lead into road `to`, each with the lane of `to` it ends up in.
+    ///
+    /// The roads leaving the node are ordered from the sharpest left turn to the sharpest right
+    /// and shared out over the lanes in that order, so left turns go from the inner lanes and
+    /// right turns from the outer ones. Every lane gets at least one road and every road at
+    /// least one lane.
+    pub fn lane_connections(&self, from: RoadID, to: RoadID) -> Vec<(usize, usize)> {
+        let (Some(from_road), Some(to_road)) = (self.roads.get(&from), self.roads.get(&to)) else { return Vec::new() };
+        let from_road = from_road.read().unwrap();
+        let to_lanes = to_road.read().unwrap().lanes.max(1);
+        let lanes = from_road.lanes.max(1);
+
+        let arriving = match from_road.points.as_slice() {
+            [.., before, end] => *end - *before,
+            _ => return Vec::new(),
+        };
+
+        let mut exits: Vec<(f32, RoadID)> = self.adjacency.get(&from_road.to.id).into_iter().flatten()
+            .filter_map(|&(_, id)| {
+                let road = self.roads.get(&id)?.read().unwrap();
+                let turn = if Some(id) == from_road.twin {
+                    -std::f32::consts::PI // a U-turn is as far left as it gets
+                } else {
+                    match road.points.as_slice() {
+                        [start, after, ..] => arriving.angle_between(*after - *start),
+                        _ => 0.0,
+                    }
+                };
+                Some((turn, id))
+            })
+            .collect();
+        exits.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.0.cmp(&b.1.0)));
+
+        let Some(movement) = exits.iter().position(|&(_, id)| id == to) else { return Vec::new() };
+        let movements = exits.len();
+
+        (0..lanes)
+            .filter(|lane| movement * lanes < (lane + 1) * movements && lane * movements < (movement + 1) * lanes)
+            .map(|lane| (lane, (lane * to_lanes / lanes).min(to_lanes - 1)))
+            .collect()
+    }
+
+    /// Roads a car in `lane` of road `from` can turn into.
+    pub fn lane_exits(&self, from: RoadID, lane: usize) -> Vec<RoadID> {
+        self.movements_from(from).into_iter()
+            .map(|(_, to)| to)
+            .filter(|to| self.lane_connections(from, *to).iter().any(|&(from_lane, _)| from_lane == lane))
+            .collect()
+    }
+
+    /// The lane of road `to` a car in `lane` of road `from` turns into.
+    ///
+    /// Cars turning from a lane that doesn't lead to `to` keep the same position across the road.
+    pub fn entry_lane(&self, from: RoadID, lane: usize, to: RoadID) -> usize {
+        if let Some(&(_, entry)) = self.lane_connections(from, to).iter().find(|&&(from_lane, _)| from_lane == lane) {
+            return entry;
+        }
+
+        let lanes_of = |id: RoadID| self.roads.get(&id).map_or(1, |road| road.read().unwrap().lanes.max(1));
+        (lane * lanes_of(to) / lanes_of(from)).min(lanes_of(to) - 1)
+    }
+
+    /// Whether cars making movements `a` and `b` through the same node (each an incoming and
+    /// an outgoing road) would cross or merge into each other.
+    pub fn movements_conflict(&self, a: (RoadID, RoadID), b: (RoadID, RoadID)) -> bool {
+        if a.0 == b.0 {
+            return false; // same lane, they just queue
+        }
+        if a.1 == b.1 {
+            return true;
+        }
+
+        let bearings = [(a.0, true), (a.1, false), (b.0, true), (b.1, false)]
+            .map(|(road, incoming)| self.bearing_at_node(road, incoming));
+        match bearings {
+            [Some(a_in), Some(a_out), Some(b_in), Some(b_out)] => movements_cross(a_in, a_out, b_in, b_out),
+            _ => false,
+        }
+    }
+
+    /// Direction (radians) from the node a road touches towards the rest of the road.
+    /// `incoming` picks the node at its end, otherwise the node at its start.
+    fn bearing_at_node(&self, road: RoadID, incoming: bool) -> Option<f32> {
+        let road = self.roads.get(&road)?.read().unwrap();
+        let (node, next) = if incoming {
+            (road.to.position, *road.points.iter().rev().nth(1)?)
+        } else {
+            (road.from.position, *road.points.get(1)?)
+        };
+
+        let direction = next - node;
+        Some(direction.y.atan2(direction.x))
+    }
+
+    /// The first car still driving in each lane of each road into `node`, with where it goes
+    /// next. Cars without a planned next road aren't going through the node and are left out.
+    pub fn approaches(&self, node: NodeID) -> Vec<Approach> {
+        let mut approaches = Vec::new();
+
+        for id in self.incoming_roads(node) {
+            let road = self.roads.get(&id).unwrap().read().unwrap();
+            let length = road.length;
+
+            let mut lanes_seen = Vec::new();
+            let cars = road.vehicles_on.iter()
+                .filter_map(|car| self.cars.get(car))
+                .map(|car| car.read().unwrap())
+                .filter(|car| !car.has_arrived(&road));
+
+            for car in cars {
+                if lanes_seen.contains(&car.lane) {
+                    continue;
+                }
+                lanes_seen.push(car.lane);
+
+                let Some(&next_road) = car.get_path().first() else { continue };
+                approaches.push(Approach {
+                    road: id,
+                    next_road,
+                    car: car.get_id(),
+                    distance: length - car.along,
+                    speed: car.velocity,
+                    braking: car.idm.comfortable_braking,
+                });
+            }
+        }
+
+        approaches
+    }
+
+    /// Advances every control by `dt`. Called once per tick, before cars move.
+    pub fn update_controls(&mut self, dt: f32) {
+        let mut controls = std::mem::take(&mut self.controls);
+
+        for (node, control) in controls.iter_mut() {
+            match control {
+                NodeControl::Signal(signal) => {
+                    signal.update(dt, |phase| phase.incoming_roads().iter().any(|road| self.has_waiting_car(*road)));
+                }
+                NodeControl::Priority(priority) => {
+                    priority.update(dt, &self.approaches(*node), |a, b| self.movements_conflict(a, b));
+                }
+            }
+        }
+
+        self.controls = controls;
+    }
+
+    /// How many seconds of history `Road::traffic_density` is averaged over.
+    pub fn density_window(&self) -> f32 {
+        self.density_window
+    }
+
+    /// Sets the smoothing window for densities, zero makes them follow every tick exactly.
+    pub fn set_density_window(&mut self, seconds: f32) {
+        self.density_window = seconds.max(0.0);
+    }
+
+    /// Refreshes every road's `num_vehicles_on`, `traffic_density` and `vehicles_per_km` from
+    /// who is on it, smoothed over `density_window`. Called once per tick, after cars move.
+    ///
+    /// Starts a new cost epoch when any road has drifted `epoch_threshold` since the last one.
+    pub fn update_density(&mut self, dt: f32) {
+        // An exponential moving average, weighted so that `density_window` seconds ago counts
+        // for about a third of now
+        let weight = if self.density_window > 0.0 { 1.0 - (-dt / self.density_window).exp() } else { 1.0 };
+
+        for road in self.roads.values() {
+            let mut road = road.write().unwrap();
+            road.num_vehicles_on = road.vehicles_on.len() as i32;
+
+            let occupancy = road.occupancy();
+            let per_km = road.current_vehicles_per_km();
+            road.traffic_density += (occupancy - road.traffic_density) * weight;
+            road.vehicles_per_km += (per_km - road.vehicles_per_km) * weight;
+        }
+
+        let drifted = self.roads.iter().any(|(id, road)| {
+            let then = self.epoch_densities.get(id).copied().unwrap_or(0.0);
+            (road.read().unwrap().traffic_density - then).abs() > self.epoch_threshold
+        });
+        if drifted {
+            self.invalidate_routes();
+        }
+    }
+
+    /// The cheapest route from `start` to `goal` as `model` prices it, from the route cache
+    /// when this cost epoch has already searched it.
+    pub fn route(&self, start: NodeID, goal: NodeID, model: &dyn CostModel) -> Result<Route, RouteError> {
+        let key = (start, goal, model.cache_key());
+
+        if let Some(found) = self.route_cache.lock().unwrap().get(self.cost_epoch, &key) {
+            return found;
+        }
+
+        let found = a_star(start, goal, self, model);
+        self.route_cache.lock().unwrap().insert(self.cost_epoch, key, found.clone());
+        found
+    }
+
+    /// Looks the graph over for anything broken or suspicious: roads that don't match their
+    /// nodes or have no shape, nodes without roads, roads without capacity, and nodes or car
+    /// destinations no route leads to. See the `validation` module.
+    pub fn validate(&self) -> Validation {
+        validation::validate(self)
+    }
+
+    /// How many routes are cached for this epoch.
+    pub fn cached_routes(&self) -> usize {
+        self.route_cache.lock().unwrap().len()
+    }
+
+    /// Route lookups answered from the cache and lookups that had to search, since the graph
+    /// was made.
+    pub fn route_cache_stats(&self) -> (u64, u64) {
+        let cache = self.route_cache.lock().unwrap();
+        (cache.hits(), cache.misses())
+    }
+
+    /// Bumped whenever cached routes go stale.
+    pub fn cost_epoch(&self) -> u64 {
+        self.cost_epoch
+    }
+
+    pub fn epoch_threshold(&self) -> f32 {
+        self.epoch_threshold
+    }
+
+    /// Sets how far a road's density may drift before cached routes are dropped, zero drops
+    /// them on any change at all.
+    pub fn set_epoch_threshold(&mut self, threshold: f32) {
+        self.epoch_threshold = threshold.max(0.0);
+    }
+
+    /// Drops every cached route. The graph does this itself when roads come and go or densities
+    /// drift, call it after changing anything else a cost model looks at (tolls, speed limits).
+    pub fn invalidate_routes(&mut self) {
+        self.cost_epoch += 1;
+        self.epoch_densities = self.roads.iter()
+            .map(|(id, road)| (*id, road.read().unwrap().traffic_density))
+            .collect();
+    }
+
+    /// Puts every road's `vehicles_on` back in front-to-back order.
+    ///
+    /// Cars in different lanes overtake each other, call this once every car has moved.
+    pub fn sort_vehicles(&self) {
+        for road in self.roads.values() {
+            let mut road = road.write().unwrap();
+            let mut order: Vec<(f32, CarID)> = road.vehicles_on.iter()
+                .filter_map(|id| self.cars.get(id).map(|car| (car.read().unwrap().along, *id)))
+                .collect();
+            order.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+            if order.len() == road.vehicles_on.len() {
+                road.vehicles_on = order.into_iter().map(|(_, id)| id).collect();
+            }
+        }
+    }
+
+    /// Whether a car is within detector range of the end of `road`, as a signal would see it.
+    fn has_waiting_car(&self, road: RoadID) -> bool {
+        let Some(road) = self.roads.get(&road) else { return false };
+        let road = road.read().unwrap();
+        let length = road.length;
+
+        road.vehicles_on.iter()
+            .filter_map(|id| self.cars.get(id))
+            .map(|car| car.read().unwrap())
+            .any(|car| !car.has_arrived(&road) && length - car.along <= DETECTOR_RANGE)
+    }
+
 
 
 }
@@ -336,8 +1488,7 @@ impl RoadGraph {
 
 //////////////// TESTING FUNCTIONS /////////////////
 
-pub fn generate_random_roads(num: i32, nodes: &[Node]) -> Vec<Road> {
-    let mut rng = rand::rng();
+pub fn generate_random_roads(num: i32, nodes: &[Node], rng: &mut impl Rng) -> Vec<Road> {
     let mut roads = Vec::new();
 
     for i in 0..num {
@@ -355,14 +1506,14 @@ pub fn generate_random_roads(num: i32, nodes: &[Node]) -> Vec<Road> {
             to,
             rng.random_range(20..100),    // capacity
             rng.random_range(30.0..80.0), // speed_limit
+            rng,
         ));
     }
 
     roads
 }
 
-pub fn generate_random_nodes(num: i32, x_size: f32, y_size: f32) -> Vec<Node> {
-    let mut rng = rand::rng();
+pub fn generate_random_nodes(num: i32, x_size: f32, y_size: f32, rng: &mut impl Rng) -> Vec<Node> {
     (0..num)
         .map(|i| {
             let x = rng.random_range(0.0..x_size);
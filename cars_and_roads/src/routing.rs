@@ -0,0 +1,440 @@
+//! Route search over a `RoadGraph`.
+//!
+//! Routes are searched node to node with A*. What a road costs is up to a `CostModel`, so
+//! drivers can weigh distance, time, congestion and tolls differently on the same graph.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use macroquad::math::Vec2;
+
+use crate::{NodeID, Road, RoadGraph, RoadID};
+
+
+
+#[derive(Clone, Debug, PartialEq)]
+/// A way through the graph.
+pub struct Route {
+    pub nodes: Vec<NodeID>, // from the start node to the goal, both included
+    pub roads: Vec<RoadID>, // one fewer than `nodes`, `roads[i]` goes from `nodes[i]` to `nodes[i + 1]`
+    pub cost: f32,          // sum of the cost model over `roads`
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RouteError {
+    UnknownNode(NodeID),
+    /// No chain of roads leads from the first node to the second
+    Unreachable(NodeID, NodeID),
+    /// Start and goal are the same node, there is nothing to drive
+    StartIsGoal(NodeID),
+}
+
+impl std::fmt::Display for RouteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RouteError::UnknownNode(id) => write!(f, "no node with id {} in the graph", id),
+            RouteError::Unreachable(from, to) => write!(f, "no route from node {} to node {}", from, to),
+            RouteError::StartIsGoal(id) => write!(f, "already at node {}", id),
+        }
+    }
+}
+
+impl std::error::Error for RouteError {}
+
+
+/// What driving a road costs a driver.
+pub trait CostModel: std::fmt::Debug + Send + Sync {
+    /// Cost of driving the whole of `road`, never negative.
+    fn cost(&self, road: &Road) -> f32;
+
+    /// The least covering `distance` meters could cost, on roads no faster than `top_speed`
+    /// m/s. A* uses it to aim its search, zero is always safe but slow.
+    fn lower_bound(&self, _distance: f32, _top_speed: f32) -> f32 {
+        0.0
+    }
+
+    /// Tells models apart in the route cache, two models with the same key must price every
+    /// road the same.
+    fn cache_key(&self) -> ModelKey;
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+/// What the route cache knows a cost model by: its kind and its parameters, bit for bit.
+pub enum ModelKey {
+    Distance,
+    FreeFlowTime,
+    Bpr { alpha: u32, beta: u32 },
+    Generalized { value_of_time: u32, per_km: u32, alpha: u32, beta: u32 },
+    /// A model from outside this crate, numbered however it likes
+    Other(u64),
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+/// Shortest way in meters, whatever the speed limits and traffic.
+pub struct Distance;
+
+impl CostModel for Distance {
+    fn cost(&self, road: &Road) -> f32 {
+        road.route_length().max(1.0)
+    }
+
+    fn lower_bound(&self, distance: f32, _top_speed: f32) -> f32 {
+        distance
+    }
+
+    fn cache_key(&self) -> ModelKey {
+        ModelKey::Distance
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+/// Quickest way in seconds on empty roads, driving at the limit.
+pub struct FreeFlowTime;
+
+impl CostModel for FreeFlowTime {
+    fn cost(&self, road: &Road) -> f32 {
+        road.route_length().max(1.0) / road.max_speed()
+    }
+
+    fn lower_bound(&self, distance: f32, top_speed: f32) -> f32 {
+        if top_speed > 0.0 { distance / top_speed } else { 0.0 }
+    }
+
+    fn cache_key(&self) -> ModelKey {
+        ModelKey::FreeFlowTime
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Bureau of Public Roads volume-delay: free-flow time stretched to `t0 * (1 + alpha * (v/c)^beta)`,
+/// with `v/c` the road's `traffic_density`, its load against `Road::capacity`.
+pub struct Bpr {
+    pub alpha: f32,
+    pub beta: f32,
+}
+
+impl Bpr {
+    pub fn new(alpha: f32, beta: f32) -> Self {
+        Bpr { alpha, beta }
+    }
+
+    /// Time through `road` at its current load.
+    pub fn travel_time(&self, road: &Road) -> f32 {
+        FreeFlowTime.cost(road) * (1.0 + self.alpha * road.traffic_density.max(0.0).powf(self.beta))
+    }
+}
+
+impl Default for Bpr {
+    /// The textbook curve, 0.15 and 4.
+    fn default() -> Self {
+        Bpr::new(0.15, 4.0)
+    }
+}
+
+impl CostModel for Bpr {
+    fn cost(&self, road: &Road) -> f32 {
+        self.travel_time(road)
+    }
+
+    fn lower_bound(&self, distance: f32, top_speed: f32) -> f32 {
+        FreeFlowTime.lower_bound(distance, top_speed)
+    }
+
+    fn cache_key(&self) -> ModelKey {
+        ModelKey::Bpr { alpha: self.alpha.to_bits(), beta: self.beta.to_bits() }
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Everything in money: tolls, time at the driver's value of time and distance at a running
+/// cost. Time is congested time from `congestion`.
+pub struct Generalized {
+    pub value_of_time: f32, // per hour
+    pub per_km: f32,
+    pub congestion: Bpr,
+}
+
+impl Generalized {
+    pub fn new(value_of_time: f32, per_km: f32) -> Self {
+        Generalized { value_of_time, per_km, congestion: Bpr::default() }
+    }
+}
+
+impl CostModel for Generalized {
+    fn cost(&self, road: &Road) -> f32 {
+        road.toll.max(0.0)
+            + self.value_of_time * self.congestion.travel_time(road) / 3600.0
+            + self.per_km * road.route_length().max(1.0) / 1000.0
+    }
+
+    fn lower_bound(&self, distance: f32, top_speed: f32) -> f32 {
+        self.value_of_time * FreeFlowTime.lower_bound(distance, top_speed) / 3600.0
+            + self.per_km * distance / 1000.0
+    }
+
+    fn cache_key(&self) -> ModelKey {
+        ModelKey::Generalized {
+            value_of_time: self.value_of_time.to_bits(),
+            per_km: self.per_km.to_bits(),
+            alpha: self.congestion.alpha.to_bits(),
+            beta: self.congestion.beta.to_bits(),
+        }
+    }
+}
+
+
+/// What `model` charges for `road` as it is right now, `None` if obstructions close it.
+///
+/// A partly closed road costs more by the share of its lanes that are shut: with half of them
+/// blocked it costs twice as much. Every search prices roads through this.
+pub fn passable_cost(model: &dyn CostModel, road: &Road) -> Option<f32> {
+    let open = road.open_share();
+    (open > 0.0).then(|| model.cost(road) / open)
+}
+
+
+#[derive(Copy, Clone, PartialEq)]
+/// State is used for pathfinding algorithms, `N` is whatever the search calls a node
+pub(crate) struct State<N = NodeID> {
+    pub(crate) node: N,
+    pub(crate) cost: f32,         // g(n)
+    est_total: f32,    // f(n) = g(n) + h(n)
+}
+
+impl<N> State<N> {
+    pub(crate) fn new(node: N, cost: f32, est_total: f32) -> Self {
+        State { node, cost, est_total }
+    }
+}
+
+impl<N: PartialEq> Eq for State<N> {}
+
+impl<N: PartialEq> Ord for State<N> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reverse for min-heap behavior
+        other.est_total.partial_cmp(&self.est_total).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl<N: PartialEq> PartialOrd for State<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+
+/// Cheapest route from `start_node` to `goal_node`, as `model` prices it.
+pub fn a_star(start_node: NodeID, goal_node: NodeID, road_graph: &RoadGraph, model: &dyn CostModel) -> Result<Route, RouteError> {
+
+    let nodes = road_graph.get_nodes();
+    let start_pos = nodes.get(&start_node).ok_or(RouteError::UnknownNode(start_node))?.position;
+    let goal_pos = nodes.get(&goal_node).ok_or(RouteError::UnknownNode(goal_node))?.position;
+
+    if start_node == goal_node {
+        return Err(RouteError::StartIsGoal(start_node));
+    }
+
+    // Time based costs need the straight-line distance driven at the fastest limit anywhere
+    // in the graph to keep the heuristic from overestimating
+    let top_speed = road_graph.top_speed();
+    let heuristic = |pos: Vec2| model.lower_bound(pos.distance(goal_pos), top_speed);
+
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<NodeID, (NodeID, RoadID)> = HashMap::new(); // the road too, two nodes can have several roads between them
+    let mut cost_so_far: HashMap<NodeID, f32> = HashMap::new();
+
+    open.push(State::new(start_node, 0.0, heuristic(start_pos)));
+    cost_so_far.insert(start_node, 0.0);
+
+    while let Some(State { node: current, cost, .. }) = open.pop() {
+        if current == goal_node {
+            return Ok(reconstruct(start_node, goal_node, &came_from, cost));
+        }
+
+        // A cheaper way here was found after this entry was pushed
+        if cost > cost_so_far[&current] {
+            continue;
+        }
+
+        let Some(neighbors) = road_graph.adjacency.get(&current) else { continue };
+        for &(neighbor, road_id) in neighbors {
+            // Roads are directed edges, so a one-way road simply has no edge back
+            let Some(road) = road_graph.get_roads().get(&road_id) else { continue };
+            let Some(road_cost) = passable_cost(model, &road.read().unwrap()) else { continue };
+            let new_cost = cost + road_cost;
+
+            if new_cost < *cost_so_far.get(&neighbor).unwrap_or(&f32::INFINITY) {
+                cost_so_far.insert(neighbor, new_cost);
+                came_from.insert(neighbor, (current, road_id));
+                let est = new_cost + nodes.get(&neighbor).map_or(0.0, |node| heuristic(node.position));
+                open.push(State::new(neighbor, new_cost, est));
+            }
+        }
+    }
+
+    Err(RouteError::Unreachable(start_node, goal_node))
+}
+
+/// Walks `came_from` back from the goal.
+fn reconstruct(start_node: NodeID, goal_node: NodeID, came_from: &HashMap<NodeID, (NodeID, RoadID)>, cost: f32) -> Route {
+    let mut nodes = vec![goal_node];
+    let mut roads = Vec::new();
+
+    let mut current = goal_node;
+    while current != start_node {
+        let (previous, road) = came_from[&current];
+        nodes.push(previous);
+        roads.push(road);
+        current = previous;
+    }
+
+    nodes.reverse();
+    roads.reverse();
+
+    Route { nodes, roads, cost }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+/// Cheapest routes from one origin to every node it can reach, from a single Dijkstra run.
+pub struct PathTree {
+    pub origin: NodeID,
+    costs: HashMap<NodeID, f32>,
+    came_from: HashMap<NodeID, (NodeID, RoadID)>,
+}
+
+impl PathTree {
+
+    /// Cost of the cheapest route from the origin to `node`, `None` if there is none.
+    pub fn cost_to(&self, node: NodeID) -> Option<f32> {
+        self.costs.get(&node).copied()
+    }
+
+    /// Every node the origin can reach, the origin included.
+    pub fn reachable(&self) -> impl Iterator<Item = NodeID> + '_ {
+        self.costs.keys().copied()
+    }
+
+    /// The cheapest route from the origin to `goal`.
+    pub fn route_to(&self, goal: NodeID) -> Result<Route, RouteError> {
+        if goal == self.origin {
+            return Err(RouteError::StartIsGoal(goal));
+        }
+        let cost = self.cost_to(goal).ok_or(RouteError::Unreachable(self.origin, goal))?;
+
+        Ok(reconstruct(self.origin, goal, &self.came_from, cost))
+    }
+
+}
+
+
+/// Dijkstra from `origin` to everywhere, as `model` prices it.
+pub fn shortest_path_tree(origin: NodeID, road_graph: &RoadGraph, model: &dyn CostModel) -> Result<PathTree, RouteError> {
+    if !road_graph.get_nodes().contains_key(&origin) {
+        return Err(RouteError::UnknownNode(origin));
+    }
+
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<NodeID, (NodeID, RoadID)> = HashMap::new();
+    let mut costs: HashMap<NodeID, f32> = HashMap::new();
+
+    open.push(State::new(origin, 0.0, 0.0));
+    costs.insert(origin, 0.0);
+
+    while let Some(State { node: current, cost, .. }) = open.pop() {
+        if cost > costs[&current] {
+            continue;
+        }
+
+        let Some(neighbors) = road_graph.adjacency.get(&current) else { continue };
+        for &(neighbor, road_id) in neighbors {
+            let Some(road) = road_graph.get_roads().get(&road_id) else { continue };
+            let Some(road_cost) = passable_cost(model, &road.read().unwrap()) else { continue };
+            let new_cost = cost + road_cost;
+
+            if new_cost < *costs.get(&neighbor).unwrap_or(&f32::INFINITY) {
+                costs.insert(neighbor, new_cost);
+                came_from.insert(neighbor, (current, road_id));
+                open.push(State::new(neighbor, new_cost, new_cost));
+            }
+        }
+    }
+
+    Ok(PathTree { origin, costs, came_from })
+}
+
+
+/// Route costs from every origin to every destination, `matrix[i][j]` from `origins[i]` to
+/// `destinations[j]`. `None` where there is no route, zero on the diagonal.
+///
+/// Runs one `shortest_path_tree` per origin.
+pub fn cost_matrix(origins: &[NodeID], destinations: &[NodeID], road_graph: &RoadGraph, model: &dyn CostModel) -> Result<Vec<Vec<Option<f32>>>, RouteError> {
+    if let Some(&unknown) = destinations.iter().find(|node| !road_graph.get_nodes().contains_key(node)) {
+        return Err(RouteError::UnknownNode(unknown));
+    }
+
+    origins.iter()
+        .map(|&origin| {
+            let tree = shortest_path_tree(origin, road_graph, model)?;
+            Ok(destinations.iter().map(|&destination| tree.cost_to(destination)).collect())
+        })
+        .collect()
+}
+
+
+#[derive(Debug, Default)]
+/// Routes already searched on a graph, shared by every car on it.
+///
+/// Entries are keyed by origin, destination and cost model, and all dropped together whenever
+/// the graph moves on to a new cost epoch.
+pub(crate) struct RouteCache {
+    epoch: u64,
+    routes: HashMap<(NodeID, NodeID, ModelKey), Result<Route, RouteError>>,
+    hits: u64,
+    misses: u64,
+}
+
+impl RouteCache {
+
+    /// The cached search, if there is one for this epoch.
+    pub(crate) fn get(&mut self, epoch: u64, key: &(NodeID, NodeID, ModelKey)) -> Option<Result<Route, RouteError>> {
+        if epoch != self.epoch {
+            self.routes.clear();
+            self.epoch = epoch;
+        }
+
+        let found = self.routes.get(key).cloned();
+        match found {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+        found
+    }
+
+    pub(crate) fn insert(&mut self, epoch: u64, key: (NodeID, NodeID, ModelKey), route: Result<Route, RouteError>) {
+        if epoch == self.epoch {
+            self.routes.insert(key, route);
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    /// Lookups answered from the cache.
+    pub(crate) fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Lookups that had to search.
+    pub(crate) fn misses(&self) -> u64 {
+        self.misses
+    }
+
+}
@@ -0,0 +1,222 @@
+//! Headless driver for the traffic simulation.
+//!
+//! A `Simulation` owns a `RoadGraph` and advances it in fixed-size ticks, so the
+//! result of a run only depends on the number of ticks, never on the frame rate
+//! of whatever is (or isn't) drawing it.
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{CarID, CarState, Demand, NodeID, RoadGraph, TripID};
+
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+/// Settings shared by everything that builds or runs a simulation.
+///
+/// The same `seed` always produces the same network, cars and trajectories.
+pub struct SimConfig {
+    pub seed: u64,
+}
+
+impl SimConfig {
+    pub fn new(seed: u64) -> Self {
+        SimConfig { seed }
+    }
+
+    /// A fresh RNG seeded from `seed`. Pass it to the road, car and level constructors.
+    pub fn rng(&self) -> StdRng {
+        StdRng::seed_from_u64(self.seed)
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// A finished trip, recorded when its car arrives and leaves the graph.
+pub struct Trip {
+    pub id: TripID,
+    pub car: CarID,
+    pub origin: NodeID,
+    pub destination: NodeID,
+    pub depart_time: f32, // simulated seconds
+    pub arrive_time: f32, // simulated seconds
+    pub distance: f32,    // meters driven
+}
+
+impl Trip {
+    /// Seconds from departure to arrival.
+    pub fn duration(&self) -> f32 {
+        self.arrive_time - self.depart_time
+    }
+}
+
+
+/// Owns a `RoadGraph` and steps it forward with a fixed `dt`.
+pub struct Simulation {
+    pub road_graph: RoadGraph,
+    pub demand: Option<Demand>, // spawns cars as the run goes, on top of any the graph starts with
+
+    dt: f32,
+    time: f32,
+    ticks: u64,
+    accumulator: f32, // leftover time from `run_for` that didn't fill a whole tick
+
+    trips: Vec<Trip>, // finished since the last `take_trips`
+    trips_completed: u64,
+    trips_removed: u64, // cut short by edits to the graph, see `RoadGraph::take_removed_trips`
+    finished_reroutes: u64, // made by cars that have since arrived and gone
+    rng: StdRng, // for whatever happens during the run rather than at set up, like spawning
+}
+
+
+impl Simulation {
+
+    /// Wraps a `RoadGraph` in a simulation advancing `dt` seconds per tick.
+    pub fn new(road_graph: RoadGraph, dt: f32) -> Self {
+        assert!(dt > 0.0, "Simulation dt must be positive, got {}", dt);
+
+        Simulation {
+            road_graph,
+            demand: None,
+            dt,
+            time: 0.0,
+            ticks: 0,
+            accumulator: 0.0,
+            trips: Vec::new(),
+            trips_completed: 0,
+            trips_removed: 0,
+            finished_reroutes: 0,
+            rng: SimConfig::default().rng(),
+        }
+    }
+
+    /// Spawns cars from `demand` as the run goes, drawing arrivals from `config`'s seed.
+    pub fn with_demand(mut self, demand: Demand, config: &SimConfig) -> Self {
+        self.demand = Some(demand);
+        self.rng = config.rng();
+        self
+    }
+
+    /// Advances the world by exactly one tick of `dt`.
+    ///
+    /// New cars from `demand` join first, then cars are updated one at a time in `CarID`
+    /// order so a run is reproducible. Cars that arrive are taken out of the graph at the end
+    /// of the tick, and their trip recorded.
+    pub fn step(&mut self) {
+
+        // Edits since the last tick may have taken cars out
+        self.trips_removed += self.road_graph.take_removed_trips().len() as u64;
+
+        if let Some(demand) = &mut self.demand {
+            demand.spawn(&mut self.road_graph, self.time, self.dt, &mut self.rng);
+        }
+
+        self.road_graph.update_controls(self.dt);
+        self.road_graph.update_obstructions(self.dt);
+
+        let mut car_ids: Vec<CarID> = self.road_graph.get_cars().keys().copied().collect();
+        car_ids.sort_by_key(|id| id.0);
+
+        let mut arrived = Vec::new();
+        for id in car_ids {
+            let car = self.road_graph.get_cars().get(&id).unwrap().clone();
+            let mut car = car.write().unwrap();
+            car.move_car_to_destination(&self.road_graph, self.dt);
+
+            if car.state() == CarState::Arrived {
+                arrived.push(id);
+            }
+        }
+
+        self.ticks += 1;
+        self.time = self.ticks as f32 * self.dt;
+
+        for id in arrived {
+            self.finish_trip(id);
+        }
+
+        self.road_graph.sort_vehicles();
+        self.road_graph.update_density(self.dt);
+    }
+
+    /// Records the trip of an arrived car and takes it out of the graph.
+    fn finish_trip(&mut self, id: CarID) {
+        let Some(car) = self.road_graph.get_cars().get(&id) else { return };
+        let car = car.read().unwrap();
+
+        self.trips.push(Trip {
+            id: car.trip,
+            car: id,
+            origin: car.origin,
+            destination: car.destination,
+            depart_time: car.depart_time,
+            arrive_time: self.time,
+            distance: car.distance_travelled(),
+        });
+        self.trips_completed += 1;
+        self.finished_reroutes += car.reroutes() as u64;
+        drop(car);
+
+        self.road_graph.remove_car(id);
+    }
+
+    /// Trips finished since the last call, in the order they finished.
+    pub fn take_trips(&mut self) -> Vec<Trip> {
+        std::mem::take(&mut self.trips)
+    }
+
+    /// Trips finished since the start of the run.
+    pub fn trips_completed(&self) -> u64 {
+        self.trips_completed
+    }
+
+    /// Trips cut short since the start of the run, their cars taken out by edits to the graph.
+    pub fn trips_removed(&self) -> u64 {
+        self.trips_removed
+    }
+
+    /// Runs as many whole ticks as fit in `duration` seconds.
+    ///
+    /// Time that doesn't fill a whole tick is carried over to the next call, so calling
+    /// this once per rendered frame with the frame time keeps the sim in step with the clock.
+    ///
+    /// Returns the number of ticks that were run.
+    pub fn run_for(&mut self, duration: f32) -> u64 {
+
+        self.accumulator += duration.max(0.0);
+
+        let mut ran = 0;
+        while self.accumulator >= self.dt {
+            self.step();
+            self.accumulator -= self.dt;
+            ran += 1;
+        }
+
+        ran
+    }
+
+    /// Runs exactly `ticks` ticks.
+    pub fn run_ticks(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.step();
+        }
+    }
+
+    pub fn dt(&self) -> f32 {
+        self.dt
+    }
+
+    /// Simulated seconds since the start of the run.
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Route changes made on the way by every car this run, arrived or not, see `ReroutePolicy`.
+    pub fn total_reroutes(&self) -> u64 {
+        self.finished_reroutes + self.road_graph.cars_to_iter().map(|car| car.read().unwrap().reroutes() as u64).sum::<u64>()
+    }
+
+}
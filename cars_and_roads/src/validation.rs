@@ -0,0 +1,333 @@
+//! Checks that a network makes sense before anything drives on it.
+//!
+//! `RoadGraph::validate` looks the whole graph over and lists every `Issue` it finds. Some
+//! are errors, the graph is broken and cars on it would misbehave. The rest are warnings:
+//! legal but likely a mistake, like destinations nothing can reach. Loading a level runs it,
+//! see `ValidationMode` for what happens next.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{CarID, NodeID, RoadGraph, RoadID};
+
+
+/// Roads shorter than this, in meters, have no real shape
+const MIN_ROAD_LENGTH: f32 = 0.01;
+
+/// Most `Issue::Unreachable` pairs of groups to list before summing the rest up
+const UNREACHABLE_LISTED: usize = 20;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Something wrong with a graph, found by `RoadGraph::validate`.
+pub enum Issue {
+    /// No road starts or ends at the node
+    DanglingNode(NodeID),
+    /// The road's copy of one of its nodes isn't in the nodes map, or is somewhere else
+    MismatchedNode { road: RoadID, node: NodeID },
+    /// The road never counts as busy, whatever is on it
+    ZeroCapacity(RoadID),
+    /// The road has fewer than two points, points that aren't finite, or no length
+    DegeneratePoints(RoadID),
+    /// No route leads from `from`, or any node in its group, to `to` or any node in its. Groups
+    /// are `Validation::components` and go by their lowest node ID
+    Unreachable { from: NodeID, to: NodeID },
+    /// More groups than `Unreachable` lists can't reach every other group, `groups` of them
+    /// in all
+    MoreUnreachable { groups: usize },
+    /// No route leads from where the car is to its destination
+    StrandedCar { car: CarID, destination: NodeID },
+}
+
+impl Issue {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Issue::MismatchedNode { .. } | Issue::DegeneratePoints(_) => Severity::Error,
+            Issue::DanglingNode(_) | Issue::ZeroCapacity(_) | Issue::Unreachable { .. } | Issue::MoreUnreachable { .. }
+                | Issue::StrandedCar { .. } => Severity::Warning,
+        }
+    }
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Issue::DanglingNode(node) => write!(f, "node {} has no roads", node),
+            Issue::MismatchedNode { road, node } => write!(f, "road {:?} doesn't match node {} in the nodes map", road, node),
+            Issue::ZeroCapacity(road) => write!(f, "road {:?} has no capacity", road),
+            Issue::DegeneratePoints(road) => write!(f, "road {:?} has no usable shape", road),
+            Issue::Unreachable { from, to } => write!(f, "no route from node {} or the nodes grouped with it to node {} or those grouped with it", from, to),
+            Issue::MoreUnreachable { groups } => write!(f, "{} groups of nodes in all can't reach every other group", groups),
+            Issue::StrandedCar { car, destination } => write!(f, "car {:?} can't reach its destination, node {}", car, destination),
+        }
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq, Default)]
+/// Everything `RoadGraph::validate` found.
+pub struct Validation {
+    pub issues: Vec<Issue>,
+    pub components: Vec<Vec<NodeID>>, // strongly connected, see `strongly_connected_components`
+}
+
+impl Validation {
+
+    /// True when nothing is worse than a warning.
+    pub fn is_ok(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Issue> {
+        self.issues.iter().filter(|issue| issue.severity() == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Issue> {
+        self.issues.iter().filter(|issue| issue.severity() == Severity::Warning)
+    }
+
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+/// What loading a level does about the issues `RoadGraph::validate` finds.
+pub enum ValidationMode {
+    /// Don't check
+    Off,
+    /// Refuse levels with errors, load anyway with the warnings in `Level::issues`
+    #[default]
+    Warn,
+    /// Refuse levels with any issue at all
+    Strict,
+}
+
+
+/// Runs every check, see `RoadGraph::validate`.
+pub(crate) fn validate(road_graph: &RoadGraph) -> Validation {
+    let mut issues = Vec::new();
+    let nodes = road_graph.get_nodes();
+
+    let mut roads: Vec<_> = road_graph.roads_to_iter().map(|road| road.read().unwrap()).collect();
+    roads.sort_by_key(|road| road.id.0);
+
+    let mut touched = HashSet::new();
+    for road in &roads {
+        for node in [road.from, road.to] {
+            touched.insert(node.id);
+            if nodes.get(&node.id).is_none_or(|known| known.position != node.position) {
+                issues.push(Issue::MismatchedNode { road: road.id, node: node.id });
+            }
+        }
+
+        if road.capacity <= 0 {
+            issues.push(Issue::ZeroCapacity(road.id));
+        }
+
+        let finite = road.points.iter().all(|point| point.is_finite()) && road.length.is_finite();
+        if road.points.len() < 2 || !finite || road.length < MIN_ROAD_LENGTH {
+            issues.push(Issue::DegeneratePoints(road.id));
+        }
+    }
+    drop(roads);
+
+    let mut ids: Vec<NodeID> = nodes.keys().copied().collect();
+    ids.sort_by_key(|id| id.0);
+
+    issues.extend(ids.iter().filter(|id| !touched.contains(id)).map(|id| Issue::DanglingNode(*id)));
+
+    let components = strongly_connected_components(road_graph);
+    let mut condensation = Condensation::new(road_graph, &components);
+
+    // Only groups of nodes with roads, a dangling node has already been reported
+    let groups: Vec<usize> = (0..components.len()).filter(|group| touched.contains(&components[*group][0])).collect();
+
+    // One more than gets listed, to know whether to sum the rest up
+    let mut unreachable = Vec::new();
+    for &from in &groups {
+        let reached = condensation.reachable_from(from);
+        unreachable.extend(groups.iter()
+            .filter(|to| !reached.contains(to))
+            .map(|to| Issue::Unreachable { from: components[from][0], to: components[*to][0] })
+            .take(UNREACHABLE_LISTED + 1 - unreachable.len()));
+
+        if unreachable.len() > UNREACHABLE_LISTED {
+            break;
+        }
+    }
+
+    if unreachable.len() > UNREACHABLE_LISTED {
+        unreachable.truncate(UNREACHABLE_LISTED);
+        unreachable.push(Issue::MoreUnreachable { groups: condensation.groups_short_of_everywhere(&groups) });
+    }
+    issues.extend(unreachable);
+
+    let mut cars: Vec<_> = road_graph.cars_to_iter().map(|car| car.read().unwrap()).collect();
+    cars.sort_by_key(|car| car.get_id().0);
+
+    for car in cars {
+        let Some(end) = road_graph.get_roads().get(&car.current_road).map(|road| road.read().unwrap().to.id) else { continue };
+        let arrives = end == car.destination || condensation.reaches(end, car.destination);
+        if !arrives {
+            issues.push(Issue::StrandedCar { car: car.get_id(), destination: car.destination });
+        }
+    }
+
+    Validation { issues, components }
+}
+
+
+/// The graph with each strongly connected group of nodes squashed into one, which leaves no
+/// cycles. Who reaches whom between groups is the same as between any of their nodes, and
+/// there are usually far fewer groups than nodes.
+struct Condensation {
+    group_of: HashMap<NodeID, usize>, // index into the components
+    edges: Vec<Vec<usize>>,           // from each group to the groups one road away
+    reached: HashMap<usize, HashSet<usize>>, // searches already run, by starting group
+}
+
+impl Condensation {
+    fn new(road_graph: &RoadGraph, components: &[Vec<NodeID>]) -> Self {
+        let group_of: HashMap<NodeID, usize> = components.iter().enumerate()
+            .flat_map(|(group, nodes)| nodes.iter().map(move |node| (*node, group)))
+            .collect();
+
+        let mut edges: Vec<HashSet<usize>> = vec![HashSet::new(); components.len()];
+        for (from, roads) in &road_graph.adjacency {
+            let Some(&from) = group_of.get(from) else { continue };
+            for (to, _) in roads {
+                if let Some(&to) = group_of.get(to).filter(|to| **to != from) {
+                    edges[from].insert(to);
+                }
+            }
+        }
+
+        let edges = edges.into_iter()
+            .map(|next| {
+                let mut next: Vec<usize> = next.into_iter().collect();
+                next.sort();
+                next
+            })
+            .collect();
+
+        Condensation { group_of, edges, reached: HashMap::new() }
+    }
+
+    /// Every group some route leads to from `start`, `start` included.
+    fn reachable_from(&mut self, start: usize) -> &HashSet<usize> {
+        if self.reached.contains_key(&start) {
+            return &self.reached[&start];
+        }
+
+        let mut reached = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(group) = queue.pop_front() {
+            for &next in &self.edges[group] {
+                if reached.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        self.reached.entry(start).or_insert(reached)
+    }
+
+    /// Whether some route leads from node `from` to node `to`.
+    fn reaches(&mut self, from: NodeID, to: NodeID) -> bool {
+        match (self.group_of.get(&from).copied(), self.group_of.get(&to).copied()) {
+            (Some(from), Some(to)) => from == to || self.reachable_from(from).contains(&to),
+            _ => false,
+        }
+    }
+
+    /// How many of `groups` can't reach all the others. Without cycles, only a group nothing
+    /// else leads into can reach everywhere, and then only if it is the only such group.
+    fn groups_short_of_everywhere(&self, groups: &[usize]) -> usize {
+        let mut led_into = HashSet::new();
+        for &group in groups {
+            led_into.extend(self.edges[group].iter().copied());
+        }
+
+        let sources = groups.iter().filter(|group| !led_into.contains(group)).count();
+        groups.len() - usize::from(sources == 1)
+    }
+}
+
+/// The graph split into groups of nodes that can all reach each other, biggest first, each
+/// in ID order. A node no road leads into or out of is a group of its own.
+pub fn strongly_connected_components(road_graph: &RoadGraph) -> Vec<Vec<NodeID>> {
+    let known = road_graph.get_nodes();
+    let mut nodes: Vec<NodeID> = known.keys().copied().collect();
+    nodes.sort_by_key(|id| id.0);
+
+    let mut forward: HashMap<NodeID, Vec<NodeID>> = HashMap::new();
+    let mut backward: HashMap<NodeID, Vec<NodeID>> = HashMap::new();
+    for (from, edges) in road_graph.adjacency.iter().filter(|(from, _)| known.contains_key(from)) {
+        for &(to, _) in edges.iter().filter(|(to, _)| known.contains_key(to)) {
+            forward.entry(*from).or_default().push(to);
+            backward.entry(to).or_default().push(*from);
+        }
+    }
+
+    // Kosaraju: order the nodes by when a depth-first search finishes with them...
+    let mut visited = HashSet::new();
+    let mut finished = Vec::with_capacity(nodes.len());
+
+    for &root in &nodes {
+        if !visited.insert(root) {
+            continue;
+        }
+
+        let mut stack = vec![(root, 0)];
+        while let Some(top) = stack.len().checked_sub(1) {
+            let (node, next) = stack[top];
+            match forward.get(&node).and_then(|children| children.get(next)) {
+                Some(&child) => {
+                    stack[top].1 += 1;
+                    if visited.insert(child) {
+                        stack.push((child, 0));
+                    }
+                }
+                None => {
+                    finished.push(node);
+                    stack.pop();
+                }
+            }
+        }
+    }
+
+    // ...then, last finished first, everything that reaches a node it hasn't been grouped
+    // with yet is in its group
+    let mut grouped = HashSet::new();
+    let mut components = Vec::new();
+
+    for &root in finished.iter().rev() {
+        if !grouped.insert(root) {
+            continue;
+        }
+
+        let mut component = vec![root];
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            for &parent in backward.get(&node).into_iter().flatten() {
+                if grouped.insert(parent) {
+                    component.push(parent);
+                    stack.push(parent);
+                }
+            }
+        }
+
+        component.sort_by_key(|id| id.0);
+        components.push(component);
+    }
+
+    components.sort_by_key(|component| (std::cmp::Reverse(component.len()), component[0].0));
+    components
+}
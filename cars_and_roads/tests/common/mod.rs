@@ -0,0 +1,93 @@
+//! Graphs and cost models shared by the tests and benchmarks.
+#![allow(dead_code)] // each test crate uses its own share of these
+
+use rand::Rng;
+
+use cars_and_roads::{generate_random_nodes, generate_random_roads, Bpr, CostModel, Distance, FreeFlowTime, Generalized, Node, NodeID, RoadGraph, Road, RoadID, SimConfig, Vec2};
+
+
+/// Nodes at `positions` and straight one-way roads `(from, to)` between them, with node and
+/// road IDs counting up from 0 in the order given. Roads have room for 50 cars at 50 km/h in
+/// one lane, `tweak` changes each one before the graph is built.
+pub fn network(positions: &[(f32, f32)], roads: &[(usize, usize)], mut tweak: impl FnMut(&mut Road)) -> RoadGraph {
+    let mut rng = SimConfig::new(0).rng();
+    let nodes: Vec<Node> = positions.iter().enumerate()
+        .map(|(id, &(x, y))| Node::new_node(NodeID(id as i32), Vec2::new(x, y)))
+        .collect();
+
+    let roads = roads.iter().enumerate()
+        .map(|(id, &(from, to))| {
+            let mut road = Road::new_road_with_curves(RoadID(id as i32), nodes[from], nodes[to], 50, 50.0, 0.0, &mut rng);
+            road.one_way = true;
+            tweak(&mut road);
+            road
+        })
+        .collect();
+
+    RoadGraph::new(Some(roads), Some(nodes))
+}
+
+/// Random roads between random nodes, some of them one-way, busy or tolled, so routes
+/// aren't just the shortest ones.
+pub fn random_graph(seed: u64, nodes: i32, roads: i32) -> RoadGraph {
+    let mut rng = SimConfig::new(seed).rng();
+    let nodes = generate_random_nodes(nodes, 1000.0, 1000.0, &mut rng);
+    let mut roads = generate_random_roads(roads, &nodes, &mut rng);
+
+    for road in &mut roads {
+        road.traffic_density = rng.random_range(0.0..1.0);
+        road.toll = if rng.random_bool(0.3) { rng.random_range(0.5..5.0) } else { 0.0 };
+        road.one_way = rng.random_bool(0.3);
+    }
+
+    RoadGraph::new(Some(roads), Some(nodes))
+}
+
+/// A `side` by `side` block of streets, every other avenue one-way and some roads busy.
+pub fn grid_graph(side: i32, rng: &mut impl Rng) -> RoadGraph {
+    let id = |x: i32, y: i32| NodeID(y * side + x);
+
+    let nodes: Vec<Node> = (0..side * side)
+        .map(|i| Node::new_node(NodeID(i), Vec2::new((i % side) as f32 * 100.0, (i / side) as f32 * 100.0)))
+        .collect();
+
+    let mut roads = Vec::new();
+    for y in 0..side {
+        for x in 0..side {
+            let from = nodes[id(x, y).0 as usize];
+            let mut link = |to: NodeID, one_way: bool| {
+                let mut road = Road::new_road(RoadID(roads.len() as i32), from, nodes[to.0 as usize], 50, rng.random_range(30.0..80.0), rng);
+                road.one_way = one_way;
+                road.traffic_density = rng.random_range(0.0..1.0);
+                roads.push(road);
+            };
+
+            if x + 1 < side {
+                link(id(x + 1, y), y % 2 == 1);
+            }
+            if y + 1 < side {
+                link(id(x, y + 1), false);
+            }
+        }
+    }
+
+    RoadGraph::new(Some(roads), Some(nodes))
+}
+
+/// One of each kind of cost model, the BPR curve both gentle and steep.
+pub fn models() -> Vec<Box<dyn CostModel>> {
+    vec![
+        Box::new(Distance),
+        Box::new(FreeFlowTime),
+        Box::new(Bpr::default()),
+        Box::new(Bpr::new(3.0, 1.0)),
+        Box::new(Generalized::new(20.0, 0.3)),
+    ]
+}
+
+/// The graph's nodes in ID order, so tests go through them the same way every run.
+pub fn sorted_nodes(graph: &RoadGraph) -> Vec<NodeID> {
+    let mut nodes: Vec<NodeID> = graph.get_nodes().keys().copied().collect();
+    nodes.sort_by_key(|node| node.0);
+    nodes
+}
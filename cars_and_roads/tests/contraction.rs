@@ -0,0 +1,71 @@
+use cars_and_roads::routing::a_star;
+use cars_and_roads::{ContractionHierarchy, CostModel, Distance, NodeID, RoadGraph, RouteError, SimConfig};
+
+mod common;
+use common::{grid_graph, models, random_graph, sorted_nodes};
+
+
+fn assert_matches_a_star(graph: &RoadGraph, model: &dyn CostModel, context: &str) {
+    let hierarchy = ContractionHierarchy::build(graph, model);
+
+    for start in sorted_nodes(graph) {
+        for goal in sorted_nodes(graph) {
+            let expected = a_star(start, goal, graph, model);
+            let found = hierarchy.route(start, goal);
+
+            match (&expected, &found) {
+                (Ok(expected), Ok(found)) => {
+                    assert!((expected.cost - found.cost).abs() <= expected.cost * 1e-4, "{context}, {start} -> {goal}: {} vs {}", found.cost, expected.cost);
+
+                    // A real route, not just the right number
+                    assert_eq!(found.nodes.first(), Some(&start));
+                    assert_eq!(found.nodes.last(), Some(&goal));
+                    for (index, id) in found.roads.iter().enumerate() {
+                        let road = graph.get_roads()[id].read().unwrap();
+                        assert_eq!((road.from.id, road.to.id), (found.nodes[index], found.nodes[index + 1]), "{context}, {start} -> {goal}");
+                    }
+                }
+                _ => assert_eq!(found, expected, "{context}, {start} -> {goal}"),
+            }
+        }
+    }
+}
+
+
+#[test]
+fn matches_a_star_on_random_graphs() {
+    for seed in 0..10 {
+        let graph = random_graph(seed, 15, 35);
+        for model in models() {
+            assert_matches_a_star(&graph, model.as_ref(), &format!("seed {seed}, {model:?}"));
+        }
+    }
+}
+
+#[test]
+fn matches_a_star_on_a_grid() {
+    let graph = grid_graph(8, &mut SimConfig::new(0).rng());
+    for model in models() {
+        assert_matches_a_star(&graph, model.as_ref(), &format!("grid, {model:?}"));
+    }
+}
+
+#[test]
+fn errors_match_a_star() {
+    let graph = random_graph(0, 5, 10);
+    let hierarchy = ContractionHierarchy::build(&graph, &Distance);
+
+    assert_eq!(hierarchy.route(NodeID(99), NodeID(0)), Err(RouteError::UnknownNode(NodeID(99))));
+    assert_eq!(hierarchy.route(NodeID(0), NodeID(99)), Err(RouteError::UnknownNode(NodeID(99))));
+    assert_eq!(hierarchy.route(NodeID(2), NodeID(2)), Err(RouteError::StartIsGoal(NodeID(2))));
+}
+
+#[test]
+fn goes_stale_when_the_graph_changes() {
+    let mut graph = grid_graph(4, &mut SimConfig::new(0).rng());
+    let hierarchy = ContractionHierarchy::build(&graph, &Distance);
+    assert!(!hierarchy.is_stale(&graph));
+
+    graph.invalidate_routes();
+    assert!(hierarchy.is_stale(&graph));
+}
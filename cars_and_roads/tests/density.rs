@@ -0,0 +1,76 @@
+use cars_and_roads::{Bpr, Car, CostModel, FreeFlowTime, NodeID, RoadGraph, RoadID, SimConfig};
+
+mod common;
+use common::network;
+
+
+/// A straight one-way road 1 km long with room for `capacity` cars, and `cars` on it.
+fn loaded_road(capacity: i32, cars: usize) -> RoadGraph {
+    let mut rng = SimConfig::new(0).rng();
+    let mut graph = network(&[(0.0, 0.0), (1000.0, 0.0)], &[(0, 1)], |road| road.capacity = capacity);
+
+    for index in 0..cars {
+        let mut car = Car::new_on_road(None, RoadID(0), &mut graph, 0.0, NodeID(1), &mut rng);
+        car.along = 100.0 * index as f32;
+        graph.add_car(car).unwrap();
+    }
+    graph
+}
+
+fn density(graph: &RoadGraph) -> (f32, f32) {
+    let road = graph.get_roads()[&RoadID(0)].read().unwrap();
+    (road.traffic_density, road.vehicles_per_km)
+}
+
+
+#[test]
+fn densities_catch_up_with_the_traffic_over_the_window() {
+    let mut graph = loaded_road(4, 2);
+    graph.set_density_window(10.0);
+    assert_eq!(graph.density_window(), 10.0);
+
+    // One second in, a tenth of the window: about a tenth of the way there
+    graph.update_density(1.0);
+    let (share, per_km) = density(&graph);
+    let weight = 1.0 - (-0.1_f32).exp();
+    assert!((share - 0.5 * weight).abs() < 1e-5, "{share}");
+    assert!((per_km - 2.0 * weight).abs() < 1e-5, "{per_km}");
+    assert_eq!(graph.get_roads()[&RoadID(0)].read().unwrap().num_vehicles_on, 2);
+
+    // Ticking in smaller steps over the same time gets the same answer
+    let mut fine = loaded_road(4, 2);
+    fine.set_density_window(10.0);
+    (0..10).for_each(|_| fine.update_density(0.1));
+    assert!((density(&fine).0 - share).abs() < 1e-5);
+
+    // After a few windows it has settled on what is really there
+    (0..60).for_each(|_| graph.update_density(1.0));
+    let (share, per_km) = density(&graph);
+    assert!((share - 0.5).abs() < 0.01 && (per_km - 2.0).abs() < 0.01, "{share}, {per_km}");
+}
+
+#[test]
+fn without_a_window_densities_follow_every_tick() {
+    let mut graph = loaded_road(4, 3);
+    graph.set_density_window(-5.0);
+    assert_eq!(graph.density_window(), 0.0);
+
+    graph.update_density(0.1);
+    assert_eq!(density(&graph), (0.75, 3.0));
+}
+
+#[test]
+fn roads_without_capacity_still_have_a_price() {
+    let mut graph = loaded_road(0, 3);
+    graph.set_density_window(0.0);
+    graph.update_density(0.1);
+
+    let road = graph.get_roads()[&RoadID(0)].read().unwrap();
+    assert_eq!(road.occupancy(), 0.0);
+    assert_eq!(road.traffic_density, 0.0);
+    assert_eq!(road.vehicles_per_km, 3.0);
+
+    let cost = Bpr::default().cost(&road);
+    assert!(cost.is_finite());
+    assert_eq!(cost, FreeFlowTime.cost(&road));
+}
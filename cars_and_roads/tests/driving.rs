@@ -0,0 +1,101 @@
+use cars_and_roads::{Car, NodeID, RoadGraph, RoadID, SimConfig, Simulation};
+
+mod common;
+use common::network;
+
+
+/// A one-way road `length` meters long from node 0 to node 1, `curviness` off straight.
+fn single_road(length: f32, speed_limit: f32, curviness: f32) -> RoadGraph {
+    let mut graph = network(&[(0.0, 0.0), (length, 0.0)], &[(0, 1)], |road| {
+        (road.capacity, road.speed_limit) = (100, speed_limit);
+    });
+    graph.set_curviness(RoadID(0), curviness).unwrap();
+    graph
+}
+
+/// Puts a car `along` meters down road 0 going `speed`, heading for node 1.
+fn place(graph: &mut RoadGraph, along: f32, speed: f32) -> Car {
+    let mut car = Car::new_on_road(None, RoadID(0), graph, speed, NodeID(1), &mut SimConfig::new(0).rng());
+    car.along = along;
+    graph.add_car(car.clone()).unwrap();
+    car
+}
+
+
+#[test]
+fn cars_queue_behind_slower_ones_without_touching() {
+    let mut graph = single_road(3000.0, 80.0, 0.0);
+    let leader = place(&mut graph, 400.0, 5.0);
+    graph.get_cars()[&leader.get_id()].write().unwrap().idm.desired_speed = 5.0;
+    let followers: Vec<Car> = (0..8).map(|index| place(&mut graph, 300.0 - index as f32 * 35.0, 20.0)).collect();
+
+    let mut sim = Simulation::new(graph, 0.1);
+    let mut closest = f32::INFINITY;
+
+    for _ in 0..1800 {
+        sim.step();
+
+        let road = sim.road_graph.get_roads()[&RoadID(0)].read().unwrap();
+        let cars: Vec<_> = road.vehicles_on.iter().map(|id| sim.road_graph.get_cars()[id].read().unwrap()).collect();
+        for pair in cars.windows(2) {
+            let gap = pair[0].along - pair[1].along - pair[0].get_height();
+            assert!(gap > 0.0, "{:?} ran into {:?} at {} s", pair[1].get_id(), pair[0].get_id(), sim.time());
+            closest = closest.min(gap);
+        }
+    }
+
+    // Everyone has caught up with the leader and sits in its queue at its speed
+    assert!(closest < 15.0, "never caught up, closest {closest} m");
+    for follower in &followers {
+        let car = sim.road_graph.get_cars()[&follower.get_id()].read().unwrap();
+        assert!((car.velocity - 5.0).abs() < 0.5, "{:?} going {} m/s", car.get_id(), car.velocity);
+    }
+}
+
+#[test]
+fn cars_keep_to_the_limit_as_their_drivers_read_it() {
+    for compliance in [1.0, 1.1, 0.8] {
+        let mut graph = single_road(2000.0, 50.0, 0.0);
+        let car = place(&mut graph, 0.0, 0.0);
+        graph.get_cars()[&car.get_id()].write().unwrap().compliance = compliance;
+        let limit = graph.get_roads()[&RoadID(0)].read().unwrap().max_speed() * compliance;
+
+        let mut sim = Simulation::new(graph, 0.1);
+        let mut fastest: f32 = 0.0;
+        for _ in 0..600 {
+            sim.step();
+            fastest = fastest.max(sim.road_graph.get_cars()[&car.get_id()].read().unwrap().velocity);
+        }
+
+        assert!(fastest <= limit + 0.01 && fastest > limit - 0.5, "compliance {compliance}: {fastest} m/s against {limit}");
+    }
+}
+
+#[test]
+fn cars_slow_down_for_bends() {
+    let mut graph = single_road(600.0, 80.0, 300.0);
+    let car = place(&mut graph, 0.0, 0.0);
+    let limit = graph.get_roads()[&RoadID(0)].read().unwrap().max_speed();
+
+    let mut sim = Simulation::new(graph, 0.1);
+    let (mut tightest, mut speed_there, mut fastest) = (0.0, 0.0, 0.0_f32);
+    while sim.road_graph.get_cars().contains_key(&car.get_id()) {
+        sim.step();
+        let Some(car) = sim.road_graph.get_cars().get(&car.get_id()) else { break };
+        let car = car.read().unwrap();
+        let curvature = sim.road_graph.get_roads()[&RoadID(0)].read().unwrap().curvature_at(car.along);
+
+        // Sideways acceleration stays comfortable. Drivers aim for 2 m/s², judging bends at
+        // the road's points, so a little more in between them
+        let lateral = car.velocity * car.velocity * curvature;
+        assert!(lateral < 2.2, "{lateral} m/s² at {} m, {} m/s", car.along, car.velocity);
+
+        if curvature > tightest {
+            (tightest, speed_there) = (curvature, car.velocity);
+        }
+        fastest = fastest.max(car.velocity);
+        assert!(sim.time() < 120.0, "never got to the end");
+    }
+
+    assert!(speed_there < fastest - 1.0 && speed_there < limit * 0.8, "{speed_there} m/s in the bend, {fastest} m/s at most");
+}
@@ -0,0 +1,61 @@
+use cars_and_roads::{CostModel, Distance, Node, NodeID, Road, RoadID, SimConfig, Vec2};
+
+
+fn road(curviness: f32) -> Road {
+    let (a, b) = (Node::new_node(NodeID(0), Vec2::new(0.0, 0.0)), Node::new_node(NodeID(1), Vec2::new(400.0, 300.0)));
+    Road::new_road_with_curves(RoadID(0), a, b, 50, 50.0, curviness, &mut SimConfig::new(0).rng())
+}
+
+
+#[test]
+fn length_is_measured_along_the_curve() {
+    let straight = road(0.0);
+    let curved = road(80.0);
+
+    assert!((straight.length - 500.0).abs() < 0.01, "{}", straight.length);
+    assert!(curved.length > straight.length + 1.0, "{} vs {}", curved.length, straight.length);
+    assert_eq!(Distance.cost(&curved), curved.length);
+
+    let arc_lengths = curved.arc_lengths();
+    assert_eq!(arc_lengths.len(), curved.points.len());
+    assert_eq!(arc_lengths[0], 0.0);
+    assert!(arc_lengths.windows(2).all(|pair| pair[1] >= pair[0]));
+    assert_eq!(*arc_lengths.last().unwrap(), curved.length);
+
+    let reversed = curved.reversed(RoadID(1));
+    assert!((reversed.length - curved.length).abs() < 0.01);
+}
+
+#[test]
+fn positions_follow_the_arc_length() {
+    let curved = road(80.0);
+
+    assert_eq!(curved.position_at(0.0), curved.points[0]);
+    assert_eq!(curved.position_at(curved.length), *curved.points.last().unwrap());
+    assert_eq!(curved.position_at(-10.0), curved.position_at(0.0));
+    assert_eq!(curved.position_at(curved.length + 10.0), curved.position_at(curved.length));
+
+    // Walking the road in small steps covers its length, facing along the steps
+    let steps = 1000;
+    let mut walked = 0.0;
+    for step in 0..steps {
+        let (s, next) = (curved.length * step as f32 / steps as f32, curved.length * (step + 1) as f32 / steps as f32);
+        let (here, there) = (curved.position_at(s), curved.position_at(next));
+        walked += here.distance(there);
+
+        // Steps across a corner of the polyline lean into the next segment a little
+        let tangent = curved.tangent_at(s);
+        assert!((tangent.length() - 1.0).abs() < 1e-4);
+        assert!(tangent.dot((there - here).normalize()) > 0.9, "at {s}");
+    }
+    assert!((walked - curved.length).abs() < 0.1, "{walked} vs {}", curved.length);
+}
+
+#[test]
+fn curvature_is_zero_on_straight_roads() {
+    let (straight, curved) = (road(0.0), road(80.0));
+
+    assert!((0..=10).all(|i| straight.curvature_at(straight.length * i as f32 / 10.0) < 1e-4));
+    assert!(curved.curvature_at(curved.length / 4.0) > 1e-4);
+    assert_eq!(curved.curvature_at(0.0), 0.0);
+}
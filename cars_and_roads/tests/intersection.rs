@@ -0,0 +1,169 @@
+use cars_and_roads::level::Level;
+use cars_and_roads::{Approach, Aspect, CarID, PriorityControl, RoadID, SignalController, SignalPhase};
+
+
+/// Car `car` on road `road` heading for road 9, `distance` from the line at `speed`.
+fn approach(car: i32, road: i32, distance: f32, speed: f32) -> Approach {
+    Approach { road: RoadID(road), next_road: RoadID(9), car: CarID(car), distance, speed, braking: 1.5 }
+}
+
+/// Every movement crosses every other, so only one car may go at a time.
+fn everything_crosses(_: (RoadID, RoadID), _: (RoadID, RoadID)) -> bool {
+    true
+}
+
+fn granted(control: &PriorityControl, cars: &[i32]) -> Vec<bool> {
+    cars.iter().map(|car| control.is_granted(CarID(*car))).collect()
+}
+
+/// North-south on roads 0 and 1 for 10 s, then east-west on roads 2 and 3 for 5 s, with 3 s
+/// of amber and 2 s of all-red after each.
+fn crossroads_phases() -> Vec<SignalPhase> {
+    vec![
+        SignalPhase::new(vec![(RoadID(0), RoadID(5)), (RoadID(1), RoadID(4))], 10.0, 3.0, 2.0),
+        SignalPhase::new(vec![(RoadID(2), RoadID(7)), (RoadID(3), RoadID(6))], 5.0, 3.0, 2.0),
+    ]
+}
+
+/// Runs `signal` for `seconds` in half-second ticks, with `demand` saying which phases have
+/// cars waiting, and returns what north-south saw after each tick.
+fn watch(signal: &mut SignalController, seconds: f32, demand: impl Fn(&SignalPhase) -> bool) -> Vec<Aspect> {
+    (0..(seconds / 0.5) as usize).map(|_| {
+        signal.update(0.5, &demand);
+        signal.aspect(RoadID(0), RoadID(5))
+    }).collect()
+}
+
+/// How many ticks in a row from the start of `aspects` show `aspect`.
+fn run_of(aspects: &[Aspect], aspect: Aspect) -> usize {
+    aspects.iter().take_while(|shown| **shown == aspect).count()
+}
+
+
+#[test]
+fn minor_roads_go_in_the_order_they_stopped() {
+    let mut control = PriorityControl::two_way_stop(vec![RoadID(0)]);
+
+    // Car 1 stops first, car 2 a second later, and road 2 would win a tie
+    control.update(0.1, &[approach(1, 1, 1.0, 0.0), approach(2, 2, 20.0, 5.0)], everything_crosses);
+    assert_eq!(granted(&control, &[1, 2]), [true, false]);
+
+    control.update(1.0, &[approach(1, 1, 1.0, 0.0), approach(2, 2, 1.0, 0.0)], everything_crosses);
+    assert_eq!(granted(&control, &[1, 2]), [true, false]);
+
+    // Once it has gone, the other one gets its turn
+    control.update(0.1, &[approach(2, 2, 1.0, 0.0)], everything_crosses);
+    assert_eq!(granted(&control, &[2]), [true]);
+}
+
+#[test]
+fn yield_roads_go_in_the_order_they_turned_up() {
+    let mut control = PriorityControl::yield_to(vec![RoadID(0)]);
+
+    control.update(0.1, &[approach(1, 2, 60.0, 10.0)], everything_crosses);
+    control.update(0.1, &[approach(1, 2, 59.0, 10.0), approach(2, 1, 30.0, 10.0)], everything_crosses);
+    assert_eq!(granted(&control, &[1, 2]), [true, false]);
+}
+
+#[test]
+fn minor_roads_give_way_to_major_ones() {
+    let mut control = PriorityControl::two_way_stop(vec![RoadID(0)]);
+
+    // Priority traffic close by holds the stopped car, far off it leaves a gap
+    control.update(0.1, &[approach(1, 1, 1.0, 0.0), approach(2, 0, 30.0, 15.0)], everything_crosses);
+    assert_eq!(granted(&control, &[1, 2]), [false, true]);
+
+    control.update(0.1, &[approach(1, 1, 1.0, 0.0), approach(3, 0, 200.0, 15.0)], everything_crosses);
+    assert_eq!(granted(&control, &[1, 3]), [true, true]);
+
+    // Nobody stops for a movement that doesn't cross theirs
+    let mut control = PriorityControl::two_way_stop(vec![RoadID(0)]);
+    control.update(0.1, &[approach(1, 1, 1.0, 0.0), approach(2, 0, 30.0, 15.0)], |_, _| false);
+    assert_eq!(granted(&control, &[1, 2]), [true, true]);
+}
+
+#[test]
+fn crossing_major_roads_take_turns_by_who_gets_there_first() {
+    let mut control = PriorityControl::two_way_stop(vec![RoadID(0), RoadID(1)]);
+
+    control.update(0.1, &[approach(1, 0, 100.0, 10.0), approach(2, 1, 40.0, 10.0)], everything_crosses);
+    assert_eq!(granted(&control, &[1, 2]), [false, true]);
+
+    // A car already committed to the node keeps going, and nobody goes across it
+    control.update(0.1, &[approach(1, 0, 12.0, 10.0), approach(2, 1, 2.0, 10.0)], everything_crosses);
+    assert_eq!(granted(&control, &[1, 2]), [false, true]);
+}
+
+#[test]
+fn all_way_stops_go_in_the_order_they_stopped() {
+    let mut control = PriorityControl::all_way_stop();
+
+    control.update(0.1, &[approach(1, 3, 1.0, 0.0), approach(2, 0, 10.0, 3.0)], everything_crosses);
+    control.update(0.5, &[approach(1, 3, 1.0, 0.0), approach(2, 0, 1.0, 0.0)], everything_crosses);
+    assert_eq!(granted(&control, &[1, 2]), [true, false]);
+
+    // Stopping at the same moment, the lower road ID goes first
+    let mut control = PriorityControl::all_way_stop();
+    control.update(0.1, &[approach(1, 3, 1.0, 0.0), approach(2, 0, 1.0, 0.0)], everything_crosses);
+    assert_eq!(granted(&control, &[1, 2]), [false, true]);
+}
+
+#[test]
+fn everyone_gets_round_the_roundabout() {
+    let level = Level::load(concat!(env!("CARGO_MANIFEST_DIR"), "/levels/roundabout.json")).unwrap();
+    let cars = level.road_graph.get_cars().len();
+    let mut sim = level.into_simulation();
+    sim.demand = None;
+
+    while !sim.road_graph.get_cars().is_empty() && sim.time() < 1000.0 {
+        sim.step();
+    }
+    assert_eq!(sim.trips_completed() as usize, cars, "{} cars left", sim.road_graph.get_cars().len());
+}
+
+#[test]
+fn fixed_time_signals_keep_to_their_plan() {
+    let mut signal = SignalController::fixed_time(crossroads_phases());
+    assert_eq!(signal.aspect(RoadID(0), RoadID(5)), Aspect::Green);
+    assert_eq!(signal.aspect(RoadID(2), RoadID(7)), Aspect::Red);
+    assert_eq!(signal.aspect(RoadID(0), RoadID(6)), Aspect::Red, "not a movement of the phase");
+
+    // Green for 10 s and amber for 3 s, then red for the 2 s of all-red and east-west's 10 s
+    let seen = watch(&mut signal, 30.0, |_| false);
+    assert_eq!(run_of(&seen, Aspect::Green), 19);
+    assert_eq!(run_of(&seen[19..], Aspect::Amber), 6);
+    assert_eq!(run_of(&seen[25..], Aspect::Red), 24);
+    assert_eq!(seen[49..], [Aspect::Green; 11]);
+    assert_eq!(signal.current_phase(), 0);
+}
+
+#[test]
+fn actuated_signals_hold_green_for_traffic_and_hand_over_when_it_stops() {
+    let north_south = |phase: &SignalPhase| phase.allows(RoadID(0), RoadID(5));
+
+    // Nobody else waiting, green stays however long it runs
+    let mut signal = SignalController::actuated(crossroads_phases(), 5.0, 20.0);
+    assert!(watch(&mut signal, 60.0, |_| false).iter().all(|aspect| *aspect == Aspect::Green));
+
+    // Cars keep coming both ways: held to the maximum green
+    let mut signal = SignalController::actuated(crossroads_phases(), 5.0, 20.0);
+    assert_eq!(run_of(&watch(&mut signal, 30.0, |_| true), Aspect::Green), 39);
+
+    // Nobody left on north-south but someone across: handed over at the minimum green
+    let mut signal = SignalController::actuated(crossroads_phases(), 5.0, 20.0);
+    assert_eq!(run_of(&watch(&mut signal, 30.0, |phase| !north_south(phase)), Aspect::Green), 9);
+
+    // Phases nobody is waiting on are skipped
+    let mut phases = crossroads_phases();
+    phases.push(SignalPhase::new(vec![(RoadID(0), RoadID(7))], 10.0, 3.0, 2.0));
+    let mut signal = SignalController::actuated(phases, 5.0, 20.0);
+
+    let mut order = vec![signal.current_phase()];
+    for _ in 0..200 {
+        signal.update(0.5, |phase: &SignalPhase| !phase.allows(RoadID(0), RoadID(7)));
+        if order.last() != Some(&signal.current_phase()) {
+            order.push(signal.current_phase());
+        }
+    }
+    assert_eq!(order[..4], [0, 1, 0, 1]);
+}
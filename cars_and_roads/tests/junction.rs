@@ -0,0 +1,89 @@
+use cars_and_roads::{generate_bezier, Car, NodeID, RoadGraph, RoadID, SimConfig, Simulation, Vec2};
+
+mod common;
+use common::network;
+
+
+/// Node 0 in the middle with two-way, gently curved arms out to nodes 1 (west), 2 (east)
+/// and 3 (south). Roads 0 to 2 run in towards the middle.
+fn tee() -> RoadGraph {
+    let nodes = [(0.0, 0.0), (-300.0, 0.0), (300.0, 0.0), (0.0, 300.0)];
+    let mut graph = network(&nodes, &[(1, 0), (2, 0), (3, 0)], |road| road.one_way = false);
+    for id in 0..3 {
+        graph.set_curviness(RoadID(id), 20.0).unwrap();
+    }
+    graph
+}
+
+
+#[test]
+fn bezier_controls_scale_with_the_road() {
+    let [start, control1, control2, end] = generate_bezier(Vec2::ZERO, Vec2::new(400.0, 0.0), 0.0);
+    assert_eq!((start, end), (Vec2::ZERO, Vec2::new(400.0, 0.0)));
+    assert_eq!((control1, control2), (Vec2::new(100.0, 0.0), Vec2::new(300.0, 0.0)));
+}
+
+#[test]
+fn roads_stop_short_of_junctions_only() {
+    let graph = tee();
+
+    for road in graph.roads_to_iter() {
+        let road = road.read().unwrap();
+        let (start, end) = (road.points[0], *road.points.last().unwrap());
+
+        // Dead ends are no junction, the middle is
+        let (outer, inner) = if road.to.id == NodeID(0) { (start, end) } else { (end, start) };
+        let outer_node = if road.to.id == NodeID(0) { road.from } else { road.to };
+        assert!(outer.distance(outer_node.position) < 0.01, "road {:?}", road.id);
+        assert!(inner.distance(Vec2::ZERO) > 5.0, "road {:?}", road.id);
+
+        assert!(road.route_length() >= road.from.position.distance(road.to.position), "road {:?}", road.id);
+    }
+}
+
+#[test]
+fn turns_join_both_roads_without_a_kink() {
+    let graph = tee();
+    let connectors = graph.connectors(NodeID(0));
+
+    // Every movement into and out of the middle but the U-turns
+    assert_eq!(connectors.len(), 6);
+
+    for connector in connectors {
+        let from = graph.get_roads()[&connector.from].read().unwrap();
+        let to = graph.get_roads()[&connector.to].read().unwrap();
+
+        assert!(connector.position_at(0.0).distance(from.position_at(from.length)) < 0.01);
+        assert!(connector.position_at(connector.length).distance(to.position_at(0.0)) < 0.01);
+        assert!(connector.tangent_at(0.0).dot(from.tangent_at(from.length)) > 0.99, "{:?} -> {:?}", from.id, to.id);
+        assert!(connector.tangent_at(connector.length).dot(to.tangent_at(0.0)) > 0.99, "{:?} -> {:?}", from.id, to.id);
+    }
+}
+
+#[test]
+fn cars_drive_the_turn_without_jumping() {
+    let mut graph = tee();
+    let car = Car::new_on_road(None, RoadID(0), &mut graph, 10.0, NodeID(3), &mut SimConfig::new(0).rng());
+    graph.add_car(car.clone()).unwrap();
+
+    let mut sim = Simulation::new(graph, 0.1);
+    let mut last = (car.position, car.get_direction());
+    let mut turned = false;
+
+    while sim.road_graph.get_cars().contains_key(&car.get_id()) && sim.time() < 120.0 {
+        sim.step();
+        let Some(car) = sim.road_graph.get_cars().get(&car.get_id()) else { break };
+        let car = car.read().unwrap();
+
+        let road_length = sim.road_graph.get_roads()[&car.current_road].read().unwrap().length;
+        turned |= car.along > road_length;
+
+        let heading_step = (car.get_direction() - last.1 + std::f32::consts::PI).rem_euclid(std::f32::consts::TAU) - std::f32::consts::PI;
+        assert!(heading_step.abs() < 0.2, "heading jumped {heading_step} at {:?}", car.position);
+        assert!(car.position.distance(last.0) < 0.1 * 20.0, "moved {} m in a tick", car.position.distance(last.0));
+        last = (car.position, car.get_direction());
+    }
+
+    assert!(turned, "never drove the turn");
+    assert_eq!(sim.take_trips().len(), 1);
+}
@@ -0,0 +1,69 @@
+use cars_and_roads::{Car, NodeID, RoadGraph, RoadID, SimConfig, Simulation};
+
+mod common;
+use common::network;
+
+
+/// One-way roads only: road 0 comes up from the south into node 0 with `lanes` lanes, and
+/// roads 1, 2 and 3 leave it west, north and east, two lanes each.
+fn crossroads(lanes: usize) -> RoadGraph {
+    let nodes = [(0.0, 0.0), (0.0, 400.0), (-400.0, 0.0), (0.0, -400.0), (400.0, 0.0)];
+    network(&nodes, &[(1, 0), (0, 2), (0, 3), (0, 4)], |road| {
+        road.lanes = if road.id == RoadID(0) { lanes } else { 2 };
+    })
+}
+
+const WEST: RoadID = RoadID(1);
+const NORTH: RoadID = RoadID(2);
+const EAST: RoadID = RoadID(3);
+
+
+#[test]
+fn turns_are_shared_out_over_the_lanes_left_to_right() {
+    let graph = crossroads(3);
+    assert_eq!(graph.lane_connections(RoadID(0), WEST), vec![(0, 0)]);
+    assert_eq!(graph.lane_connections(RoadID(0), NORTH), vec![(1, 0)]);
+    assert_eq!(graph.lane_connections(RoadID(0), EAST), vec![(2, 1)]);
+
+    // Fewer lanes than turns: straight on from either lane, the turns from the lane on their side
+    let graph = crossroads(2);
+    assert_eq!(graph.lane_connections(RoadID(0), WEST), vec![(0, 0)]);
+    assert_eq!(graph.lane_connections(RoadID(0), NORTH), vec![(0, 0), (1, 1)]);
+    assert_eq!(graph.lane_connections(RoadID(0), EAST), vec![(1, 1)]);
+    assert_eq!(graph.lane_exits(RoadID(0), 0), vec![WEST, NORTH]);
+    assert_eq!(graph.lane_exits(RoadID(0), 1), vec![NORTH, EAST]);
+
+    // Roads that don't meet have no connections
+    assert!(graph.lane_connections(WEST, EAST).is_empty());
+}
+
+#[test]
+fn cars_turn_into_the_lane_on_their_side() {
+    let graph = crossroads(3);
+    assert_eq!(graph.entry_lane(RoadID(0), 0, WEST), 0);
+    assert_eq!(graph.entry_lane(RoadID(0), 2, EAST), 1);
+
+    // From a lane that doesn't lead there, the same place across the road
+    assert_eq!(graph.entry_lane(RoadID(0), 0, EAST), 0);
+    assert_eq!(graph.entry_lane(RoadID(0), 2, WEST), 1);
+}
+
+#[test]
+fn cars_change_into_a_lane_for_their_turn() {
+    let mut graph = crossroads(3);
+    let mut car = Car::new_on_road(None, RoadID(0), &mut graph, 10.0, NodeID(4), &mut SimConfig::new(0).rng());
+    car.lane = 0;
+    graph.add_car(car.clone()).unwrap();
+
+    let mut sim = Simulation::new(graph, 0.1);
+    let mut last_lane = 0;
+    while sim.road_graph.get_cars()[&car.get_id()].read().unwrap().current_road == RoadID(0) {
+        last_lane = sim.road_graph.get_cars()[&car.get_id()].read().unwrap().lane;
+        sim.step();
+        assert!(sim.time() < 120.0, "never left the first road");
+    }
+
+    let car = sim.road_graph.get_cars()[&car.get_id()].read().unwrap();
+    assert_eq!(last_lane, 2);
+    assert_eq!((car.current_road, car.lane), (EAST, 1));
+}
@@ -0,0 +1,99 @@
+use cars_and_roads::level::{Level, LevelData, LevelError, NodeData};
+use cars_and_roads::{GraphError, Issue, NodeID, Obstruction, ObstructionKind, ReroutePolicy, Simulation, ValidationMode};
+
+
+const LEVELS: [&str; 4] = ["sim1", "sim2", "sim3", "roundabout"];
+
+fn path(name: &str) -> String {
+    format!("{}/levels/{name}.json", env!("CARGO_MANIFEST_DIR"))
+}
+
+
+#[test]
+fn shipped_levels_round_trip() {
+    for name in LEVELS {
+        let level = Level::load(path(name)).unwrap_or_else(|error| panic!("{name}: {error}"));
+        assert!(!level.road_graph.get_cars().is_empty(), "{name}");
+
+        let saved = std::env::temp_dir().join(format!("cars_and_roads_{name}_{}.json", std::process::id()));
+        level.save(&saved).unwrap();
+        let reloaded = Level::load(&saved).unwrap();
+        std::fs::remove_file(&saved).ok();
+
+        assert_eq!(reloaded.to_data(), level.to_data(), "{name}");
+        assert_eq!(reloaded.road_graph.get_roads().len(), level.road_graph.get_roads().len(), "{name}");
+        for (id, road) in level.road_graph.get_roads() {
+            let (road, other) = (road.read().unwrap(), reloaded.road_graph.get_roads()[id].read().unwrap());
+            assert_eq!((road.from.id, road.to.id, road.twin, &road.points), (other.from.id, other.to.id, other.twin, &other.points), "{name}, road {id:?}");
+        }
+    }
+}
+
+#[test]
+fn a_running_level_saves_and_loads_where_it_was() {
+    let level = Level::load(path("sim2")).unwrap();
+    let (config, dt) = (level.config, level.dt);
+    let mut sim = Simulation::new(level.road_graph, dt);
+    sim.run_ticks(600);
+
+    let busiest = sim.road_graph.roads_to_iter()
+        .map(|road| road.read().unwrap())
+        .max_by_key(|road| (road.vehicles_on.len(), -road.id.0))
+        .map(|road| road.id)
+        .unwrap();
+    sim.road_graph.add_obstruction(busiest, Obstruction::closure(ObstructionKind::Accident, 400.0, Some(90.0))).unwrap();
+
+    let first = sim.road_graph.get_cars().keys().min_by_key(|id| id.0).copied().unwrap();
+    {
+        let mut car = sim.road_graph.get_cars()[&first].write().unwrap();
+        car.idm.time_headway = 2.5;
+        car.compliance = 0.9;
+        car.reroute = ReroutePolicy::Periodic(30.0);
+    }
+
+    let level = Level { road_graph: sim.road_graph, demand: None, config, dt, validation: ValidationMode::Warn, issues: Vec::new() };
+    let saved = std::env::temp_dir().join(format!("cars_and_roads_running_{}.json", std::process::id()));
+    level.save(&saved).unwrap();
+    let reloaded = Level::load(&saved).unwrap();
+    std::fs::remove_file(&saved).ok();
+
+    assert_eq!(reloaded.to_data(), level.to_data());
+    assert_eq!(reloaded.to_data().obstructions.len(), 1);
+
+    let mut ids: Vec<_> = level.road_graph.get_cars().keys().copied().collect();
+    ids.sort_by_key(|id| id.0);
+    assert!(ids.len() < 30 && ids.last().unwrap().0 == 29, "some cars should have arrived, {ids:?}");
+
+    for id in ids {
+        let (car, other) = (level.road_graph.get_cars()[&id].read().unwrap(), reloaded.road_graph.get_cars()[&id].read().unwrap());
+        assert_eq!((car.current_road, car.lane, car.along, car.get_path()), (other.current_road, other.lane, other.along, other.get_path()), "car {id:?}");
+        assert!(car.position.distance(other.position) < 0.01, "car {id:?}");
+        assert_eq!((car.idm, car.compliance, car.reroute), (other.idm, other.compliance, other.reroute), "car {id:?}");
+    }
+}
+
+#[test]
+fn levels_must_be_consistent() {
+    let text = std::fs::read_to_string(path("sim1")).unwrap();
+    let mut data: LevelData = serde_json::from_str(&text).unwrap();
+    data.roads[0].to = NodeID(99);
+
+    assert!(matches!(Level::from_data(&data), Err(LevelError::Graph(GraphError::UnknownNode(NodeID(99))))));
+    assert!(matches!(Level::load(path("missing")), Err(LevelError::Io(_))));
+}
+
+#[test]
+fn validation_mode_decides_what_stops_a_load() {
+    let text = std::fs::read_to_string(path("sim1")).unwrap();
+    let mut data: LevelData = serde_json::from_str(&text).unwrap();
+    data.nodes.push(NodeData { id: NodeID(50), x: 0.0, y: 0.0 });
+
+    let level = Level::from_data(&data).unwrap();
+    assert_eq!(level.issues, vec![Issue::DanglingNode(NodeID(50))]);
+
+    data.sim.validation = ValidationMode::Strict;
+    assert!(matches!(Level::from_data(&data), Err(LevelError::Invalid(issues)) if issues == vec![Issue::DanglingNode(NodeID(50))]));
+
+    data.sim.validation = ValidationMode::Off;
+    assert!(Level::from_data(&data).unwrap().issues.is_empty());
+}
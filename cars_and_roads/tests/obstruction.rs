@@ -0,0 +1,121 @@
+use cars_and_roads::routing::{a_star, passable_cost};
+use cars_and_roads::{Car, FreeFlowTime, GraphError, NodeID, Obstruction, ObstructionKind, RoadGraph, RoadID, SimConfig, Simulation};
+
+mod common;
+use common::network;
+
+
+/// An entry road from node 3 into node 0, then a straight road from 0 to 1 and a longer way
+/// round via 2. All one-way.
+fn entry_then_straight_or_round() -> RoadGraph {
+    let nodes = [(0.0, 0.0), (600.0, 0.0), (300.0, 300.0), (-200.0, 0.0)];
+    network(&nodes, &[(3, 0), (0, 1), (0, 2), (2, 1)], |_| {})
+}
+
+fn spawn(graph: &mut RoadGraph, road: RoadID, destination: NodeID) -> Car {
+    let mut rng = SimConfig::new(0).rng();
+    let car = Car::new_on_road(None, road, graph, 10.0, destination, &mut rng);
+    graph.add_car(car.clone()).unwrap();
+    car
+}
+
+
+#[test]
+fn closed_roads_are_routed_round() {
+    let mut graph = entry_then_straight_or_round();
+    assert_eq!(a_star(NodeID(0), NodeID(1), &graph, &FreeFlowTime).unwrap().roads, vec![RoadID(1)]);
+
+    let closure = graph.add_obstruction(RoadID(1), Obstruction::closure(ObstructionKind::Roadworks, 100.0, None)).unwrap();
+    assert!(graph.get_roads()[&RoadID(1)].read().unwrap().is_closed());
+    assert_eq!(a_star(NodeID(0), NodeID(1), &graph, &FreeFlowTime).unwrap().roads, vec![RoadID(2), RoadID(3)]);
+
+    graph.clear_obstruction(closure).unwrap();
+    assert_eq!(a_star(NodeID(0), NodeID(1), &graph, &FreeFlowTime).unwrap().roads, vec![RoadID(1)]);
+    assert!(graph.clear_obstruction(closure).is_none());
+}
+
+#[test]
+fn partial_closures_cost_more() {
+    let mut graph = entry_then_straight_or_round();
+    graph.get_roads()[&RoadID(1)].write().unwrap().lanes = 2;
+    let clear = passable_cost(&FreeFlowTime, &graph.get_roads()[&RoadID(1)].read().unwrap()).unwrap();
+
+    graph.add_obstruction(RoadID(1), Obstruction::lanes(ObstructionKind::Accident, 100.0, vec![1], None)).unwrap();
+    let road = graph.get_roads()[&RoadID(1)].read().unwrap();
+    assert!(!road.is_closed());
+    assert_eq!(road.open_share(), 0.5);
+    assert_eq!(passable_cost(&FreeFlowTime, &road).unwrap(), clear * 2.0);
+    drop(road);
+
+    let bad_lane = Obstruction::lanes(ObstructionKind::Accident, 100.0, vec![2], None);
+    assert_eq!(graph.add_obstruction(RoadID(1), bad_lane), Err(GraphError::UnknownLane(RoadID(1), 2)));
+}
+
+#[test]
+fn cars_queue_behind_obstructions_and_reroute_round_closures() {
+    let mut graph = entry_then_straight_or_round();
+    let stuck = spawn(&mut graph, RoadID(1), NodeID(1));
+    let coming = spawn(&mut graph, RoadID(0), NodeID(1));
+
+    let mut sim = Simulation::new(graph, 0.1);
+    sim.step();
+    assert_eq!(sim.road_graph.get_cars()[&coming.get_id()].read().unwrap().get_path(), vec![RoadID(1)]);
+
+    // Closed for a minute, the car already on the road waits behind it and the one on its way
+    // goes round
+    sim.road_graph.add_obstruction(RoadID(1), Obstruction::closure(ObstructionKind::Accident, 200.0, Some(60.0))).unwrap();
+    sim.run_for(50.0);
+
+    let car = sim.road_graph.get_cars()[&stuck.get_id()].read().unwrap();
+    let road = sim.road_graph.get_roads()[&RoadID(1)].read().unwrap();
+    assert_eq!(car.current_road, RoadID(1));
+    assert!(car.along < 200.0 && car.velocity < 0.1, "{} m at {} m/s", car.along, car.velocity);
+    drop((car, road));
+
+    let mut trips = sim.take_trips();
+    while !sim.road_graph.get_cars().is_empty() && sim.time() < 300.0 {
+        sim.step();
+        trips.extend(sim.take_trips());
+    }
+
+    assert!(sim.road_graph.get_roads()[&RoadID(1)].read().unwrap().obstructions.is_empty());
+    assert_eq!(trips.len(), 2);
+    let distance = |car: &Car| trips.iter().find(|trip| trip.car == car.get_id()).unwrap().distance;
+    assert!(distance(&coming) > 200.0 + 600.0, "went round, {} m", distance(&coming));
+    assert!(distance(&stuck) < 600.0);
+}
+
+#[test]
+fn cars_already_turning_keep_their_next_road_when_it_closes() {
+    let mut graph = entry_then_straight_or_round();
+    let car = spawn(&mut graph, RoadID(0), NodeID(1));
+    let mut sim = Simulation::new(graph, 0.1);
+
+    let into_turn = |sim: &Simulation| {
+        let car = sim.road_graph.get_cars()[&car.get_id()].read().unwrap();
+        car.current_road == RoadID(0) && car.along > sim.road_graph.get_roads()[&RoadID(0)].read().unwrap().length
+    };
+    while !into_turn(&sim) {
+        sim.step();
+        assert!(sim.time() < 60.0, "never reached the junction");
+    }
+    assert_eq!(sim.road_graph.get_cars()[&car.get_id()].read().unwrap().get_path(), vec![RoadID(1)]);
+
+    // Closed behind the junction, it is too late to go round
+    sim.road_graph.add_obstruction(RoadID(1), Obstruction::closure(ObstructionKind::Accident, 400.0, Some(30.0))).unwrap();
+    assert_eq!(sim.road_graph.get_cars()[&car.get_id()].read().unwrap().get_path(), vec![RoadID(1)]);
+
+    let mut last = sim.road_graph.get_cars()[&car.get_id()].read().unwrap().position;
+    while sim.road_graph.get_cars().contains_key(&car.get_id()) && sim.time() < 300.0 {
+        sim.step();
+        let Some(car) = sim.road_graph.get_cars().get(&car.get_id()) else { break };
+        let car = car.read().unwrap();
+        assert!(car.position.distance(last) < 0.1 * 20.0, "jumped {} m", car.position.distance(last));
+        assert!(car.current_road != RoadID(2), "turned round the closure from inside the junction");
+        last = car.position;
+    }
+
+    let trips = sim.take_trips();
+    assert_eq!(trips.len(), 1);
+    assert!(trips[0].distance < 200.0 + 600.0, "went round, {} m", trips[0].distance);
+}
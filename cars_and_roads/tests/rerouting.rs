@@ -0,0 +1,99 @@
+use cars_and_roads::{Car, CarID, NodeID, ReroutePolicy, RoadGraph, RoadID, SimConfig, Simulation};
+
+mod common;
+use common::network;
+
+
+/// One-way roads: road 0 runs 400 m to node 1 and road 1 another 300 m to node 2, where the
+/// way splits. Road 2 goes straight to node 3 in 500 m, roads 3 and 4 the long way round by
+/// node 4.
+fn long_approach_then_fork() -> RoadGraph {
+    let nodes = [(-700.0, 0.0), (-300.0, 0.0), (0.0, 0.0), (500.0, 0.0), (250.0, 300.0)];
+    network(&nodes, &[(0, 1), (1, 2), (2, 3), (2, 4), (4, 3)], |_| {})
+}
+
+const DIRECT: RoadID = RoadID(2);
+const ROUND: RoadID = RoadID(3);
+
+/// Starts one car per policy on road 0, 80 m apart, all heading for node 3.
+fn drivers(graph: &mut RoadGraph, policies: &[ReroutePolicy]) -> Vec<CarID> {
+    policies.iter().enumerate().map(|(index, policy)| {
+        let mut car = Car::new_on_road(None, RoadID(0), graph, 10.0, NodeID(3), &mut SimConfig::new(0).rng());
+        car.along = 320.0 - 80.0 * index as f32;
+        car.reroute = *policy;
+        let id = car.get_id();
+        graph.add_car(car).unwrap();
+        id
+    }).collect()
+}
+
+/// Runs until everyone has arrived, and returns which way each car went at the fork and how
+/// many times it changed its route.
+fn drive(sim: &mut Simulation, cars: &[CarID]) -> Vec<(RoadID, u32)> {
+    let mut seen = vec![(RoadID(-1), 0); cars.len()];
+    while !sim.road_graph.get_cars().is_empty() {
+        sim.step();
+        for (car, seen) in cars.iter().zip(&mut seen) {
+            if let Some(car) = sim.road_graph.get_cars().get(car) {
+                let car = car.read().unwrap();
+                if [DIRECT, ROUND].contains(&car.current_road) {
+                    *seen = (car.current_road, car.reroutes());
+                }
+            }
+        }
+        assert!(sim.time() < 600.0, "{} cars never arrived", sim.road_graph.get_cars().len());
+    }
+    seen
+}
+
+
+#[test]
+fn drivers_look_for_a_way_round_as_their_policy_says() {
+    let policies = [ReroutePolicy::Never, ReroutePolicy::EveryNode, ReroutePolicy::Periodic(2.0), ReroutePolicy::OnDegradation(0.2)];
+    let mut graph = long_approach_then_fork();
+    let cars = drivers(&mut graph, &policies);
+    let mut sim = Simulation::new(graph, 0.1);
+
+    // Everyone sets out planning the direct road, then it slows to a crawl
+    sim.step();
+    for car in &cars {
+        assert_eq!(sim.road_graph.get_cars()[car].read().unwrap().get_path(), vec![RoadID(1), DIRECT]);
+    }
+    sim.road_graph.set_road_limits(DIRECT, 50, 5.0).unwrap();
+
+    let went = drive(&mut sim, &cars);
+    assert_eq!(went, [(DIRECT, 0), (ROUND, 1), (ROUND, 1), (ROUND, 1)]);
+    assert_eq!(sim.trips_completed(), 4);
+    assert_eq!(sim.total_reroutes(), 3, "counts the reroutes of cars that have arrived");
+}
+
+#[test]
+fn small_slowdowns_dont_trip_a_degradation_threshold() {
+    let mut graph = long_approach_then_fork();
+    let cars = drivers(&mut graph, &[ReroutePolicy::OnDegradation(0.5), ReroutePolicy::OnDegradation(0.05)]);
+    let mut sim = Simulation::new(graph, 0.1);
+
+    // The direct road takes a fifth longer, still quicker than the long way round
+    sim.step();
+    sim.road_graph.set_road_limits(DIRECT, 50, 40.0).unwrap();
+
+    // The sensitive driver looks again, but finds nothing better
+    assert_eq!(drive(&mut sim, &cars), [(DIRECT, 0), (DIRECT, 0)]);
+    assert_eq!(sim.total_reroutes(), 0);
+}
+
+#[test]
+fn reroutes_are_counted_while_cars_are_still_driving() {
+    let mut graph = long_approach_then_fork();
+    let cars = drivers(&mut graph, &[ReroutePolicy::EveryNode]);
+    let mut sim = Simulation::new(graph, 0.1);
+    sim.step();
+    sim.road_graph.set_road_limits(DIRECT, 50, 5.0).unwrap();
+
+    while sim.road_graph.get_cars()[&cars[0]].read().unwrap().current_road != ROUND {
+        sim.step();
+        assert!(sim.time() < 120.0, "never got to the fork");
+    }
+    assert_eq!(sim.trips_completed(), 0);
+    assert_eq!(sim.total_reroutes(), 1);
+}
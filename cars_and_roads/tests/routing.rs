@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use cars_and_roads::routing::{a_star, cost_matrix, shortest_path_tree};
+use cars_and_roads::{generate_random_nodes, generate_random_roads, Bpr, CostModel, Distance, FreeFlowTime, Generalized, Node, NodeID, RemovalPolicy, RoadGraph, RoadID, RouteError, SimConfig, Vec2};
+
+mod common;
+use common::{models, network, random_graph, sorted_nodes};
+
+
+/// Cheapest cost from `start` to every node, relaxing every road once per node (Bellman-Ford).
+fn brute_force_costs(graph: &RoadGraph, start: NodeID, model: &dyn CostModel) -> HashMap<NodeID, f32> {
+    let mut costs = HashMap::from([(start, 0.0)]);
+
+    for _ in 0..graph.get_nodes().len() {
+        for road in graph.roads_to_iter() {
+            let road = road.read().unwrap();
+            let Some(&from) = costs.get(&road.from.id) else { continue };
+            let through = from + model.cost(&road);
+
+            if costs.get(&road.to.id).is_none_or(|&to| through < to) {
+                costs.insert(road.to.id, through);
+            }
+        }
+    }
+
+    costs
+}
+
+
+#[test]
+fn matches_brute_force_on_random_graphs() {
+    for (seed, model) in (0..20).flat_map(|seed| models().into_iter().map(move |model| (seed, model))) {
+        let graph = random_graph(seed, 12, 30);
+
+        for start in sorted_nodes(&graph) {
+            let expected = brute_force_costs(&graph, start, model.as_ref());
+
+            for goal in sorted_nodes(&graph) {
+                let result = a_star(start, goal, &graph, model.as_ref());
+
+                match expected.get(&goal) {
+                    _ if start == goal => assert_eq!(result, Err(RouteError::StartIsGoal(start))),
+                    None => assert_eq!(result, Err(RouteError::Unreachable(start, goal)), "seed {seed}, {start} -> {goal}"),
+                    Some(&cost) => {
+                        let route = result.unwrap_or_else(|error| panic!("seed {seed}, {model:?}, {start} -> {goal}: {error}"));
+                        assert!((route.cost - cost).abs() <= cost * 1e-4, "seed {seed}, {model:?}, {start} -> {goal}: {} vs {}", route.cost, cost);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn routes_are_connected_and_costed() {
+    let model = Bpr::default();
+
+    for seed in 0..20 {
+        let graph = random_graph(seed, 12, 30);
+
+        for start in sorted_nodes(&graph) {
+            for goal in sorted_nodes(&graph) {
+                let Ok(route) = a_star(start, goal, &graph, &model) else { continue };
+
+                assert_eq!(route.nodes.first(), Some(&start));
+                assert_eq!(route.nodes.last(), Some(&goal));
+                assert_eq!(route.roads.len() + 1, route.nodes.len());
+
+                let mut cost = 0.0;
+                for (index, id) in route.roads.iter().enumerate() {
+                    let road = graph.get_roads()[id].read().unwrap();
+                    assert_eq!(road.from.id, route.nodes[index]);
+                    assert_eq!(road.to.id, route.nodes[index + 1]);
+                    cost += model.cost(&road);
+                }
+                assert!((route.cost - cost).abs() <= cost * 1e-4);
+            }
+        }
+    }
+}
+
+#[test]
+fn unknown_nodes_are_errors() {
+    let graph = random_graph(0, 5, 10);
+
+    assert_eq!(a_star(NodeID(99), NodeID(0), &graph, &Distance), Err(RouteError::UnknownNode(NodeID(99))));
+    assert_eq!(a_star(NodeID(0), NodeID(99), &graph, &Distance), Err(RouteError::UnknownNode(NodeID(99))));
+}
+
+#[test]
+fn disconnected_nodes_are_unreachable() {
+    let mut rng = SimConfig::new(0).rng();
+    let mut nodes = generate_random_nodes(5, 1000.0, 1000.0, &mut rng);
+    let roads = generate_random_roads(10, &nodes, &mut rng);
+
+    let island = NodeID(nodes.len() as i32);
+    nodes.push(Node::new_node(island, Vec2::new(5000.0, 5000.0)));
+    let graph = RoadGraph::new(Some(roads), Some(nodes));
+
+    assert_eq!(a_star(NodeID(0), island, &graph, &Distance), Err(RouteError::Unreachable(NodeID(0), island)));
+}
+
+#[test]
+fn drivers_weigh_tolls_against_time() {
+    // Straight to node 1 with a toll on, or round by node 2 for free
+    let graph = network(&[(0.0, 0.0), (2000.0, 0.0), (1000.0, 1500.0)], &[(0, 1), (0, 2), (2, 1)], |road| {
+        road.speed_limit = 60.0;
+        road.toll = if road.id == RoadID(0) { 5.0 } else { 0.0 };
+    });
+
+    let in_a_hurry = a_star(NodeID(0), NodeID(1), &graph, &Generalized::new(200.0, 0.0)).unwrap();
+    let thrifty = a_star(NodeID(0), NodeID(1), &graph, &Generalized::new(5.0, 0.0)).unwrap();
+
+    assert_eq!(in_a_hurry.roads, vec![RoadID(0)]);
+    assert_eq!(thrifty.roads, vec![RoadID(1), RoadID(2)]);
+}
+
+#[test]
+fn trees_agree_with_a_star() {
+    for (seed, model) in (0..10).flat_map(|seed| models().into_iter().map(move |model| (seed, model))) {
+        let graph = random_graph(seed, 12, 30);
+        let nodes = sorted_nodes(&graph);
+        let matrix = cost_matrix(&nodes, &nodes, &graph, model.as_ref()).unwrap();
+
+        for (i, &start) in nodes.iter().enumerate() {
+            let tree = shortest_path_tree(start, &graph, model.as_ref()).unwrap();
+
+            for (j, &goal) in nodes.iter().enumerate() {
+                let expected = a_star(start, goal, &graph, model.as_ref());
+
+                match &expected {
+                    Ok(route) => {
+                        let found = tree.route_to(goal).unwrap();
+                        assert!((found.cost - route.cost).abs() <= route.cost * 1e-4, "seed {seed}, {model:?}, {start} -> {goal}");
+                        assert!((matrix[i][j].unwrap() - route.cost).abs() <= route.cost * 1e-4);
+                    }
+                    Err(RouteError::StartIsGoal(_)) => assert_eq!(matrix[i][j], Some(0.0)),
+                    Err(_) => {
+                        assert_eq!(tree.route_to(goal), expected);
+                        assert_eq!(matrix[i][j], None);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn cached_routes_go_stale_with_the_costs() {
+    let mut graph = random_graph(3, 12, 30);
+    let model = Bpr::new(3.0, 1.0);
+    let (start, goal) = (NodeID(0), NodeID(5));
+
+    let first = graph.route(start, goal, &model);
+    assert_eq!(first, a_star(start, goal, &graph, &model));
+    assert_eq!(graph.route(start, goal, &model), first);
+    assert_eq!(graph.route_cache_stats(), (1, 1));
+
+    // Another model is another entry, and so is the same kind of model tuned differently
+    graph.route(start, goal, &Distance).ok();
+    graph.route(start, goal, &Bpr::new(3.0, 2.0)).ok();
+    graph.route(start, goal, &Bpr::new(3.0, 1.0)).ok();
+    assert_eq!(graph.cached_routes(), 3);
+    assert_eq!(graph.route_cache_stats(), (2, 3));
+
+    // Nobody is actually driving, so the random densities all fall straight to zero
+    let epoch = graph.cost_epoch();
+    graph.set_density_window(0.0);
+    graph.update_density(0.1);
+    assert!(graph.cost_epoch() > epoch);
+
+    graph.route(start, goal, &model).ok();
+    assert_eq!((graph.route_cache_stats().1, graph.cached_routes()), (4, 1));
+    assert_eq!(graph.route(start, goal, &model), a_star(start, goal, &graph, &model));
+}
+
+#[test]
+fn the_top_speed_follows_the_fastest_road() {
+    let mut graph = network(&[(0.0, 0.0), (1000.0, 0.0), (1000.0, 1000.0)], &[(0, 1), (1, 2)], |road| {
+        road.speed_limit = if road.id == RoadID(0) { 90.0 } else { 36.0 };
+    });
+    assert_eq!(graph.top_speed(), 25.0);
+
+    // Slowing the fastest road down lets the next one lead
+    graph.set_road_limits(RoadID(0), 50, 18.0).unwrap();
+    assert_eq!(graph.top_speed(), 10.0);
+
+    graph.set_road_limits(RoadID(0), 50, 108.0).unwrap();
+    assert_eq!(graph.top_speed(), 30.0);
+    graph.remove_road(RoadID(0), RemovalPolicy::Reject).unwrap();
+    assert_eq!(graph.top_speed(), 10.0);
+
+    // Routes are still priced the same as the roads say
+    let route = a_star(NodeID(1), NodeID(2), &graph, &FreeFlowTime).unwrap();
+    assert_eq!(route.cost, FreeFlowTime.cost(&graph.get_roads()[&RoadID(1)].read().unwrap()));
+}
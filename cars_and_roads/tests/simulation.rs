@@ -0,0 +1,376 @@
+use cars_and_roads::level::{Level, LevelData};
+use cars_and_roads::{generate_random_nodes, generate_random_roads, Car, CarID, Demand, GraphError, Node, NodeID, Obstruction, ObstructionKind, OdMatrix, RemovalPolicy, Road, RoadGraph, RoadID, SimConfig, Simulation, SpawnPolicy, Vec2};
+
+mod common;
+use common::network;
+
+
+#[test]
+fn arrived_cars_leave_and_report_their_trips() {
+    let level = Level::load(concat!(env!("CARGO_MANIFEST_DIR"), "/levels/sim2.json")).unwrap();
+    let mut sim = Simulation::new(level.road_graph, level.dt);
+
+    let mut trips = Vec::new();
+    while !sim.road_graph.get_cars().is_empty() && sim.time() < 1000.0 {
+        sim.step();
+        trips.extend(sim.take_trips());
+    }
+
+    assert_eq!(trips.len(), 30);
+    assert_eq!(sim.trips_completed(), 30);
+    assert!(sim.road_graph.roads_to_iter().all(|road| road.read().unwrap().vehicles_on.is_empty()));
+
+    for trip in &trips {
+        assert!(trip.arrive_time > trip.depart_time && trip.arrive_time <= sim.time());
+        assert!(trip.distance > 0.0);
+    }
+}
+
+/// Where every car is, in ID order.
+fn snapshot(sim: &Simulation) -> Vec<(i32, RoadID, f32, Vec2)> {
+    let mut cars: Vec<_> = sim.road_graph.cars_to_iter()
+        .map(|car| car.read().unwrap())
+        .map(|car| (car.get_id().0, car.current_road, car.along, car.position))
+        .collect();
+    cars.sort_by_key(|car| car.0);
+    cars
+}
+
+#[test]
+fn frame_times_dont_change_the_run() {
+    let load = || {
+        let level = Level::load(concat!(env!("CARGO_MANIFEST_DIR"), "/levels/sim2.json")).unwrap();
+        Simulation::new(level.road_graph, level.dt)
+    };
+
+    // Frames of all sorts of lengths, some shorter than a tick and some many ticks long
+    let mut framed = load();
+    let frames = [0.016, 0.033, 0.007, 0.25, 0.1, 0.0, 1.3, 0.05];
+    let mut elapsed = 0.0;
+    for frame in frames.iter().cycle().take(400) {
+        let before = framed.ticks();
+        let ran = framed.run_for(*frame);
+        assert_eq!(framed.ticks(), before + ran);
+        elapsed += frame;
+    }
+    assert!((framed.ticks() as f32 - elapsed / framed.dt()).abs() <= 1.0, "{} ticks in {elapsed} s", framed.ticks());
+
+    let mut ticked = load();
+    ticked.run_ticks(framed.ticks());
+
+    assert_eq!(ticked.time(), framed.time());
+    assert_eq!(snapshot(&ticked), snapshot(&framed));
+    assert_eq!(ticked.trips_completed(), framed.trips_completed());
+}
+
+/// A random network with random cars on it, built from `seed`, and where the cars are at
+/// every tick of a minute's run.
+fn seeded_run(seed: u64) -> Vec<Vec<(i32, RoadID, f32, Vec2)>> {
+    let config = SimConfig::new(seed);
+    let mut rng = config.rng();
+
+    let nodes = generate_random_nodes(8, 1000.0, 1000.0, &mut rng);
+    let roads = generate_random_roads(16, &nodes, &mut rng);
+    let mut graph = RoadGraph::new(Some(roads), Some(nodes.clone()));
+
+    let mut road_ids: Vec<RoadID> = graph.get_roads().keys().copied().collect();
+    road_ids.sort_by_key(|id| id.0);
+    for index in 0..10 {
+        let road = road_ids[index * 7 % road_ids.len()];
+        let destination = nodes[index * 3 % nodes.len()].id;
+        let car = Car::new_on_road(None, road, &mut graph, 10.0, destination, &mut rng);
+        graph.add_car(car).unwrap();
+    }
+
+    let mut sim = Simulation::new(graph, 0.1);
+    (0..600).map(|_| {
+        sim.step();
+        snapshot(&sim)
+    }).collect()
+}
+
+#[test]
+fn the_seed_decides_the_run() {
+    let run = seeded_run(7);
+    assert_ne!(run.first(), run.last(), "nothing moved");
+
+    assert_eq!(run, seeded_run(7));
+    assert_ne!(run, seeded_run(8));
+}
+
+fn corridor(capacity: i32) -> RoadGraph {
+    network(&[(0.0, 0.0), (500.0, 0.0)], &[(0, 1)], |road| road.capacity = capacity)
+}
+
+fn demand(per_hour: f32, policy: SpawnPolicy) -> Demand {
+    let mut matrix = OdMatrix::new();
+    matrix.set(NodeID(0), NodeID(1), per_hour);
+    Demand::new(matrix).with_policy(policy)
+}
+
+#[test]
+fn demand_spawns_at_the_matrix_rate() {
+    let config = SimConfig::new(3);
+    let mut sim = Simulation::new(corridor(100), 0.1).with_demand(demand(600.0, SpawnPolicy::Queue), &config);
+    sim.run_ticks(36_000);
+
+    // An hour at 600 trips an hour, Poisson spread is about 25
+    let stats = sim.demand.as_ref().unwrap().stats();
+    assert!((500..700).contains(&stats.requested), "{stats:?}");
+    assert!(stats.spawned + sim.demand.as_ref().unwrap().queued() as u64 == stats.requested);
+    assert!(sim.trips_completed() > 0);
+}
+
+#[test]
+fn full_entry_roads_queue_or_refuse() {
+    let config = SimConfig::new(0);
+
+    let mut queueing = Simulation::new(corridor(0), 0.1).with_demand(demand(600.0, SpawnPolicy::Queue), &config);
+    queueing.run_ticks(3000);
+    let demand_state = queueing.demand.as_ref().unwrap();
+    assert!(demand_state.stats().requested > 0);
+    assert_eq!(demand_state.stats().spawned, 0);
+    assert_eq!(demand_state.queued_at(NodeID(0)) as u64, demand_state.stats().requested);
+
+    let mut refusing = Simulation::new(corridor(0), 0.1).with_demand(demand(600.0, SpawnPolicy::Refuse), &config);
+    refusing.run_ticks(3000);
+    let demand_state = refusing.demand.as_ref().unwrap();
+    assert_eq!(demand_state.stats().refused, demand_state.stats().requested);
+    assert_eq!(demand_state.queued(), 0);
+    assert!(refusing.road_graph.get_cars().is_empty());
+}
+
+#[test]
+fn queued_trips_take_a_detour_when_their_road_goes() {
+    // Straight to node 1 on a road with no room, or round by node 2
+    let graph = network(&[(0.0, 0.0), (500.0, 0.0), (250.0, 200.0)], &[(0, 1), (0, 2), (2, 1)], |road| {
+        road.capacity = if road.id == RoadID(0) { 0 } else { 100 };
+    });
+
+    let config = SimConfig::new(0);
+    let mut sim = Simulation::new(graph, 0.1).with_demand(demand(600.0, SpawnPolicy::Queue), &config);
+
+    // The direct road is full, so everything waits for it...
+    sim.run_ticks(600);
+    let waiting = sim.demand.as_ref().unwrap().queued_at(NodeID(0));
+    assert!(waiting > 0);
+    assert_eq!(sim.demand.as_ref().unwrap().stats().spawned, 0);
+
+    // ...until it goes, and the queue drains onto the way round
+    sim.road_graph.remove_road(RoadID(0), RemovalPolicy::Reject).unwrap();
+    sim.run_ticks(3000);
+    let stats = sim.demand.as_ref().unwrap().stats();
+    assert!(stats.spawned as usize > waiting, "{stats:?}");
+    assert!(sim.demand.as_ref().unwrap().queued() < 5, "{stats:?}");
+
+    // With no way left at all, trips are dropped as unroutable rather than kept waiting
+    sim.road_graph.add_obstruction(RoadID(1), Obstruction::closure(ObstructionKind::Roadworks, 0.0, None)).unwrap();
+    sim.run_ticks(600);
+    let after = sim.demand.as_ref().unwrap().stats();
+    assert_eq!(sim.demand.as_ref().unwrap().queued(), 0, "{after:?}");
+    assert_eq!(after.spawned, stats.spawned);
+    assert!(after.unroutable > stats.unroutable);
+}
+
+#[test]
+fn roads_can_be_edited_mid_run() {
+    let level = Level::load(concat!(env!("CARGO_MANIFEST_DIR"), "/levels/sim2.json")).unwrap();
+    let mut sim = Simulation::new(level.road_graph, level.dt);
+    sim.run_ticks(100);
+
+    let busiest = |sim: &Simulation| sim.road_graph.roads_to_iter()
+        .map(|road| road.read().unwrap())
+        .max_by_key(|road| (road.vehicles_on.len(), -road.id.0))
+        .map(|road| road.id)
+        .unwrap();
+
+    // Reshaping a road keeps its cars the same share of the way along, in the same order
+    let road_id = busiest(&sim);
+    let shares = |sim: &Simulation| {
+        let road = sim.road_graph.get_roads()[&road_id].read().unwrap();
+        road.vehicles_on.iter()
+            .map(|car| sim.road_graph.get_cars()[car].read().unwrap().along / road.length)
+            .collect::<Vec<f32>>()
+    };
+    let before = shares(&sim);
+    assert!(!before.is_empty());
+
+    sim.road_graph.set_curviness(road_id, -60.0).unwrap();
+    sim.road_graph.set_road_limits(road_id, 20, 30.0).unwrap();
+
+    let after = shares(&sim);
+    assert_eq!(before.len(), after.len());
+    assert!(before.iter().zip(&after).all(|(before, after)| (before - after).abs() < 0.02), "{before:?} vs {after:?}");
+
+    let road = sim.road_graph.get_roads()[&road_id].read().unwrap();
+    assert_eq!((road.capacity, road.speed_limit, road.curviness), (20, 30.0, -60.0));
+    if let Some(twin) = road.twin {
+        let twin = sim.road_graph.get_roads()[&twin].read().unwrap();
+        assert_eq!(twin.points.first(), road.points.last());
+        assert_eq!((twin.capacity, twin.speed_limit), (20, 30.0));
+    }
+    drop(road);
+
+    // Taking a busy road away altogether moves its cars on, and the run still finishes.
+    // Anyone left with no way to their destination is taken out, and their trip noted
+    let road_id = busiest(&sim);
+    let cars = sim.road_graph.get_cars().len();
+
+    sim.road_graph.remove_road(road_id, RemovalPolicy::Reroute).unwrap();
+    assert!(!sim.road_graph.get_roads().contains_key(&road_id));
+    assert_eq!(sim.road_graph.get_cars().len() + sim.road_graph.take_removed_trips().len(), cars);
+
+    while !sim.road_graph.get_cars().is_empty() && sim.time() < 2000.0 {
+        sim.step();
+    }
+    assert!(sim.road_graph.get_cars().is_empty(), "{} cars left", sim.road_graph.get_cars().len());
+}
+
+/// One-way roads out of node 0: road 0 in three lanes to node 1 and on to node 2 by road 1,
+/// road 2 to a dead end, and road 3 round to node 2 by road 4.
+fn straight_or_round_past_a_dead_end() -> RoadGraph {
+    let nodes = [(0.0, 0.0), (400.0, 0.0), (800.0, 0.0), (0.0, 400.0), (400.0, 300.0)];
+    network(&nodes, &[(0, 1), (1, 2), (0, 3), (0, 4), (4, 2)], |road| {
+        road.lanes = if road.id == RoadID(0) { 3 } else { 1 };
+    })
+}
+
+#[test]
+fn removed_roads_send_their_cars_the_way_they_were_going() {
+    let mut graph = straight_or_round_past_a_dead_end();
+    let mut rng = SimConfig::new(0).rng();
+
+    let mut car = |destination: i32, along: f32| {
+        let mut car = Car::new_on_road(None, RoadID(0), &mut graph, 10.0, NodeID(destination), &mut rng);
+        (car.along, car.lane) = (along, 2);
+        graph.add_car(car.clone()).unwrap();
+        car
+    };
+    let (onward, stranded) = (car(2, 200.0), car(1, 100.0));
+
+    graph.remove_road(RoadID(0), RemovalPolicy::Reroute).unwrap();
+
+    // Round by road 3 rather than into the dead end, in the one lane it has
+    let moved = graph.get_cars()[&onward.get_id()].read().unwrap();
+    assert_eq!((moved.current_road, moved.lane, moved.along), (RoadID(3), 0, 0.0));
+    assert_eq!(graph.get_roads()[&RoadID(3)].read().unwrap().vehicles_on, vec![onward.get_id()]);
+    drop(moved);
+
+    // Node 1 can't be reached any more
+    assert!(!graph.get_cars().contains_key(&stranded.get_id()));
+    assert_eq!(graph.take_removed_trips(), vec![stranded.trip]);
+    assert!(graph.take_removed_trips().is_empty());
+
+    let mut sim = Simulation::new(graph, 0.1);
+    while !sim.road_graph.get_cars().is_empty() && sim.time() < 300.0 {
+        sim.step();
+    }
+    assert_eq!(sim.take_trips().iter().map(|trip| trip.car).collect::<Vec<_>>(), vec![onward.get_id()]);
+}
+
+#[test]
+fn cars_sent_round_queue_up_without_overlapping() {
+    let mut graph = straight_or_round_past_a_dead_end();
+    let mut rng = SimConfig::new(0).rng();
+
+    let mut car = |road: RoadID, along: f32| {
+        let mut car = Car::new_on_road(None, road, &mut graph, 0.0, NodeID(2), &mut rng);
+        car.along = along;
+        graph.add_car(car.clone()).unwrap();
+        car.get_id()
+    };
+    // Someone already a little way along the way round, and six cars spread down road 0
+    let ahead = car(RoadID(3), 30.0);
+    let displaced: Vec<CarID> = (0..6).map(|index| car(RoadID(0), 350.0 - 60.0 * index as f32)).collect();
+
+    graph.remove_road(RoadID(0), RemovalPolicy::Reroute).unwrap();
+
+    let road = graph.get_roads()[&RoadID(3)].read().unwrap();
+    let cars = graph.get_cars();
+    let queue: Vec<_> = road.vehicles_on.iter().map(|id| cars[id].read().unwrap()).collect();
+    assert_eq!(road.vehicles_on[0], ahead);
+    assert!(queue.len() > 1, "nobody fitted behind the car ahead");
+
+    // Front to back, each its stopping gap behind the one ahead
+    for pair in queue.windows(2) {
+        let gap = pair[0].along - pair[0].get_height() - pair[1].along;
+        assert!(gap >= pair[1].idm.min_gap - 1e-3, "{} m between {:?} and {:?}", gap, pair[0].get_id(), pair[1].get_id());
+    }
+
+    // Those that didn't fit were taken out front first, the back of the old queue kept
+    let kept: Vec<CarID> = road.vehicles_on[1..].to_vec();
+    let gone = displaced.len() - kept.len();
+    assert!(gone > 0, "all six fitted in 30 m");
+    assert_eq!(kept, displaced[gone..]);
+    assert!(displaced[..gone].iter().all(|id| !cars.contains_key(id)));
+    drop((queue, road));
+
+    // The simulation counts them on its next tick
+    let mut sim = Simulation::new(graph, 0.1);
+    sim.step();
+    assert_eq!(sim.trips_removed(), gone as u64);
+    assert!(sim.road_graph.take_removed_trips().is_empty());
+}
+
+#[test]
+fn new_ids_count_on_from_everything_loaded() {
+    let text = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/levels/sim2.json")).unwrap();
+    let mut data: LevelData = serde_json::from_str(&text).unwrap();
+
+    // A few cars with IDs of their own, out of order, and the rest left to the loader
+    data.cars[3].id = Some(CarID(40));
+    data.cars[7].id = Some(CarID(2));
+    let mut graph = Level::from_data(&data).unwrap().road_graph;
+
+    let mut cars: Vec<i32> = graph.get_cars().keys().map(|id| id.0).collect();
+    cars.sort();
+    cars.dedup();
+    assert_eq!(cars.len(), data.cars.len(), "two cars were given the same ID");
+    assert!(cars.contains(&2) && cars.contains(&40));
+
+    let roads = graph.get_roads().keys().map(|id| id.0).max().unwrap();
+    let nodes = graph.get_nodes().keys().map(|id| id.0).max().unwrap();
+    let highest = *cars.last().unwrap();
+
+    // Every allocator hands out one past the highest it has seen, and keeps counting up
+    assert_eq!([graph.next_car_id(), graph.next_car_id()], [CarID(highest + 1), CarID(highest + 2)]);
+    assert_eq!([graph.next_road_id(), graph.next_road_id()], [RoadID(roads + 1), RoadID(roads + 2)]);
+    assert_eq!([graph.next_node_id(), graph.next_node_id()], [NodeID(nodes + 1), NodeID(nodes + 2)]);
+
+    // Taking the highest away doesn't free its ID, and adding one with a higher ID skips past it
+    graph.remove_car(CarID(highest));
+    assert_eq!(graph.next_car_id(), CarID(highest + 3));
+
+    let road = graph.get_cars().values().next().unwrap().read().unwrap().current_road;
+    let destination = graph.get_roads()[&road].read().unwrap().to.id;
+    let car = Car::new_on_road(Some(CarID(100)), road, &mut graph, 0.0, destination, &mut SimConfig::new(0).rng());
+    graph.add_car(car).unwrap();
+    assert_eq!(graph.next_car_id(), CarID(101));
+}
+
+#[test]
+fn duplicate_ids_are_refused_and_change_nothing() {
+    let mut graph = network(&[(0.0, 0.0), (500.0, 0.0), (1000.0, 0.0)], &[(0, 1), (1, 2)], |_| {});
+    let mut rng = SimConfig::new(0).rng();
+    let car = Car::new_on_road(None, RoadID(0), &mut graph, 10.0, NodeID(2), &mut rng);
+    graph.add_car(car.clone()).unwrap();
+
+    let on = |graph: &RoadGraph, road: i32| graph.get_roads()[&RoadID(road)].read().unwrap().vehicles_on.clone();
+
+    // The same car again, this time on the other road
+    let again = Car::new_on_road(Some(car.get_id()), RoadID(1), &mut graph, 10.0, NodeID(2), &mut rng);
+    assert_eq!(graph.add_car(again), Err(GraphError::DuplicateCar(car.get_id())));
+    assert_eq!((on(&graph, 0), on(&graph, 1)), (vec![car.get_id()], vec![]));
+    assert_eq!(graph.get_cars()[&car.get_id()].read().unwrap().current_road, RoadID(0));
+
+    // An empty road with the ID of the one the car is on
+    let nodes = graph.get_nodes().clone();
+    let road = Road::new_road_with_curves(RoadID(0), nodes[&NodeID(1)], nodes[&NodeID(2)], 10, 30.0, 0.0, &mut rng);
+    assert_eq!(graph.add_road(road), Err(GraphError::DuplicateRoad(RoadID(0))));
+    assert_eq!(on(&graph, 0), vec![car.get_id()]);
+    assert_eq!(graph.get_roads()[&RoadID(0)].read().unwrap().to.id, NodeID(1));
+
+    assert_eq!(graph.add_node(Node::new_node(NodeID(1), Vec2::new(5.0, 5.0))), Err(GraphError::DuplicateNode(NodeID(1))));
+    assert_eq!(graph.get_nodes()[&NodeID(1)].position, Vec2::new(500.0, 0.0));
+    assert_eq!(graph.get_cars().len(), 1);
+}
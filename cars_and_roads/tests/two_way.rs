@@ -0,0 +1,73 @@
+use cars_and_roads::routing::a_star;
+use cars_and_roads::{Car, Distance, NodeID, RemovalPolicy, RoadGraph, RoadID, RouteError, SimConfig, Simulation, Vec2};
+
+mod common;
+use common::network;
+
+
+/// Road 0 runs both ways between nodes 0 and 1 in a wide curve, its twin is road 2. Road 1
+/// leads one-way into node 1 from node 2.
+fn there_and_back() -> RoadGraph {
+    let mut graph = network(&[(0.0, 0.0), (500.0, 0.0), (900.0, 0.0)], &[(0, 1), (2, 1)], |road| {
+        road.one_way = road.id == RoadID(1);
+    });
+    graph.set_curviness(RoadID(0), 120.0).unwrap();
+    graph
+}
+
+const TWIN: RoadID = RoadID(2);
+
+
+#[test]
+fn cars_drive_back_along_the_same_curve() {
+    let mut graph = there_and_back();
+    let road = graph.get_roads()[&RoadID(0)].read().unwrap().clone();
+    let twin = graph.get_roads()[&TWIN].read().unwrap().clone();
+    assert_eq!((road.twin, twin.twin), (Some(TWIN), Some(RoadID(0))));
+    assert_eq!(twin.points, road.points.iter().rev().copied().collect::<Vec<_>>());
+    assert_eq!(a_star(NodeID(1), NodeID(0), &graph, &Distance).unwrap().roads, vec![TWIN]);
+
+    let car = Car::new_on_road(None, RoadID(1), &mut graph, 10.0, NodeID(0), &mut SimConfig::new(0).rng());
+    graph.add_car(car.clone()).unwrap();
+    let mut sim = Simulation::new(graph, 0.1);
+
+    let mut seen = Vec::new();
+    while sim.road_graph.get_cars().contains_key(&car.get_id()) {
+        let car = sim.road_graph.get_cars()[&car.get_id()].read().unwrap();
+        if car.current_road == TWIN && car.along <= twin.length {
+            // In its lane, just to the right of the curve it is on
+            let on_curve = twin.position_at(car.along);
+            assert!(car.position.distance(on_curve) <= twin.lane_offset(car.lane) + 0.01, "{} m off", car.position.distance(on_curve));
+            seen.push(car.position);
+        }
+        drop(car);
+
+        sim.step();
+        assert!(sim.time() < 120.0, "never got back to node 0");
+    }
+
+    // From the twin's first point, the far end of road 0, to its last, the near end
+    let (first, last) = (seen[0], *seen.last().unwrap());
+    let near = |point: Vec2, to: Vec2| point.distance(to) < twin.lane_offset(0) + 2.0;
+    assert!(near(first, twin.points[0]) && near(first, *road.points.last().unwrap()), "started at {first}");
+    assert!(near(last, *twin.points.last().unwrap()) && near(last, road.points[0]), "ended at {last}");
+    assert!(seen.windows(2).all(|pair| pair[1].x <= pair[0].x), "went back on itself");
+}
+
+#[test]
+fn one_way_roads_lose_their_way_back() {
+    let mut graph = there_and_back();
+    graph.set_one_way(RoadID(0), true, RemovalPolicy::Reject).unwrap();
+
+    assert_eq!(a_star(NodeID(1), NodeID(0), &graph, &Distance), Err(RouteError::Unreachable(NodeID(1), NodeID(0))));
+    assert!(!graph.get_roads().contains_key(&TWIN));
+    assert!(graph.get_adjacency().values().flatten().all(|(_, road)| *road != TWIN));
+    assert!(graph.get_adjacency().get(&NodeID(1)).is_none_or(|leaving| leaving.is_empty()));
+
+    let road = graph.get_roads()[&RoadID(0)].read().unwrap();
+    assert!(road.one_way && road.twin.is_none());
+    drop(road);
+
+    // The way there still works
+    assert_eq!(a_star(NodeID(0), NodeID(1), &graph, &Distance).unwrap().roads, vec![RoadID(0)]);
+}
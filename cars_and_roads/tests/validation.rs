@@ -0,0 +1,74 @@
+use cars_and_roads::validation::strongly_connected_components;
+use cars_and_roads::{Car, Issue, Node, NodeID, Road, RoadGraph, RoadID, Severity, SimConfig, Vec2};
+
+mod common;
+use common::network;
+
+
+/// Nodes 0 and 1 joined both ways, a one-way road on from 1 to 2, and node 3 off on its own.
+fn lopsided() -> RoadGraph {
+    network(&[(0.0, 0.0), (300.0, 0.0), (600.0, 0.0), (900.0, 0.0)], &[(0, 1), (1, 2)], |road| {
+        if road.id == RoadID(0) {
+            road.one_way = false;
+        } else {
+            road.capacity = 0;
+        }
+    })
+}
+
+
+#[test]
+fn finds_components_and_unreachable_pairs() {
+    let mut graph = lopsided();
+    let car = Car::new_on_road(None, RoadID(1), &mut graph, 10.0, NodeID(0), &mut SimConfig::new(0).rng());
+    graph.add_car(car.clone()).unwrap();
+
+    let components = strongly_connected_components(&graph);
+    assert_eq!(components, vec![vec![NodeID(0), NodeID(1)], vec![NodeID(2)], vec![NodeID(3)]]);
+
+    let validation = graph.validate();
+    assert_eq!(validation.components, components);
+    assert!(validation.is_ok());
+    assert_eq!(validation.issues, vec![
+        Issue::ZeroCapacity(RoadID(1)),
+        Issue::DanglingNode(NodeID(3)),
+        Issue::Unreachable { from: NodeID(2), to: NodeID(0) },
+        Issue::StrandedCar { car: car.get_id(), destination: NodeID(0) },
+    ]);
+}
+
+#[test]
+fn broken_roads_are_errors() {
+    let mut rng = SimConfig::new(0).rng();
+    let nodes = [Node::new_node(NodeID(0), Vec2::ZERO), Node::new_node(NodeID(1), Vec2::new(300.0, 0.0))];
+
+    // Built against a node that has since moved, and a road that goes nowhere
+    let moved = Node::new_node(NodeID(1), Vec2::new(300.0, 50.0));
+    let mut stale = Road::new_road_with_curves(RoadID(0), nodes[0], moved, 50, 50.0, 0.0, &mut rng);
+    stale.one_way = true;
+    let mut stub = Road::new_road_with_curves(RoadID(1), nodes[1], nodes[1], 50, 50.0, 0.0, &mut rng);
+    stub.one_way = true;
+
+    let validation = RoadGraph::new(Some(vec![stale, stub]), Some(nodes.to_vec())).validate();
+    assert!(!validation.is_ok());
+
+    let errors: Vec<Issue> = validation.errors().copied().collect();
+    assert_eq!(errors, vec![Issue::MismatchedNode { road: RoadID(0), node: NodeID(1) }, Issue::DegeneratePoints(RoadID(1))]);
+    assert!(validation.warnings().all(|issue| issue.severity() == Severity::Warning));
+}
+
+#[test]
+fn long_one_way_chains_are_summed_up() {
+    // Each node only leads on to the next, so nothing reaches back up the chain
+    let nodes: Vec<(f32, f32)> = (0..2000).map(|id| (id as f32 * 100.0, 0.0)).collect();
+    let roads: Vec<(usize, usize)> = (1..2000).map(|to| (to - 1, to)).collect();
+
+    let validation = network(&nodes, &roads, |_| {}).validate();
+    assert_eq!(validation.components.len(), 2000);
+
+    let unreachable: Vec<Issue> = validation.warnings().copied().collect();
+    assert_eq!(unreachable.len(), 21);
+    assert_eq!(unreachable[0], Issue::Unreachable { from: NodeID(1), to: NodeID(0) });
+    assert!(unreachable[..20].iter().all(|issue| matches!(issue, Issue::Unreachable { .. })));
+    assert_eq!(unreachable[20], Issue::MoreUnreachable { groups: 1999 });
+}
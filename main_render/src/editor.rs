@@ -0,0 +1,386 @@
+//! Editor mode: change the network while the simulation keeps running.
+//!
+//! Click empty ground to place a node, drag from one node to another to build a road, click
+//! a road or node to select it. A selected road has a handle that bends it when dragged,
+//! and can have accidents and roadworks dropped onto it.
+//! Edits go through `RoadGraph`, which moves cars off removed roads and has drivers whose
+//! path ran over them plan again.
+
+use macroquad::prelude::*;
+use cars_and_roads::{bezier_point, generate_bezier, GraphError, Node, NodeID, Obstruction, ObstructionKind, RemovalPolicy, Road, RoadGraph, RoadID, SimConfig, LANE_WIDTH};
+use render::Camera;
+
+
+/// How close the mouse has to be to a node, road or handle to grab it, in pixels
+const PICK_RADIUS: f32 = 12.0;
+
+/// Where along a selected road its curviness handle sits, as a bezier parameter. The middle
+/// of the curve never moves, the S bends either side of it.
+const HANDLE_T: f32 = 0.25;
+
+/// What new roads are built with
+const NEW_CAPACITY: i32 = 100;
+const NEW_SPEED_LIMIT: f32 = 50.0; // km/h
+
+/// How much the arrow keys change a selected road by
+const CAPACITY_STEP: i32 = 10;
+const SPEED_LIMIT_STEP: f32 = 10.0; // km/h
+
+/// How long obstructions dropped in the editor last, in simulated seconds
+const ACCIDENT_DURATION: f32 = 120.0;
+const ROADWORKS_DURATION: f32 = 300.0;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Selection {
+    Node(NodeID),
+    Road(RoadID),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Drag {
+    /// From a node, becomes a road if it is let go over another one
+    NewRoad(NodeID),
+    /// The curviness handle of a road
+    Curve(RoadID),
+}
+
+
+#[derive(Debug, Default)]
+pub struct Editor {
+    pub active: bool,
+    selection: Option<Selection>,
+    drag: Option<Drag>,
+    status: String, // what came of the last edit
+}
+
+impl Editor {
+
+    pub fn new() -> Self {
+        Editor::default()
+    }
+
+    /// Handles the mouse and keyboard for one frame, if the editor is active.
+    ///
+    /// Hold shift while deleting, or while making a road one-way, to take the cars on it
+    /// out of the simulation instead of moving them onto another road.
+    pub fn update(&mut self, road_graph: &mut RoadGraph, camera: &Camera) {
+        if !self.active {
+            return;
+        }
+
+        // Whatever was selected may have gone with another edit
+        self.selection = self.selection.filter(|selection| match selection {
+            Selection::Node(id) => road_graph.get_nodes().contains_key(id),
+            Selection::Road(id) => road_graph.get_roads().contains_key(id),
+        });
+        self.drag = self.drag.filter(|drag| match drag {
+            Drag::NewRoad(id) => road_graph.get_nodes().contains_key(id),
+            Drag::Curve(id) => road_graph.get_roads().contains_key(id),
+        });
+
+        let mouse = Vec2::from(mouse_position());
+
+        if is_mouse_button_pressed(MouseButton::Left) {
+            self.press(road_graph, camera, mouse);
+        }
+
+        match self.drag {
+            Some(Drag::Curve(id)) => {
+                let road = road_graph.get_roads()[&id].read().unwrap();
+                let (start, end) = ends(&road);
+                let curviness = curviness_towards(start, end, camera.screen_to_world(mouse));
+                drop(road);
+
+                let result = road_graph.set_curviness(id, curviness);
+                self.report(result, "");
+            }
+            Some(Drag::NewRoad(from)) if is_mouse_button_released(MouseButton::Left) => {
+                if let Some(to) = node_at(road_graph, camera, mouse).filter(|to| *to != from) {
+                    self.build_road(road_graph, from, to);
+                }
+            }
+            _ => {}
+        }
+
+        if !is_mouse_button_down(MouseButton::Left) {
+            self.drag = None;
+        }
+
+        self.keys(road_graph, camera.screen_to_world(mouse));
+    }
+
+    fn press(&mut self, road_graph: &mut RoadGraph, camera: &Camera, mouse: Vec2) {
+        if let Some(Selection::Road(id)) = self.selection
+            && camera.world_to_screen(handle(&road_graph.get_roads()[&id].read().unwrap())).distance(mouse) <= PICK_RADIUS
+        {
+            self.drag = Some(Drag::Curve(id));
+        } else if let Some(node) = node_at(road_graph, camera, mouse) {
+            self.selection = Some(Selection::Node(node));
+            self.drag = Some(Drag::NewRoad(node));
+        } else if let Some(road) = road_at(road_graph, camera, mouse) {
+            self.selection = Some(Selection::Road(road));
+        } else {
+            let id = road_graph.next_node_id();
+            let result = road_graph.add_node(Node::new_node(id, camera.screen_to_world(mouse)));
+            self.report(result, &format!("placed node {id}"));
+            self.selection = Some(Selection::Node(id));
+        }
+    }
+
+    /// A straight two-way road from `from` to `to`, selected so it can be bent and tuned.
+    fn build_road(&mut self, road_graph: &mut RoadGraph, from: NodeID, to: NodeID) {
+        let (from, to) = (road_graph.get_nodes()[&from], road_graph.get_nodes()[&to]);
+        let id = road_graph.next_road_id();
+
+        // The rng only picks `one_way`, which is set right after
+        let mut road = Road::new_road_with_curves(id, from, to, NEW_CAPACITY, NEW_SPEED_LIMIT, 0.0, &mut SimConfig::new(0).rng());
+        road.one_way = false;
+
+        let result = road_graph.add_road(road);
+        if result.is_ok() {
+            self.selection = Some(Selection::Road(id));
+        }
+        self.report(result, &format!("built road {:?} from node {} to node {}", id, from.id, to.id));
+    }
+
+    /// `cursor` is where the mouse is in the world.
+    fn keys(&mut self, road_graph: &mut RoadGraph, cursor: Vec2) {
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        let policy = if shift { RemovalPolicy::Despawn } else { RemovalPolicy::Reroute };
+
+        if is_key_pressed(KeyCode::Delete) || is_key_pressed(KeyCode::Backspace) {
+            match self.selection {
+                Some(Selection::Node(id)) => {
+                    let result = road_graph.remove_node(id, policy).map(|_| ());
+                    self.report(result, &format!("deleted node {id} and its roads"));
+                }
+                Some(Selection::Road(id)) => {
+                    let result = road_graph.remove_road(id, policy).map(|_| ());
+                    self.report(result, &format!("deleted road {:?}", id));
+                }
+                None => {}
+            }
+            return;
+        }
+
+        let Some(Selection::Road(id)) = self.selection else { return };
+        let road = road_graph.get_roads()[&id].read().unwrap();
+        let (one_way, capacity, speed_limit) = (road.one_way, road.capacity, road.speed_limit);
+        drop(road);
+
+        if is_key_pressed(KeyCode::O) {
+            let result = road_graph.set_one_way(id, !one_way, policy);
+            self.report(result, &format!("road {:?} is now {}", id, if one_way { "two-way" } else { "one-way" }));
+        }
+
+        let mut capacity_change = 0;
+        if is_key_pressed(KeyCode::Up) {
+            capacity_change += CAPACITY_STEP;
+        }
+        if is_key_pressed(KeyCode::Down) {
+            capacity_change -= CAPACITY_STEP;
+        }
+
+        let mut speed_change = 0.0;
+        if is_key_pressed(KeyCode::Right) {
+            speed_change += SPEED_LIMIT_STEP;
+        }
+        if is_key_pressed(KeyCode::Left) {
+            speed_change -= SPEED_LIMIT_STEP;
+        }
+
+        if capacity_change != 0 || speed_change != 0.0 {
+            let (capacity, speed_limit) = ((capacity + capacity_change).max(0), (speed_limit + speed_change).max(SPEED_LIMIT_STEP));
+            let result = road_graph.set_road_limits(id, capacity, speed_limit);
+            self.report(result, &format!("road {:?}: capacity {}, {} km/h", id, capacity, speed_limit));
+        }
+
+        if is_key_pressed(KeyCode::X) {
+            self.obstruct(road_graph, id, cursor, shift);
+        }
+
+        if is_key_pressed(KeyCode::C) {
+            let cleared: Vec<_> = road_graph.roads_to_iter()
+                .map(|road| road.read().unwrap())
+                .filter(|road| road.id == id || road.twin == Some(id))
+                .flat_map(|road| road.obstructions.iter().map(|obstruction| obstruction.id()).collect::<Vec<_>>())
+                .collect();
+            for obstruction in &cleared {
+                road_graph.clear_obstruction(*obstruction);
+            }
+            self.status = format!("cleared {} obstruction(s) from road {:?}", cleared.len(), id);
+        }
+    }
+
+    /// Drops an accident into the lane under `cursor`, or with `whole_road` roadworks across
+    /// every lane. On a two-way road it goes on the direction whose side the cursor is on.
+    fn obstruct(&mut self, road_graph: &mut RoadGraph, id: RoadID, cursor: Vec2, whole_road: bool) {
+        let road = road_graph.get_roads()[&id].read().unwrap();
+        let (along, lateral) = nearest_along(&road.points, cursor);
+
+        // Two-way lanes are all on the right of the direction of travel
+        let (target, along, lateral) = match road.twin {
+            Some(twin) if lateral < 0.0 => (twin, road.length - along, -lateral),
+            _ => (id, along, lateral),
+        };
+        drop(road);
+
+        let road = road_graph.get_roads()[&target].read().unwrap();
+        let kerb_side = road.lane_offset(0) - LANE_WIDTH / 2.0;
+        let lane = (((lateral - kerb_side) / LANE_WIDTH).floor().max(0.0) as usize).min(road.lanes.max(1) - 1);
+        drop(road);
+
+        let (obstruction, done) = if whole_road {
+            (Obstruction::closure(ObstructionKind::Roadworks, along, Some(ROADWORKS_DURATION)), format!("roadworks close road {:?}", target))
+        } else {
+            (Obstruction::lanes(ObstructionKind::Accident, along, vec![lane], Some(ACCIDENT_DURATION)), format!("accident in lane {} of road {:?}", lane, target))
+        };
+
+        let result = road_graph.add_obstruction(target, obstruction).map(|_| ());
+        self.report(result, &done);
+    }
+
+    /// Shows what came of an edit: `done` if it went through, the reason if it didn't.
+    fn report(&mut self, result: Result<(), GraphError>, done: &str) {
+        self.status = match result {
+            Ok(()) if done.is_empty() => return,
+            Ok(()) => done.to_string(),
+            Err(error) => error.to_string(),
+        };
+    }
+
+    /// The selection, the road being dragged out, and a panel with the controls.
+    pub fn draw(&self, road_graph: &RoadGraph, camera: &Camera) {
+        if !self.active {
+            return;
+        }
+
+        let lines = match self.selection {
+            Some(Selection::Road(id)) => {
+                let road = road_graph.get_roads()[&id].read().unwrap();
+                let points: Vec<Vec2> = road.points.iter().map(|point| camera.world_to_screen(*point)).collect();
+                for pair in points.windows(2) {
+                    draw_line(pair[0].x, pair[0].y, pair[1].x, pair[1].y, 3.0, YELLOW);
+                }
+
+                let grip = camera.world_to_screen(handle(&road));
+                draw_circle(grip.x, grip.y, PICK_RADIUS / 2.0, YELLOW);
+                draw_circle_lines(grip.x, grip.y, PICK_RADIUS / 2.0, 1.0, BLACK);
+
+                vec![
+                    format!(
+                        "road {:?}: {} lane(s), capacity {}, {} km/h, {}, {} cars on it",
+                        id, road.lanes, road.capacity, road.speed_limit, if road.one_way { "one-way" } else { "two-way" }, road.vehicles_on.len()
+                    ),
+                    "drag the handle to bend it  [O] one-way  [Up/Down] capacity  [Left/Right] speed limit  [Del] delete".to_string(),
+                    "[X] accident in the lane under the mouse  [shift+X] roadworks across the road  [C] clear them".to_string(),
+                ]
+            }
+            Some(Selection::Node(id)) => {
+                let position = camera.world_to_screen(road_graph.get_nodes()[&id].position);
+                draw_circle_lines(position.x, position.y, PICK_RADIUS, 2.0, YELLOW);
+
+                vec![
+                    format!("node {id}"),
+                    "drag to another node to build a road  [Del] delete".to_string(),
+                ]
+            }
+            None => vec!["click to place a node, or click a node or road to select it".to_string()],
+        };
+
+        if let Some(Drag::NewRoad(from)) = self.drag {
+            let (from, mouse) = (camera.world_to_screen(road_graph.get_nodes()[&from].position), Vec2::from(mouse_position()));
+            draw_line(from.x, from.y, mouse.x, mouse.y, 2.0, YELLOW);
+        }
+
+        let header = "EDITOR  [E] back to watching  middle drag pans  shift+edit takes cars out".to_string();
+        for (row, line) in std::iter::once(&header).chain(&lines).chain(std::iter::once(&self.status)).enumerate() {
+            draw_text(line, 10.0, 40.0 + row as f32 * 20.0, 20.0, YELLOW);
+        }
+    }
+
+}
+
+
+/// Where the curviness handle of `road` is, in world space.
+fn handle(road: &Road) -> Vec2 {
+    let (start, end) = ends(road);
+    bezier_point(generate_bezier(start, end, road.curviness), HANDLE_T)
+}
+
+/// Where the bezier of `road` starts and ends, short of any junctions at its nodes.
+fn ends(road: &Road) -> (Vec2, Vec2) {
+    let start = road.points.first().copied().unwrap_or(road.from.position);
+    let end = road.points.last().copied().unwrap_or(road.to.position);
+    (start, end)
+}
+
+/// The curviness that puts the handle of a road from `from` to `to` as close to `target`
+/// as it can go. The handle only moves sideways, and linearly with the curviness.
+fn curviness_towards(from: Vec2, to: Vec2, target: Vec2) -> f32 {
+    let straight = bezier_point(generate_bezier(from, to, 0.0), HANDLE_T);
+    let per_unit = bezier_point(generate_bezier(from, to, 1.0), HANDLE_T) - straight;
+
+    if per_unit.length_squared() > 0.0 {
+        (target - straight).dot(per_unit) / per_unit.length_squared()
+    } else {
+        0.0
+    }
+}
+
+/// The node drawn nearest to `pixel`, within `PICK_RADIUS`.
+fn node_at(road_graph: &RoadGraph, camera: &Camera, pixel: Vec2) -> Option<NodeID> {
+    road_graph.nodes_to_iter()
+        .map(|node| (node.id, camera.world_to_screen(node.position).distance(pixel)))
+        .filter(|(_, distance)| *distance <= PICK_RADIUS)
+        .min_by(|a, b| a.1.total_cmp(&b.1).then(a.0.0.cmp(&b.0.0)))
+        .map(|(id, _)| id)
+}
+
+/// The road drawn nearest to `pixel`, within `PICK_RADIUS`. Both directions of a two-way
+/// road share a line, which counts as the one with the lower ID.
+fn road_at(road_graph: &RoadGraph, camera: &Camera, pixel: Vec2) -> Option<RoadID> {
+    road_graph.roads_to_iter()
+        .map(|road| road.read().unwrap())
+        .filter(|road| road.twin.is_none_or(|twin| twin.0 > road.id.0))
+        .map(|road| {
+            let points: Vec<Vec2> = road.points.iter().map(|point| camera.world_to_screen(*point)).collect();
+            let distance = points.windows(2)
+                .map(|pair| distance_to_segment(pixel, pair[0], pair[1]))
+                .fold(f32::INFINITY, f32::min);
+            (road.id, distance)
+        })
+        .filter(|(_, distance)| *distance <= PICK_RADIUS)
+        .min_by(|a, b| a.1.total_cmp(&b.1).then(a.0.0.cmp(&b.0.0)))
+        .map(|(id, _)| id)
+}
+
+/// How far along a polyline the point nearest `target` is, and how far to the right of the
+/// line `target` lies (negative on the left).
+fn nearest_along(points: &[Vec2], target: Vec2) -> (f32, f32) {
+    let mut best = (f32::INFINITY, 0.0, 0.0);
+    let mut travelled = 0.0;
+
+    for pair in points.windows(2) {
+        let span = pair[1] - pair[0];
+        let length = span.length();
+        let t = if length > 0.0 { ((target - pair[0]).dot(span) / (length * length)).clamp(0.0, 1.0) } else { 0.0 };
+        let nearest = pair[0] + span * t;
+        let distance = target.distance(nearest);
+
+        if distance < best.0 {
+            let right = Vec2::new(-span.y, span.x).normalize_or_zero();
+            best = (distance, travelled + length * t, (target - nearest).dot(right));
+        }
+        travelled += length;
+    }
+
+    (best.1, best.2)
+}
+
+fn distance_to_segment(point: Vec2, start: Vec2, end: Vec2) -> f32 {
+    let span = end - start;
+    let t = if span.length_squared() > 0.0 { ((point - start).dot(span) / span.length_squared()).clamp(0.0, 1.0) } else { 0.0 };
+    point.distance(start + span * t)
+}
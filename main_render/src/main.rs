@@ -1,52 +1,85 @@
-
-use rayon::prelude::*;
 use macroquad::{prelude::*};
 use cars_and_roads::level::Level;
-use cars_and_roads::Car;
 use render::*;
+use render::Camera;
+
+mod editor;
+use editor::Editor;
+
+
+/// Level files, relative to this crate
+const LEVELS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../cars_and_roads/levels");
 
+/// How many sim seconds pass per real second
+const TIME_SCALE: f32 = 4.0;
+
+/// Longest frame the sim catches up on, in real seconds. A slower frame (a window drag, a
+/// breakpoint) runs this much and lets the rest go, rather than piling up ticks for the next.
+const MAX_FRAME_TIME: f32 = 0.1;
+
+/// How close to a car a right click has to be to follow it, in pixels
+const PICK_RADIUS: f32 = 20.0;
 
 
 #[macroquad::main("Main Render")]
 async fn main() {
 
-    
-    //// INIT ////
-    
-    set_fullscreen(true);
 
+    //// INIT ////
 
-    // let level = Level::sim1();
+    // Pick a level: sim1, sim2, sim3 or roundabout. The seed in the file picks the cars,
+    // same file, same run.
+    let level = Level::load(format!("{LEVELS}/sim3.json")).expect("level file should load");
+    for issue in &level.issues {
+        eprintln!("⚠️ {}", issue);
+    }
 
-     let level = Level::sim3(30); // 'pc' is for a vertical 1080p display, laptop is for a normal 1080p display (but mine is 1920x1200) 
+    // Cars log their routing through the `log` crate, at debug level
+    let mut sim = level.into_simulation();
 
-    //let level = Level::sim1();
+    // Drag to pan, scroll to zoom, F to fit the network on the screen, right click a car to
+    // follow it and anywhere else to stop
+    let mut camera = Camera::fitted(&sim.road_graph);
 
-    let mut road_graph = level.road_graph;
+    // E switches to building and changing roads, see editor.rs
+    let mut editor = Editor::new();
 
 
-    
     //// Game Loop ////
-    loop { 
+    loop {
 
         draw_fps();
-        
+
+
+        // Input //
+        if is_key_pressed(KeyCode::E) {
+            editor.active = !editor.active;
+            camera.pan_button = if editor.active { MouseButton::Middle } else { MouseButton::Left };
+        }
+        if is_key_pressed(KeyCode::F) {
+            camera.fit_network(&sim.road_graph);
+        }
+        if is_mouse_button_pressed(MouseButton::Right) {
+            camera.follow(camera.car_at(Vec2::from(mouse_position()), PICK_RADIUS, &sim.road_graph));
+        }
+        camera.update(&sim.road_graph);
+        editor.update(&mut sim.road_graph, &camera);
+
 
         // Render //
-        draw_roads(&mut road_graph, false);
-        road_graph.nodes_to_iter().for_each(|x| draw_node(x, true));
-        road_graph.cars_to_iter().for_each(|x | draw_car(&x.read().unwrap(), false));
+        draw_roads(&mut sim.road_graph, &camera, false);
+        sim.road_graph.nodes_to_iter().for_each(|x| draw_node(x, &camera, true));
+        draw_controls(&sim.road_graph, &camera);
+        draw_obstructions(&sim.road_graph, &camera);
+        sim.road_graph.cars_to_iter().for_each(|x | draw_car(&x.read().unwrap(), &camera, false));
+        editor.draw(&sim.road_graph, &camera);
 
 
 
-        // Simulation // 
-        let frame_time = get_frame_time();
-        road_graph.get_cars().par_iter().for_each(|(_id, car)| {car.write().unwrap().move_car_to_destination(&road_graph,  frame_time * 40.0, true);});
+        // Simulation //
+        sim.run_for(get_frame_time().min(MAX_FRAME_TIME) * TIME_SCALE);
 
 
         next_frame().await
     }
 }
-
-
-
@@ -0,0 +1,165 @@
+//! Where the world is on the screen.
+//!
+//! The simulation works in meters and knows nothing about the window. The `Camera` maps
+//! world positions to pixels around a target point at a zoom level, so the same level looks
+//! right on any screen. Every `draw_*` function takes one and draws through it.
+
+use cars_and_roads::{is_mouse_button_down, mouse_position, mouse_wheel, screen_height, screen_width, CarID, MouseButton, RoadGraph, Vec2};
+
+
+/// Zoom limits, in pixels per meter
+const MIN_ZOOM: f32 = 0.02;
+const MAX_ZOOM: f32 = 50.0;
+
+/// How much one notch of the scroll wheel zooms by
+const ZOOM_STEP: f32 = 1.1;
+
+/// Room left around the network when fitting it to the screen, as a fraction of the screen
+const FIT_MARGIN: f32 = 0.05;
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Camera {
+    pub target: Vec2, // world position at the middle of the screen, in meters
+    pub zoom: f32,    // pixels per meter
+    pub pan_button: MouseButton, // dragging with it pans the view
+    following: Option<CarID>,
+    drag_from: Option<Vec2>, // world position that was under the mouse when the drag started
+}
+
+impl Camera {
+
+    pub fn new(target: Vec2, zoom: f32) -> Self {
+        Camera {
+            target,
+            zoom: zoom.clamp(MIN_ZOOM, MAX_ZOOM),
+            pan_button: MouseButton::Left,
+            following: None,
+            drag_from: None,
+        }
+    }
+
+    /// A camera showing the whole network.
+    pub fn fitted(road_graph: &RoadGraph) -> Self {
+        let mut camera = Camera::default();
+        camera.fit_network(road_graph);
+        camera
+    }
+
+    fn screen_center() -> Vec2 {
+        Vec2::new(screen_width(), screen_height()) / 2.0
+    }
+
+    /// Where a world position is on the screen, in pixels.
+    pub fn world_to_screen(&self, position: Vec2) -> Vec2 {
+        (position - self.target) * self.zoom + Camera::screen_center()
+    }
+
+    /// The world position under a pixel on the screen.
+    pub fn screen_to_world(&self, pixel: Vec2) -> Vec2 {
+        (pixel - Camera::screen_center()) / self.zoom + self.target
+    }
+
+    /// How many pixels `meters` covers at the current zoom.
+    pub fn to_pixels(&self, meters: f32) -> f32 {
+        meters * self.zoom
+    }
+
+    /// Zooms by `factor`, keeping the world position under `pixel` where it is.
+    pub fn zoom_at(&mut self, pixel: Vec2, factor: f32) {
+        let anchor = self.screen_to_world(pixel);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.target = anchor - (pixel - Camera::screen_center()) / self.zoom;
+    }
+
+    /// Centers on the box from `min` to `max` and zooms so it just fills the screen.
+    pub fn fit(&mut self, min: Vec2, max: Vec2) {
+        let size = (max - min).max(Vec2::splat(1.0));
+        let room = Vec2::new(screen_width(), screen_height()) * (1.0 - 2.0 * FIT_MARGIN);
+
+        self.target = (min + max) / 2.0;
+        self.zoom = (room.x / size.x).min(room.y / size.y).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.following = None;
+    }
+
+    /// Fits every node and road of the network on the screen. Leaves the camera alone if
+    /// the network is empty.
+    pub fn fit_network(&mut self, road_graph: &RoadGraph) {
+        let roads = road_graph.get_roads().values().map(|road| road.read().unwrap().points.clone());
+        let points: Vec<Vec2> = road_graph.nodes_to_iter()
+            .map(|node| node.position)
+            .chain(roads.flatten())
+            .collect();
+
+        if points.is_empty() {
+            return;
+        }
+
+        let min = points.iter().fold(Vec2::splat(f32::INFINITY), |min, point| min.min(*point));
+        let max = points.iter().fold(Vec2::splat(f32::NEG_INFINITY), |max, point| max.max(*point));
+        self.fit(min, max);
+    }
+
+    /// Keeps `car` in the middle of the screen until it leaves the network, the view is
+    /// dragged, or this is called with `None`.
+    pub fn follow(&mut self, car: Option<CarID>) {
+        self.following = car;
+    }
+
+    pub fn following(&self) -> Option<CarID> {
+        self.following
+    }
+
+    /// The car drawn nearest to `pixel`, if there is one within `radius` pixels of it.
+    pub fn car_at(&self, pixel: Vec2, radius: f32, road_graph: &RoadGraph) -> Option<CarID> {
+        road_graph.cars_to_iter()
+            .map(|car| {
+                let car = car.read().unwrap();
+                (car.get_id(), self.world_to_screen(car.lane_position()).distance(pixel))
+            })
+            .filter(|(_, distance)| *distance <= radius)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(id, _)| id)
+    }
+
+    /// Pans while `pan_button` is dragged and zooms about the cursor with the
+    /// scroll wheel, then moves onto the followed car. Call once a frame, before drawing.
+    pub fn update(&mut self, road_graph: &RoadGraph) {
+        let mouse = Vec2::from(mouse_position());
+
+        let scroll = mouse_wheel().1;
+        if scroll != 0.0 {
+            self.zoom_at(mouse, ZOOM_STEP.powf(scroll.signum()));
+        }
+
+        if is_mouse_button_down(self.pan_button) {
+            match self.drag_from {
+                Some(anchor) => {
+                    let moved = anchor - self.screen_to_world(mouse);
+                    if moved != Vec2::ZERO {
+                        self.target += moved;
+                        self.following = None;
+                    }
+                }
+                None => self.drag_from = Some(self.screen_to_world(mouse)),
+            }
+        } else {
+            self.drag_from = None;
+        }
+
+        if let Some(id) = self.following {
+            match road_graph.get_cars().get(&id) {
+                Some(car) => self.target = car.read().unwrap().lane_position(),
+                None => self.following = None, // arrived
+            }
+        }
+    }
+
+}
+
+impl Default for Camera {
+    /// One pixel per meter, with the world origin at the top left of the screen.
+    fn default() -> Self {
+        Camera::new(Camera::screen_center(), 1.0)
+    }
+}
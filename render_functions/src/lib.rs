@@ -1,6 +1,29 @@
-use cars_and_roads::{draw_circle, draw_line, draw_text, draw_triangle, road::Node, Car, CarID, Color, Road, RoadGraph, Vec2, Vec4, BLUE, PINK, PURPLE, RED, WHITE};
+pub mod camera;
 
-pub fn draw_car(car: &Car, debug: bool) {
+pub use camera::Camera;
+
+use cars_and_roads::{draw_circle, draw_line, draw_poly, draw_text, draw_triangle, road::Node, Aspect, Car, Color, NodeControl, ObstructionKind, Road, RoadGraph, Vec2, BLUE, DARKGRAY, GREEN, LANE_WIDTH, LIGHTGRAY, ORANGE, PINK, RED, WHITE, YELLOW};
+
+
+/// A line between two world positions, `thickness` in meters but never thinner than a pixel.
+fn draw_world_line(camera: &Camera, from: Vec2, to: Vec2, thickness: f32, color: Color) {
+    let (from, to) = (camera.world_to_screen(from), camera.world_to_screen(to));
+    draw_line(from.x, from.y, to.x, to.y, camera.to_pixels(thickness).max(1.0), color);
+}
+
+/// A polyline through world positions, see `draw_world_line`.
+fn draw_world_polyline(camera: &Camera, points: &[Vec2], thickness: f32, color: Color) {
+    for pair in points.windows(2) {
+        draw_world_line(camera, pair[0], pair[1], thickness, color);
+    }
+}
+
+fn draw_world_triangle(camera: &Camera, corners: [Vec2; 3], color: Color) {
+    let [a, b, c] = corners.map(|corner| camera.world_to_screen(corner));
+    draw_triangle(a, b, c, color);
+}
+
+pub fn draw_car(car: &Car, camera: &Camera, debug: bool) {
     let width = car.get_width();
     let height = car.get_height();
 
@@ -15,7 +38,7 @@ pub fn draw_car(car: &Car, debug: bool) {
     let forward = Vec2::from_angle(angle);
     let right = Vec2::new(-forward.y, forward.x); // 90° perp
 
-    let center = car.position;
+    let center = car.lane_position();
     let front = center + forward * (body_len / 2.0);
     let rear = center - forward * (body_len / 2.0);
     let roof_front = center + forward * (body_len / 2.0 - roof_len);
@@ -29,8 +52,8 @@ pub fn draw_car(car: &Car, debug: bool) {
         front - right * half_w,
     ];
 
-    draw_triangle(body_corners[0], body_corners[1], body_corners[2], color);
-    draw_triangle(body_corners[2], body_corners[3], body_corners[0], color);
+    draw_world_triangle(camera, [body_corners[0], body_corners[1], body_corners[2]], color);
+    draw_world_triangle(camera, [body_corners[2], body_corners[3], body_corners[0]], color);
 
     // Draw windshield / roof (smaller polygon)
     let roof_corners = [
@@ -41,8 +64,8 @@ pub fn draw_car(car: &Car, debug: bool) {
     ];
 
     let roof_color = Color::from_rgba(200, 200, 200, 200); // light grey roof
-    draw_triangle(roof_corners[0], roof_corners[1], roof_corners[2], roof_color);
-    draw_triangle(roof_corners[2], roof_corners[3], roof_corners[0], roof_color);
+    draw_world_triangle(camera, [roof_corners[0], roof_corners[1], roof_corners[2]], roof_color);
+    draw_world_triangle(camera, [roof_corners[2], roof_corners[3], roof_corners[0]], roof_color);
 
     if debug {
         // Heading arrow
@@ -50,11 +73,12 @@ pub fn draw_car(car: &Car, debug: bool) {
         let tip = center + dir * 20.0;
         let base = center + dir * 5.0;
         let perp = Vec2::new(-dir.y, dir.x) * 4.0;
-        draw_line(center.x, center.y, tip.x, tip.y, 2.0, color);
-        draw_triangle(tip, base + perp, base - perp, color);
+        draw_world_line(camera, center, tip, 2.0, color);
+        draw_world_triangle(camera, [tip, base + perp, base - perp], color);
 
-        // Car ID
-        draw_text(&format!("{:?}", car.get_id()), center.x, center.y - 10.0, 16.0, color);
+        // Car ID, the same size at any zoom
+        let label = camera.world_to_screen(center);
+        draw_text(&format!("{:?}", car.get_id()), label.x, label.y - 10.0, 16.0, color);
     }
 }
 
@@ -86,28 +110,110 @@ pub fn mix_colors(colors: Vec<(u8, u8, u8, u8)>) -> Option<(u8, u8, u8, u8)> {
     Some((avg_r, avg_g, avg_b, avg_a))
 }
 
-pub fn draw_roads(road_graph: &mut RoadGraph, debug: bool) -> () {
+pub fn draw_roads(road_graph: &mut RoadGraph, camera: &Camera, debug: bool) {
+
+    draw_junctions(road_graph, camera);
 
-    for (_id, road) in road_graph.get_roads() {
+    for road in road_graph.get_roads().values() {
 
         let road = road.read().unwrap();
 
+        draw_lane_markings(&road, camera);
+        draw_congestion(&road, camera);
+
+        // Both directions of a two-way road share the same line, draw it once
+        if road.twin.is_some_and(|twin| twin.0 < road.id.0) {
+            continue;
+        }
+
         let color = if road.one_way {PINK} else {WHITE};
 
         for pair in road.points.windows(2) {
 
-            let (x1, y1, x2, y2) = (pair[0].x, pair[0].y, pair[1].x, pair[1].y);
-            draw_line(x1, y1, x2, y2, 4.0, color);
+            draw_world_line(camera, pair[0], pair[1], 4.0, color);
             if debug {
-                let text = format!("Cars {:?} are on this Road", road_graph.get_cars().iter().map(|(id, _car)| id).collect::<Vec<_>>());
-                draw_text(&text,  (x1 + x2) / 2.0, ((y1 + y2) / 2.0) - 100.0, 14.0, color);
+                let middle = camera.world_to_screen((pair[0] + pair[1]) / 2.0);
+                let text = format!("Cars {:?} are on this Road", road.vehicles_on);
+                draw_text(&text, middle.x, middle.y - 100.0, 14.0, color);
             }
         }
         
     }
 }
 
-pub fn draw_dotted_line(road: &Road, road_graph: &mut RoadGraph, debug: bool) {
+/// The path of every turn through every junction, faintly, under the roads.
+fn draw_junctions(road_graph: &RoadGraph, camera: &Camera) {
+    for node in road_graph.nodes_to_iter() {
+        for connector in road_graph.connectors(node.id) {
+            draw_world_polyline(camera, &connector.points, 1.0, DARKGRAY);
+        }
+    }
+}
+
+/// Dashed lines between the lanes of one direction of a road and a solid line along its
+/// outer edge. A one-way road gets an edge line on both sides.
+fn draw_lane_markings(road: &Road, camera: &Camera) {
+    let lanes = road.lanes.max(1);
+    let half = LANE_WIDTH / 2.0;
+
+    for divider in 1..lanes {
+        draw_dashed_line(camera, &road.offset_points(road.lane_offset(divider) - half), 1.0, LIGHTGRAY);
+    }
+
+    let kerb = road.offset_points(road.lane_offset(lanes - 1) + half);
+    draw_world_polyline(camera, &kerb, 1.0, LIGHTGRAY);
+
+    if road.twin.is_none() {
+        let far_edge = road.offset_points(road.lane_offset(0) - half);
+        draw_world_polyline(camera, &far_edge, 1.0, LIGHTGRAY);
+    }
+}
+
+/// A stripe down the middle of a road's lanes, green when it is quiet through to red when it
+/// is at capacity. Empty roads are left bare.
+fn draw_congestion(road: &Road, camera: &Camera) {
+    let density = road.traffic_density.clamp(0.0, 1.0);
+    if density < 0.01 {
+        return;
+    }
+
+    let color = if density < 0.5 {
+        Color::new(density * 2.0, 0.8, 0.0, 0.6)
+    } else {
+        Color::new(1.0, 0.8 * (1.0 - density) * 2.0, 0.0, 0.6)
+    };
+
+    let middle = (road.lane_offset(0) + road.lane_offset(road.lanes.max(1) - 1)) / 2.0;
+    draw_world_polyline(camera, &road.offset_points(middle), 3.0, color);
+}
+
+/// Dashes along a polyline, the pattern carrying on across its corners.
+fn draw_dashed_line(camera: &Camera, points: &[Vec2], thickness: f32, color: Color) {
+    let dash = 6.0;
+    let period = 12.0;
+    let mut travelled: f32 = 0.0;
+
+    for pair in points.windows(2) {
+        let (from, to) = (pair[0], pair[1]);
+        let length = from.distance(to);
+        let direction = (to - from).normalize_or_zero();
+
+        let mut along = 0.0;
+        while along < length {
+            let phase = (travelled + along) % period;
+            let step = if phase < dash { dash - phase } else { period - phase };
+            let end = (along + step).min(length);
+
+            if phase < dash {
+                draw_world_line(camera, from + direction * along, from + direction * end, thickness, color);
+            }
+            along = end;
+        }
+        travelled += length;
+    }
+}
+
+pub fn draw_dotted_line(road: &Road, road_graph: &mut RoadGraph, camera: &Camera, _debug: bool) {
     let segment_length = 10.0;
     let spacing = 5.0;
 
@@ -115,8 +221,8 @@ pub fn draw_dotted_line(road: &Road, road_graph: &mut RoadGraph, debug: bool) {
     let color = mix_colors(
         road_graph
             .get_cars()
-            .iter()
-            .map(|(_id, car)| car.read().unwrap().get_color())
+            .values()
+            .map(|car| car.read().unwrap().get_color())
             .collect(),
     );
     let (r, g, b, a) = color.unwrap_or_default();
@@ -137,24 +243,94 @@ pub fn draw_dotted_line(road: &Road, road_graph: &mut RoadGraph, debug: bool) {
 
         for _ in 0..num_dots {
             let end = pos + direction * segment_length;
-            draw_line(
-                pos.x,
-                pos.y,
-                end.x,
-                end.y,
-                5.0,
-                Color::from_rgba(r, g, b, a),
-            );
+            draw_world_line(camera, pos, end, 5.0, Color::from_rgba(r, g, b, a));
             pos += step;
         }
     }
 }
 
-pub fn draw_node(node: &Node, debug: bool) {
-    draw_circle(node.position.x, node.position.y, 2.0, RED);
+/// A node is a dot and, when debugging, its ID. Both stay the same size at any zoom.
+pub fn draw_node(node: &Node, camera: &Camera, debug: bool) {
+    let position = camera.world_to_screen(node.position);
+    draw_circle(position.x, position.y, 2.0, RED);
     if debug {
-        draw_text(&node.id.to_string(), node.position.x + 50.0, node.position.y + 10.0, 32.0, BLUE);
+        draw_text(&node.id.to_string(), position.x + 50.0, position.y + 10.0, 32.0, BLUE);
+    }
+}
+
+
+
+/// Draws a signal head or a priority sign at the end of every road coming into a
+/// controlled node. Stop signs are red octagons and yield signs white triangles, major
+/// approaches get nothing.
+pub fn draw_controls(road_graph: &RoadGraph, camera: &Camera) {
+    for (node, control) in road_graph.get_controls() {
+        for road_id in road_graph.incoming_roads(*node) {
+            let road = road_graph.get_roads().get(&road_id).unwrap().read().unwrap();
+            let points = &road.points;
+            if points.len() < 2 {
+                continue;
+            }
+
+            // Just before the stop line, off to the right of the lane
+            let end = points[points.len() - 1];
+            let back = (points[points.len() - 2] - end).normalize_or_zero();
+            let right = Vec2::new(back.y, -back.x);
+            let head = camera.world_to_screen(end + back * 12.0 + right * 8.0);
+            let size = camera.to_pixels(1.0);
+
+            let signal = match control {
+                NodeControl::Signal(signal) => signal,
+                NodeControl::Priority(priority) => {
+                    if priority.must_stop(road_id) {
+                        draw_poly(head.x, head.y, 8, 5.0 * size, 22.5, RED);
+                    } else if !priority.is_major(road_id) {
+                        draw_poly(head.x, head.y, 3, 5.0 * size, 90.0, RED);
+                        draw_poly(head.x, head.y, 3, 3.0 * size, 90.0, WHITE);
+                    }
+                    continue;
+                }
+            };
+
+            let color = match signal.approach_aspect(road_id) {
+                Aspect::Green => GREEN,
+                Aspect::Amber => ORANGE,
+                Aspect::Red => RED,
+            };
+
+            draw_circle(head.x, head.y, 5.0 * size, DARKGRAY);
+            draw_circle(head.x, head.y, 3.5 * size, color);
+        }
     }
 }
 
 
+/// A barrier across every lane an obstruction blocks: red and white for an accident, orange
+/// and white for roadworks.
+pub fn draw_obstructions(road_graph: &RoadGraph, camera: &Camera) {
+    for road in road_graph.roads_to_iter() {
+        let road = road.read().unwrap();
+
+        for obstruction in &road.obstructions {
+            let color = match obstruction.kind {
+                ObstructionKind::Accident => RED,
+                ObstructionKind::Roadworks => ORANGE,
+            };
+
+            for lane in (0..road.lanes.max(1)).filter(|lane| obstruction.blocks(*lane)) {
+                let direction = road.tangent_at(obstruction.at);
+                let right = Vec2::new(-direction.y, direction.x);
+                let middle = road.position_at(obstruction.at) + right * road.lane_offset(lane);
+                let across = right * (LANE_WIDTH * 0.4);
+
+                draw_world_line(camera, middle - across, middle + across, 2.0, color);
+                draw_world_line(camera, middle - across * 0.3, middle + across * 0.3, 2.0, WHITE);
+            }
+
+            if let Some(remaining) = obstruction.remaining {
+                let label = camera.world_to_screen(road.position_at(obstruction.at));
+                draw_text(&format!("{:.0}s", remaining), label.x + 8.0, label.y - 8.0, 16.0, YELLOW);
+            }
+        }
+    }
+}